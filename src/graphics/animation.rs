@@ -0,0 +1,567 @@
+use super::*;
+use gltf::animation::{Interpolation, Property};
+use nalgebra::{Affine3, Matrix4, Quaternion, Translation3, UnitQuaternion, Vector3};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl NodeTransform {
+    fn blend(&self, other: &NodeTransform, t: f32) -> NodeTransform {
+        NodeTransform {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: interpolate_rotation(&self.rotation, &other.rotation, t),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+
+    pub fn to_affine(self) -> Affine3<f32> {
+        Affine3::from_matrix_unchecked(
+            Translation3::from(self.translation).to_homogeneous()
+                * self.rotation.to_homogeneous()
+                * Matrix4::new_nonuniform_scaling(&self.scale),
+        )
+    }
+}
+
+pub struct AnimationChannel {
+    pub target_node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    // Flattened keyframe values. Cubic spline keyframes are stored as in-tangent, value, out-tangent.
+    pub values: Vec<f32>,
+    pub components: usize,
+}
+
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<AnimationChannel>,
+    pub duration: f32,
+}
+
+pub struct AnimationPose {
+    pub nodes: HashMap<usize, NodeTransform>,
+    pub morph_weights: Option<Vec<f32>>,
+}
+
+struct PlaybackState {
+    clip: usize,
+    time: f32,
+}
+
+pub struct AnimationPlayer {
+    pub clips: Vec<AnimationClip>,
+    pub is_playing: bool,
+    pub is_looping: bool,
+    pub speed: f32,
+
+    rest_pose: Vec<NodeTransform>,
+    root_node: Option<usize>,
+    // The global transform of the root node's parent, which its animated transform is relative to.
+    root_parent_transform: Affine3<f32>,
+    // The entity's pose before it was animated, which places the model in the world.
+    base_pose: Option<Affine3<f32>>,
+
+    current: Option<PlaybackState>,
+    previous: Option<PlaybackState>,
+    fade_duration: f32,
+    fade_time: f32,
+}
+
+impl Component for AnimationPlayer {
+    type Storage = VecStorage<Self>;
+}
+
+fn interpolate_rotation(
+    a: &UnitQuaternion<f32>,
+    b: &UnitQuaternion<f32>,
+    t: f32,
+) -> UnitQuaternion<f32> {
+    a.try_slerp(b, t, 1.0e-6).unwrap_or_else(|| a.nlerp(b, t))
+}
+
+impl AnimationChannel {
+    fn keyframe(&self, index: usize, offset: usize) -> &[f32] {
+        let start = match self.interpolation {
+            Interpolation::CubicSpline => (index * 3 + offset) * self.components,
+            _ => index * self.components,
+        };
+
+        &self.values[start..start + self.components]
+    }
+
+    fn value(&self, index: usize) -> Vec<f32> {
+        self.keyframe(index, 1).to_vec()
+    }
+
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let last = self.times.len() - 1;
+
+        // Clamp to the first and last keyframes.
+        if time <= self.times[0] {
+            return self.value(0);
+        }
+        if time >= self.times[last] {
+            return self.value(last);
+        }
+
+        let next = self.times.iter().position(|&t| t > time).unwrap();
+        let prev = next - 1;
+
+        let delta = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / delta;
+
+        let mut result = match self.interpolation {
+            Interpolation::Step => self.value(prev),
+            Interpolation::Linear => {
+                if self.property == Property::Rotation {
+                    let a = self.value(prev);
+                    let b = self.value(next);
+
+                    let rotation = interpolate_rotation(
+                        &UnitQuaternion::from_quaternion(Quaternion::new(a[3], a[0], a[1], a[2])),
+                        &UnitQuaternion::from_quaternion(Quaternion::new(b[3], b[0], b[1], b[2])),
+                        t,
+                    );
+
+                    return rotation.coords.as_slice().to_vec();
+                }
+
+                izip!(self.value(prev), self.value(next))
+                    .map(|(a, b)| a + (b - a) * t)
+                    .collect()
+            }
+            Interpolation::CubicSpline => {
+                let t2 = t * t;
+                let t3 = t2 * t;
+
+                izip!(
+                    self.keyframe(prev, 1),
+                    self.keyframe(prev, 2),
+                    self.keyframe(next, 1),
+                    self.keyframe(next, 0)
+                )
+                .map(|(p0, m0, p1, m1)| {
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * p0
+                        + (t3 - 2.0 * t2 + t) * delta * m0
+                        + (-2.0 * t3 + 3.0 * t2) * p1
+                        + (t3 - t2) * delta * m1
+                })
+                .collect()
+            }
+        };
+
+        if self.property == Property::Rotation {
+            let length = result.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+            for x in result.iter_mut() {
+                *x /= length;
+            }
+        }
+
+        result
+    }
+}
+
+impl AnimationClip {
+    pub fn new_from_gltf(
+        animation: &gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Self, String> {
+        use gltf::accessor::{DataType::*, Dimensions::*};
+
+        let mut channels = Vec::new();
+
+        for channel in animation.channels() {
+            let sampler = channel.sampler();
+            let property = channel.target().property();
+
+            let (data_types, dimensions): (&[_], &[_]) = match property {
+                Property::Translation | Property::Scale => (&[F32], &[Vec3]),
+                Property::Rotation => (&[F32, I8, U8, I16, U16], &[Vec4]),
+                Property::MorphTargetWeights => (&[F32, I8, U8, I16, U16], &[Scalar]),
+            };
+
+            check_accessor(&sampler.input(), &[F32], &[Scalar], buffers)?;
+            check_accessor(&sampler.output(), data_types, dimensions, buffers)?;
+
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+            let times = match reader.read_inputs() {
+                Some(inputs) => inputs.collect::<Vec<f32>>(),
+                None => continue,
+            };
+
+            let (values, components): (Vec<f32>, usize) = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::Translations(iter)) => {
+                    (iter.flatten().collect(), 3)
+                }
+                Some(gltf::animation::util::ReadOutputs::Rotations(iter)) => {
+                    (iter.into_f32().flatten().collect(), 4)
+                }
+                Some(gltf::animation::util::ReadOutputs::Scales(iter)) => {
+                    (iter.flatten().collect(), 3)
+                }
+                Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(iter)) => {
+                    // There's a weight for each morph target of the node's mesh.
+                    let components = channel
+                        .target()
+                        .node()
+                        .mesh()
+                        .and_then(|mesh| mesh.primitives().next())
+                        .map_or(0, |primitive| primitive.morph_targets().count());
+
+                    (iter.into_f32().collect(), components)
+                }
+                None => continue,
+            };
+
+            // Cubic spline samplers have an in-tangent and out-tangent around each value.
+            let keyframes = match sampler.interpolation() {
+                Interpolation::CubicSpline => times.len() * 3,
+                _ => times.len(),
+            };
+
+            if components == 0 || values.len() != keyframes * components {
+                return Err(format!(
+                    "Animation {} has a sampler with {} output values for {} keyframes",
+                    animation.index(),
+                    values.len(),
+                    times.len()
+                ));
+            }
+
+            channels.push(AnimationChannel {
+                target_node: channel.target().node().index(),
+                property,
+                interpolation: sampler.interpolation(),
+                times,
+                values,
+                components,
+            });
+        }
+
+        let duration = channels
+            .iter()
+            .map(|channel| *channel.times.last().unwrap())
+            .fold(0.0, f32::max);
+
+        Ok(Self {
+            name: animation.name().map(|name| name.to_owned()),
+            channels,
+            duration,
+        })
+    }
+
+    pub fn sample(&self, time: f32, rest_pose: &[NodeTransform]) -> AnimationPose {
+        let mut nodes = HashMap::new();
+        let mut morph_weights = None;
+
+        for channel in self.channels.iter() {
+            let value = channel.sample(time);
+
+            // Weights don't move the node, so they leave it out of the pose.
+            if channel.property == Property::MorphTargetWeights {
+                morph_weights = Some(value);
+                continue;
+            }
+
+            let node = nodes
+                .entry(channel.target_node)
+                .or_insert(rest_pose[channel.target_node]);
+
+            match channel.property {
+                Property::Translation => {
+                    node.translation = Vector3::new(value[0], value[1], value[2]);
+                }
+                Property::Rotation => {
+                    node.rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+                        value[3], value[0], value[1], value[2],
+                    ));
+                }
+                Property::Scale => {
+                    node.scale = Vector3::new(value[0], value[1], value[2]);
+                }
+                Property::MorphTargetWeights => unreachable!(),
+            }
+        }
+
+        AnimationPose {
+            nodes,
+            morph_weights,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new_from_gltf(
+        gltf: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Option<Self>, String> {
+        let clips = gltf
+            .animations()
+            .map(|animation| AnimationClip::new_from_gltf(&animation, buffers))
+            .collect::<Result<Vec<AnimationClip>, String>>()?;
+
+        if clips.is_empty() {
+            return Ok(None);
+        }
+
+        let rest_pose = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+
+                NodeTransform {
+                    translation: translation.into(),
+                    rotation: UnitQuaternion::from_quaternion(Quaternion::new(
+                        rotation[3],
+                        rotation[0],
+                        rotation[1],
+                        rotation[2],
+                    )),
+                    scale: scale.into(),
+                }
+            })
+            .collect();
+
+        // Animating the node holding a rigid mesh moves the whole entity.
+        let root_node = gltf
+            .nodes()
+            .find(|node| node.mesh().is_some() && node.skin().is_none())
+            .map(|node| node.index());
+
+        let root_parent_transform = root_node
+            .and_then(|root_node| {
+                gltf.nodes()
+                    .find(|node| node.children().any(|child| child.index() == root_node))
+            })
+            .map_or_else(Affine3::identity, |parent| {
+                scene::node_world_transforms(gltf)[parent.index()]
+            });
+
+        Ok(Some(Self {
+            clips,
+            is_playing: false,
+            is_looping: true,
+            speed: 1.0,
+            rest_pose,
+            root_node,
+            root_parent_transform,
+            base_pose: None,
+            current: None,
+            previous: None,
+            fade_duration: 0.0,
+            fade_time: 0.0,
+        }))
+    }
+
+    pub fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips
+            .iter()
+            .position(|clip| clip.name.as_deref() == Some(name))
+    }
+
+    // The clip being played, or faded to.
+    pub fn current_clip(&self) -> Option<usize> {
+        self.current.as_ref().map(|state| state.clip)
+    }
+
+    pub fn play(&mut self, clip: usize) {
+        self.current = Some(PlaybackState { clip, time: 0.0 });
+        self.previous = None;
+        self.is_playing = true;
+    }
+
+    pub fn cross_fade(&mut self, clip: usize, duration: f32) {
+        if self.current.is_none() || duration <= 0.0 {
+            self.play(clip);
+            return;
+        }
+
+        self.previous = self.current.take();
+        self.current = Some(PlaybackState { clip, time: 0.0 });
+        self.fade_duration = duration;
+        self.fade_time = 0.0;
+        self.is_playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.is_playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.is_playing = true;
+    }
+
+    pub fn set_looping(&mut self, is_looping: bool) {
+        self.is_looping = is_looping;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    fn advance_state(&self, state: &mut PlaybackState, delta_time: f32) {
+        let duration = self.clips[state.clip].duration;
+
+        state.time += delta_time * self.speed;
+
+        if duration <= 0.0 {
+            state.time = 0.0;
+        } else if self.is_looping {
+            state.time = state.time.rem_euclid(duration);
+        } else {
+            state.time = state.time.max(0.0).min(duration);
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        if !self.is_playing {
+            return;
+        }
+
+        if let Some(mut state) = self.current.take() {
+            self.advance_state(&mut state, delta_time);
+            self.current = Some(state);
+        }
+
+        if let Some(mut state) = self.previous.take() {
+            self.advance_state(&mut state, delta_time);
+            self.fade_time += delta_time;
+
+            // Drop the previous clip once the fade has finished.
+            if self.fade_time < self.fade_duration {
+                self.previous = Some(state);
+            }
+        }
+    }
+
+    pub fn sample(&self) -> Option<AnimationPose> {
+        let current = self.current.as_ref()?;
+        let mut pose = self.clips[current.clip].sample(current.time, &self.rest_pose);
+
+        if let Some(previous) = &self.previous {
+            let previous_pose = self.clips[previous.clip].sample(previous.time, &self.rest_pose);
+            let t = self.fade_time / self.fade_duration;
+
+            for (&node, previous_transform) in previous_pose.nodes.iter() {
+                let current_transform = pose
+                    .nodes
+                    .get(&node)
+                    .copied()
+                    .unwrap_or(self.rest_pose[node]);
+
+                pose.nodes
+                    .insert(node, previous_transform.blend(&current_transform, t));
+            }
+
+            for (&node, current_transform) in pose.nodes.iter_mut() {
+                if !previous_pose.nodes.contains_key(&node) {
+                    *current_transform = self.rest_pose[node].blend(current_transform, t);
+                }
+            }
+
+            if let (Some(previous_weights), Some(weights)) =
+                (&previous_pose.morph_weights, &mut pose.morph_weights)
+            {
+                for (previous_weight, weight) in previous_weights.iter().zip(weights.iter_mut()) {
+                    *weight = previous_weight + (*weight - previous_weight) * t;
+                }
+            }
+        }
+
+        Some(pose)
+    }
+}
+
+// Plays the clip with the given name on every animation player that has one. Returns false if
+// none do.
+pub fn play_animation(world: &mut World, name: &str) -> bool {
+    let mut animation_players = world.write_storage::<AnimationPlayer>();
+    let mut is_found = false;
+
+    for animation_player in (&mut animation_players).join() {
+        if let Some(clip) = animation_player.find_clip(name) {
+            animation_player.play(clip);
+            is_found = true;
+        }
+    }
+
+    is_found
+}
+
+#[derive(Default)]
+pub struct AnimationSystem {
+    last_update: Option<std::time::Instant>,
+}
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        WriteStorage<'a, AnimationPlayer>,
+        WriteStorage<'a, Pose>,
+        WriteStorage<'a, Renderable>,
+        WriteStorage<'a, MorphWeights>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut animation_player, mut pose, mut renderable, mut morph_weights) = data;
+
+        let now = std::time::Instant::now();
+        let delta_time = match self.last_update {
+            Some(last_update) => (now - last_update).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last_update = Some(now);
+
+        for (animation_player, pose, renderable, morph_weights) in (
+            &mut animation_player,
+            (&mut pose).maybe(),
+            (&mut renderable).maybe(),
+            (&mut morph_weights).maybe(),
+        )
+            .join()
+        {
+            animation_player.update(delta_time);
+
+            let animation_pose = match animation_player.sample() {
+                Some(animation_pose) => animation_pose,
+                None => continue,
+            };
+
+            // Move the entity if the mesh node itself is animated.
+            if let (Some(pose), Some(root_node)) = (pose, animation_player.root_node) {
+                if let Some(transform) = animation_pose.nodes.get(&root_node) {
+                    let base_pose = *animation_player.base_pose.get_or_insert(pose.model_matrix);
+
+                    pose.model_matrix =
+                        base_pose * animation_player.root_parent_transform * transform.to_affine();
+                }
+            }
+
+            // Pose the skeleton.
+            if let Some(skin) = renderable.and_then(|renderable| renderable.skin.as_mut()) {
+                for (&node, transform) in animation_pose.nodes.iter() {
+                    if let Some(skin_node) = skin.nodes.get_mut(node) {
+                        skin_node.translation = transform.translation;
+                        skin_node.rotation = transform.rotation;
+                        skin_node.scale = transform.scale;
+                    }
+                }
+            }
+
+            // Drive blend shapes.
+            if let (Some(morph_weights), Some(weights)) =
+                (morph_weights, animation_pose.morph_weights)
+            {
+                morph_weights.weights = weights;
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use super::*;
+use specs::prelude::*;
+
+// Asset data decoded on a worker thread, waiting to be uploaded on the render thread.
+enum DecodedAsset {
+    Scene(Box<GltfImport>),
+    Ply(MeshData),
+    Obj(obj::ObjImport),
+    // Skyboxes are cached by the path of their HDR image.
+    Skybox(u64, HdrImage),
+}
+
+struct PendingAsset {
+    entity: Entity,
+    receiver: crossbeam_channel::Receiver<Result<DecodedAsset, String>>,
+    // Models wait here once decoded until the skybox they're lit by has been baked.
+    decoded: Option<DecodedAsset>,
+    is_skybox: bool,
+    // Whether the entity is drawing a placeholder added for this asset, which is removed if the
+    // asset fails to load.
+    has_placeholder: bool,
+}
+
+// Loads assets on worker threads. Each load returns the entity the asset will be added to
+// straight away, which renders a placeholder until the asset has been uploaded or fails to load.
+#[derive(Default)]
+pub struct AssetLoader {
+    // The skybox materials are lit by, once it has been baked.
+    pub skybox: Option<Handle<Skybox>>,
+    pending: Vec<PendingAsset>,
+}
+
+impl AssetLoader {
+    pub fn is_loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn load<F>(&mut self, entity: Entity, is_skybox: bool, decode: F)
+    where
+        F: FnOnce() -> Result<DecodedAsset, String> + Send + 'static,
+    {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+
+        std::thread::spawn(move || {
+            // The loader may have been dropped before decoding finished.
+            let _ = sender.send(decode());
+        });
+
+        self.pending.push(PendingAsset {
+            entity,
+            receiver,
+            decoded: None,
+            is_skybox,
+            has_placeholder: false,
+        });
+    }
+}
+
+// Loads a glTF, OBJ or PLY file in the background, returning the entity it will be added to.
+// glTF files are loaded as scenes, to include their animations and lights.
+pub fn load_model_async(world: &mut World, path: &std::path::Path, pose: Pose) -> Entity {
+    let entity = world.create_entity().with(pose).build();
+
+    queue_model(world, entity, path);
+
+    entity
+}
+
+// Loads a changed file into the entity it was loaded into before, replacing its model once the
+// new one is uploaded.
+pub fn reload_model_async(world: &mut World, entity: Entity, path: &std::path::Path) {
+    asset_server(world).remove(AssetServer::path_key(path));
+
+    queue_model(world, entity, path);
+}
+
+fn queue_model(world: &mut World, entity: Entity, path: &std::path::Path) {
+    let path = path.to_owned();
+
+    asset_loader(world).load(entity, false, move || {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("obj") => obj::import_obj(&path).map(DecodedAsset::Obj),
+            Some("ply") => {
+                let data = std::fs::read(&path).map_err(|err| err.to_string())?;
+
+                ply::read_ply(&data).map(DecodedAsset::Ply)
+            }
+            _ => {
+                image_import::import_gltf(&path).map(|import| DecodedAsset::Scene(Box::new(import)))
+            }
+        }
+    });
+}
+
+// Decodes the HDR image in the background, then bakes the skybox's lighting maps on the render
+// thread. Models aren't uploaded until the skybox is ready, so a skybox that fails to load is
+// replaced by a neutral grey one.
+pub fn load_skybox_async(world: &mut World, path: &std::path::Path) -> Entity {
+    let entity = world
+        .create_entity()
+        .with(Pose::new(nalgebra::Similarity3::identity()))
+        .build();
+
+    queue_skybox(world, entity, path);
+
+    entity
+}
+
+// Rebuilds the skybox from a changed file. Models loaded afterwards wait for the new skybox, but
+// models already loaded keep the old lighting maps until they're reloaded.
+pub fn reload_skybox_async(world: &mut World, entity: Entity, path: &std::path::Path) {
+    asset_server(world).remove(AssetServer::path_key(path));
+    asset_loader(world).skybox = None;
+
+    queue_skybox(world, entity, path);
+}
+
+fn queue_skybox(world: &mut World, entity: Entity, path: &std::path::Path) {
+    let path = path.to_owned();
+
+    asset_loader(world).load(entity, true, move || {
+        let hdr_data = std::fs::read(&path).map_err(|err| err.to_string())?;
+
+        HdrImage::decode(&hdr_data)
+            .map(|hdr_image| DecodedAsset::Skybox(AssetServer::path_key(&path), hdr_image))
+    });
+}
+
+fn asset_loader(world: &mut World) -> specs::shred::FetchMut<'_, AssetLoader> {
+    world.entry().or_insert_with(AssetLoader::default)
+}
+
+fn asset_server(world: &mut World) -> specs::shred::FetchMut<'_, AssetServer> {
+    world.entry().or_insert_with(AssetServer::default)
+}
+
+// Uploads assets once their worker threads have decoded them.
+pub struct AssetLoadSystem;
+
+impl<'a> System<'a> for AssetLoadSystem {
+    type SystemData = (
+        ReadExpect<'a, RenderState>,
+        Write<'a, AssetLoader>,
+        Write<'a, AssetServer>,
+        Entities<'a>,
+        ReadStorage<'a, Pose>,
+        WriteStorage<'a, Renderable>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            render_state,
+            mut asset_loader,
+            mut asset_server,
+            entities,
+            poses,
+            mut renderables,
+            lazy_update,
+        ) = data;
+
+        let device = &render_state.device;
+        let pipeline_cache = &render_state.pipeline_cache;
+        let sc_desc = &render_state.swap_chain_desc;
+        let queue = &render_state.queue;
+
+        let mut has_uploaded = false;
+
+        for mut pending in std::mem::take(&mut asset_loader.pending) {
+            if !entities.is_alive(pending.entity) {
+                continue;
+            }
+
+            if !pending.is_skybox && !renderables.contains(pending.entity) {
+                renderables
+                    .insert(
+                        pending.entity,
+                        create_placeholder(device, pipeline_cache, sc_desc),
+                    )
+                    .unwrap();
+
+                pending.has_placeholder = true;
+            }
+
+            if pending.decoded.is_none() {
+                let error = match pending.receiver.try_recv() {
+                    Ok(Ok(decoded)) => {
+                        pending.decoded = Some(decoded);
+                        None
+                    }
+                    Ok(Err(err)) => Some(err),
+                    Err(crossbeam_channel::TryRecvError::Empty) => None,
+                    Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                        Some("the loading thread panicked".to_owned())
+                    }
+                };
+
+                if let Some(err) = error {
+                    println!("Failed to load asset: {}", err);
+
+                    if !pending.is_skybox {
+                        remove_placeholder(&pending, &mut renderables);
+                        continue;
+                    }
+
+                    // Models wait for a skybox to light them, so they're lit by a neutral one.
+                    pending.decoded = Some(DecodedAsset::Skybox(
+                        AssetServer::hash_key("neutral_skybox"),
+                        HdrImage::neutral(),
+                    ));
+                }
+            }
+
+            // Only one asset is uploaded per frame, to spread the stalls out.
+            let is_ready = match pending.decoded {
+                Some(DecodedAsset::Skybox(..)) => !has_uploaded,
+                Some(_) => !has_uploaded && asset_loader.skybox.is_some(),
+                None => false,
+            };
+
+            if !is_ready {
+                asset_loader.pending.push(pending);
+                continue;
+            }
+
+            has_uploaded = true;
+
+            let entity = pending.entity;
+
+            match pending.decoded.take().unwrap() {
+                DecodedAsset::Skybox(key, hdr_image) => {
+                    // Skyboxes already baked from the same image are shared.
+                    let (skybox, skybox_renderable) = match (
+                        asset_server.cached_skybox(key),
+                        asset_server.renderable(key),
+                    ) {
+                        (Some(skybox), Some(skybox_renderable)) => (skybox, skybox_renderable),
+                        _ => {
+                            let (skybox, skybox_renderable) = Skybox::new_from_image(
+                                device,
+                                pipeline_cache,
+                                sc_desc,
+                                queue,
+                                &hdr_image,
+                            );
+
+                            asset_server.add_renderable(key, &skybox_renderable);
+
+                            (asset_server.add_skybox(key, skybox), skybox_renderable)
+                        }
+                    };
+
+                    renderables.insert(entity, skybox_renderable).unwrap();
+                    asset_loader.skybox = Some(skybox);
+                }
+                DecodedAsset::Scene(import) => {
+                    let skybox = asset_loader.skybox.as_ref().unwrap();
+                    let scene = Scene::import_gltf(
+                        device,
+                        pipeline_cache,
+                        sc_desc,
+                        queue,
+                        &import,
+                        skybox,
+                        &mut asset_server,
+                    );
+
+                    let scene = match scene {
+                        Ok(scene) => scene,
+                        Err(err) => {
+                            println!("Failed to load asset: {}", err);
+                            remove_placeholder(&pending, &mut renderables);
+                            continue;
+                        }
+                    };
+
+                    // Scenes create entities for their lights and cameras.
+                    lazy_update.exec_mut(move |world| scene.insert_entities(world, entity));
+                }
+                DecodedAsset::Ply(mesh_data) => {
+                    let skybox = asset_loader.skybox.as_ref().unwrap();
+
+                    let mesh = Mesh::new_from_data(device, &mesh_data);
+
+                    // PLY files have no materials.
+                    let pbr_params = obj::create_pbr_params(
+                        device,
+                        queue,
+                        None,
+                        &obj::ObjTextures::default(),
+                        skybox,
+                        &mut asset_server,
+                    );
+
+                    let renderable = Renderable::new_pbr(
+                        device,
+                        pipeline_cache,
+                        sc_desc,
+                        vec![mesh],
+                        pbr_params,
+                        None,
+                    );
+
+                    renderables.insert(entity, renderable).unwrap();
+                }
+                DecodedAsset::Obj(import) => {
+                    let skybox = asset_loader.skybox.as_ref().unwrap();
+
+                    let mut obj_renderables = Renderable::new_from_obj(
+                        device,
+                        pipeline_cache,
+                        sc_desc,
+                        queue,
+                        &import,
+                        skybox,
+                        &mut asset_server,
+                    )
+                    .into_iter();
+
+                    // Every renderable after the first becomes an entity of its own.
+                    if let Some(renderable) = obj_renderables.next() {
+                        renderables.insert(entity, renderable).unwrap();
+                    }
+
+                    let model_matrix = poses.get(entity).unwrap().model_matrix;
+
+                    for renderable in obj_renderables {
+                        lazy_update
+                            .create_entity(&entities)
+                            .with(renderable)
+                            .with(Pose { model_matrix })
+                            .build();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Models that were loaded before keep drawing their previous renderable.
+fn remove_placeholder(pending: &PendingAsset, renderables: &mut WriteStorage<Renderable>) {
+    if pending.has_placeholder {
+        renderables.remove(pending.entity);
+    }
+}
+
+// A grey cube drawn in place of models that are still loading.
+fn create_placeholder(
+    device: &wgpu::Device,
+    pipeline_cache: &PipelineCache,
+    sc_desc: &wgpu::SwapChainDescriptor,
+) -> Renderable {
+    let cube_vertices: [[f32; 3]; 8] = [
+        // front
+        [-0.5, -0.5, 0.5],
+        [0.5, -0.5, 0.5],
+        [0.5, 0.5, 0.5],
+        [-0.5, 0.5, 0.5],
+        // back
+        [-0.5, -0.5, -0.5],
+        [0.5, -0.5, -0.5],
+        [0.5, 0.5, -0.5],
+        [-0.5, 0.5, -0.5],
+    ];
+
+    let cube_elements: [u32; 36] = [
+        // front
+        0, 1, 2, 2, 3, 0, // right
+        1, 5, 6, 6, 2, 1, // back
+        7, 6, 5, 5, 4, 7, // left
+        4, 0, 3, 3, 7, 4, // bottom
+        4, 5, 1, 1, 0, 4, // top
+        3, 2, 6, 6, 7, 3,
+    ];
+
+    let cube_vertex_array = cube_vertices
+        .iter()
+        .map(|&position| Vertex {
+            position,
+            normal: [0.0, 0.0, 0.0],
+            tangent: [0.0, 0.0, 0.0, 0.0],
+            tex_coord: [0.0, 0.0],
+            joints: [0, 0, 0, 0],
+            weights: [0.0, 0.0, 0.0, 0.0],
+            colour: [1.0; 4],
+        })
+        .collect::<Vec<Vertex>>();
+
+    let mesh = Mesh::new(device, &cube_vertex_array, Some(&cube_elements));
+
+    let unlit_params = UnlitBindGroup {
+        base_colour_factor: [0.5, 0.5, 0.5, 1.0],
+        base_colour_texture: None,
+        is_skinned: false,
+        has_morph_targets: false,
+        primitive_topologies: Vec::new(),
+    };
+
+    Renderable::new_unlit(
+        device,
+        pipeline_cache,
+        sc_desc,
+        vec![mesh],
+        unlit_params,
+        None,
+    )
+}
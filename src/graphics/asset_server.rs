@@ -0,0 +1,147 @@
+use super::*;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Weak};
+
+// A shared, reference counted asset. Its GPU resources are freed once the last handle is dropped.
+pub struct Handle<T: ?Sized>(Arc<T>);
+
+pub type MaterialHandle = Handle<dyn MaterialBase + Send + Sync>;
+
+impl<T> Handle<T> {
+    // Creates a handle that isn't cached by an `AssetServer`.
+    pub fn new(asset: T) -> Self {
+        Self(Arc::new(asset))
+    }
+}
+
+impl<T: ?Sized> Handle<T> {
+    // Identifies the asset, which is shared by every clone of the handle.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Handle<T> {
+    fn from(asset: Box<T>) -> Self {
+        Self(Arc::from(asset))
+    }
+}
+
+impl<T: ?Sized> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> std::ops::Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// Caches the assets in use by path or content hash, so loading the same file or image twice
+// shares its GPU resources. Only weak references are kept, so assets are freed once nothing
+// holds a handle to them.
+#[derive(Default)]
+pub struct AssetServer {
+    textures: HashMap<u64, Weak<Texture>>,
+    lods: HashMap<u64, Weak<Vec<Lod>>>,
+    materials: HashMap<u64, Weak<dyn MaterialBase + Send + Sync>>,
+    skyboxes: HashMap<u64, Weak<Skybox>>,
+}
+
+impl AssetServer {
+    pub fn path_key(path: &std::path::Path) -> u64 {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+
+        AssetServer::hash_key(&path)
+    }
+
+    pub fn hash_key<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        value.hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    pub fn cached_texture(&self, key: u64) -> Option<Handle<Texture>> {
+        self.textures.get(&key)?.upgrade().map(Handle)
+    }
+
+    pub fn texture<F>(&mut self, key: u64, create: F) -> Handle<Texture>
+    where
+        F: FnOnce() -> Texture,
+    {
+        get_or_insert(&mut self.textures, key, || Arc::new(create()))
+    }
+
+    pub fn cached_skybox(&self, key: u64) -> Option<Handle<Skybox>> {
+        self.skyboxes.get(&key)?.upgrade().map(Handle)
+    }
+
+    pub fn add_skybox(&mut self, key: u64, skybox: Skybox) -> Handle<Skybox> {
+        remove_unused(&mut self.skyboxes);
+
+        let skybox = Arc::new(skybox);
+        self.skyboxes.insert(key, Arc::downgrade(&skybox));
+
+        Handle(skybox)
+    }
+
+    // A new renderable sharing the meshes and material cached under the key, if they're still
+    // loaded.
+    pub fn renderable(&self, key: u64) -> Option<Renderable> {
+        let lods = self.lods.get(&key)?.upgrade()?;
+        let material = self.materials.get(&key)?.upgrade()?;
+
+        Some(Renderable::new_shared(Handle(lods), Handle(material)))
+    }
+
+    pub fn add_renderable(&mut self, key: u64, renderable: &Renderable) {
+        remove_unused(&mut self.lods);
+        remove_unused(&mut self.materials);
+
+        self.lods
+            .insert(key, Arc::downgrade(&renderable.lod_handle().0));
+        self.materials
+            .insert(key, Arc::downgrade(&renderable.material.0));
+    }
+
+    // Forgets the assets cached under a key, so the next load creates them again. Handles already
+    // given out are unaffected.
+    pub fn remove(&mut self, key: u64) {
+        self.textures.remove(&key);
+        self.lods.remove(&key);
+        self.materials.remove(&key);
+        self.skyboxes.remove(&key);
+    }
+}
+
+// Returns the asset cached under the key while it's still in use, or creates and caches it.
+pub fn get_or_insert<K, T: ?Sized, F>(
+    cache: &mut HashMap<K, Weak<T>>,
+    key: K,
+    create: F,
+) -> Handle<T>
+where
+    K: Eq + Hash,
+    F: FnOnce() -> Arc<T>,
+{
+    if let Some(asset) = cache.get(&key).and_then(Weak::upgrade) {
+        return Handle(asset);
+    }
+
+    remove_unused(cache);
+
+    let asset = create();
+    cache.insert(key, Arc::downgrade(&asset));
+
+    Handle(asset)
+}
+
+fn remove_unused<K, T: ?Sized>(cache: &mut HashMap<K, Weak<T>>) {
+    cache.retain(|_, asset| asset.strong_count() > 0);
+}
@@ -0,0 +1,1381 @@
+// Transcodes Basis Universal textures, in either ETC1S or UASTC, to RGBA8. wgpu 0.5 has no block
+// compressed formats for them to be transcoded to, so every texel is decoded.
+
+// ETC1's intensity modifiers, in the order Basis Universal selectors index them.
+const ETC1_INTENSITIES: [[i32; 4]; 8] = [
+    [-8, -2, 2, 8],
+    [-17, -5, 5, 17],
+    [-29, -9, 9, 29],
+    [-42, -13, 13, 42],
+    [-60, -18, 18, 60],
+    [-80, -24, 24, 80],
+    [-106, -33, 33, 106],
+    [-183, -47, 47, 183],
+];
+
+// Code length codes are sent in this order, so trailing unused lengths can be left out.
+const CODE_LENGTH_ORDER: [usize; 21] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+// The endpoint prediction symbol that repeats the previous one.
+const ENDPOINT_PRED_REPEAT_SYMBOL: u32 = 256;
+
+// The selector history run length symbol that's followed by a longer count.
+const SELECTOR_RUN_LONG_SYMBOL: u32 = 63;
+
+const IMAGE_DESC_SIZE: usize = 20;
+
+// KTX2 image flags.
+const IMAGE_IS_P_FRAME: u32 = 0x2;
+
+// Reads bits starting from the least significant bit of each byte, as Basis Universal streams are
+// written.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit_offset: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            bit_offset: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.bit_offset / 8)
+                .ok_or_else(|| "Basis Universal data is truncated".to_owned())?;
+
+            value |= (((byte >> (self.bit_offset % 8)) & 1) as u32) << i;
+            self.bit_offset += 1;
+        }
+
+        Ok(value)
+    }
+
+    // A number sent in chunks, each followed by a bit saying whether another chunk follows.
+    fn vlc(&mut self, chunk_bits: u32) -> Result<u32, String> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let chunk = self.bits(chunk_bits + 1)?;
+            value |= (chunk & ((1 << chunk_bits) - 1)) << shift;
+
+            if chunk >> chunk_bits == 0 {
+                return Ok(value);
+            }
+
+            shift += chunk_bits;
+
+            if shift >= 32 {
+                return Err("Invalid variable length number in Basis Universal data".to_owned());
+            }
+        }
+    }
+
+    // Canonical Huffman codes are sent as their code lengths, themselves Huffman coded with run
+    // lengths for repeated and zero lengths.
+    fn huffman_table(&mut self) -> Result<HuffmanTable, String> {
+        let symbol_count = self.bits(14)? as usize;
+
+        if symbol_count == 0 {
+            return HuffmanTable::new(&[]);
+        }
+
+        let code_length_count = self.bits(5)? as usize;
+
+        if code_length_count == 0 || code_length_count > CODE_LENGTH_ORDER.len() {
+            return Err("Invalid Huffman table in Basis Universal data".to_owned());
+        }
+
+        let mut code_length_lengths = [0; 21];
+
+        for &code_length in CODE_LENGTH_ORDER[..code_length_count].iter() {
+            code_length_lengths[code_length] = self.bits(3)? as u8;
+        }
+
+        let code_length_table = HuffmanTable::new(&code_length_lengths)?;
+        let mut code_lengths = Vec::with_capacity(symbol_count);
+
+        while code_lengths.len() < symbol_count {
+            let (length, count) = match code_length_table.decode(self)? {
+                length @ 0..=16 => (length as u8, 1),
+                17 => (0, self.bits(3)? + 3),
+                18 => (0, self.bits(7)? + 11),
+                repeat => {
+                    let count = if repeat == 19 {
+                        self.bits(2)? + 3
+                    } else {
+                        self.bits(7)? + 7
+                    };
+
+                    match code_lengths.last() {
+                        Some(&length) if length > 0 => (length, count),
+                        _ => return Err("Invalid Huffman table in Basis Universal data".to_owned()),
+                    }
+                }
+            };
+
+            if code_lengths.len() + count as usize > symbol_count {
+                return Err("Invalid Huffman table in Basis Universal data".to_owned());
+            }
+
+            code_lengths.extend(std::iter::repeat(length).take(count as usize));
+        }
+
+        HuffmanTable::new(&code_lengths)
+    }
+}
+
+// A canonical Huffman code, decoded a bit at a time from the most significant bit of each code.
+struct HuffmanTable {
+    // The number of codes of each length, and the symbols ordered by code length then value.
+    length_counts: [u32; 17],
+    symbols: Vec<u32>,
+}
+
+impl HuffmanTable {
+    fn new(code_lengths: &[u8]) -> Result<Self, String> {
+        let mut length_counts = [0; 17];
+
+        for &length in code_lengths.iter().filter(|&&length| length > 0) {
+            match length_counts.get_mut(length as usize) {
+                Some(count) => *count += 1,
+                None => return Err("Invalid Huffman table in Basis Universal data".to_owned()),
+            }
+        }
+
+        // Codes may be left unused, but there can't be more codes of a length than remain.
+        let mut available_codes = 1i64;
+
+        for &count in length_counts[1..].iter() {
+            available_codes = available_codes * 2 - count as i64;
+
+            if available_codes < 0 {
+                return Err("Invalid Huffman table in Basis Universal data".to_owned());
+            }
+        }
+
+        let mut symbols: Vec<u32> = (0..code_lengths.len() as u32)
+            .filter(|&symbol| code_lengths[symbol as usize] > 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| code_lengths[symbol as usize]);
+
+        Ok(Self {
+            length_counts,
+            symbols,
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u32, String> {
+        let mut code = 0;
+        let mut first_code = 0;
+        let mut first_index = 0;
+
+        for &count in self.length_counts[1..].iter() {
+            code |= reader.bits(1)?;
+
+            if code < first_code + count {
+                return Ok(self.symbols[(first_index + code - first_code) as usize]);
+            }
+
+            first_index += count;
+            first_code = (first_code + count) << 1;
+            code <<= 1;
+        }
+
+        Err("Invalid Huffman code in Basis Universal data".to_owned())
+    }
+}
+
+// An ETC1S endpoint, a 5-bit colour with an intensity table around it.
+#[derive(Copy, Clone)]
+struct Endpoint {
+    colour: [u8; 3],
+    intensity: usize,
+}
+
+impl Endpoint {
+    // The four colours a selector chooses between.
+    fn colours(&self) -> [[u8; 3]; 4] {
+        let mut colours = [[0; 3]; 4];
+
+        for (colour, &modifier) in colours
+            .iter_mut()
+            .zip(ETC1_INTENSITIES[self.intensity].iter())
+        {
+            for (channel, &base) in colour.iter_mut().zip(self.colour.iter()) {
+                let base = ((base << 3) | (base >> 2)) as i32;
+
+                *channel = (base + modifier).clamp(0, 255) as u8;
+            }
+        }
+
+        colours
+    }
+}
+
+// Recently used selectors. Used entries move halfway towards the front and new entries are added
+// from the middle, roughly keeping the most used ones at the front.
+struct SelectorHistory {
+    selectors: Vec<usize>,
+    next: usize,
+}
+
+impl SelectorHistory {
+    fn new(size: usize) -> Self {
+        Self {
+            selectors: vec![0; size],
+            next: size / 2,
+        }
+    }
+
+    fn add(&mut self, selector: usize) {
+        self.selectors[self.next] = selector;
+        self.next += 1;
+
+        if self.next == self.selectors.len() {
+            self.next = self.selectors.len() / 2;
+        }
+    }
+
+    fn take(&mut self, index: usize) -> usize {
+        let selector = self.selectors[index];
+        self.selectors.swap(index / 2, index);
+
+        selector
+    }
+}
+
+// The endpoint and selector codebooks shared by every slice of an ETC1S texture, and the Huffman
+// tables the slices are coded with.
+struct Etc1sCodebooks {
+    endpoints: Vec<Endpoint>,
+    // Each selector picks one of its endpoint's four colours for every texel, row by row.
+    selectors: Vec<[u8; 16]>,
+    endpoint_pred_model: HuffmanTable,
+    delta_endpoint_model: HuffmanTable,
+    selector_model: HuffmanTable,
+    selector_run_model: HuffmanTable,
+    selector_history_size: usize,
+}
+
+impl Etc1sCodebooks {
+    fn new(
+        endpoint_count: usize,
+        endpoint_data: &[u8],
+        selector_count: usize,
+        selector_data: &[u8],
+        table_data: &[u8],
+    ) -> Result<Self, String> {
+        if endpoint_count == 0 || selector_count == 0 {
+            return Err("ETC1S texture has no endpoints or selectors".to_owned());
+        }
+
+        // Endpoints are delta coded from the previous one, with the colour's table chosen by the
+        // previous value.
+        let mut reader = BitReader::new(endpoint_data);
+        let colour_delta_models = [
+            reader.huffman_table()?,
+            reader.huffman_table()?,
+            reader.huffman_table()?,
+        ];
+        let intensity_delta_model = reader.huffman_table()?;
+        let is_grayscale = reader.bits(1)? != 0;
+
+        let mut endpoints = Vec::with_capacity(endpoint_count);
+        let mut previous = Endpoint {
+            colour: [16; 3],
+            intensity: 0,
+        };
+
+        for _ in 0..endpoint_count {
+            let intensity_delta = intensity_delta_model.decode(&mut reader)? as usize;
+            let mut endpoint = Endpoint {
+                colour: previous.colour,
+                intensity: (previous.intensity + intensity_delta) & 7,
+            };
+
+            for channel in 0..if is_grayscale { 1 } else { 3 } {
+                let model = match previous.colour[channel] {
+                    0..=9 => &colour_delta_models[0],
+                    10..=21 => &colour_delta_models[1],
+                    _ => &colour_delta_models[2],
+                };
+
+                let delta = model.decode(&mut reader)? as u8;
+                endpoint.colour[channel] = previous.colour[channel].wrapping_add(delta) & 31;
+            }
+
+            if is_grayscale {
+                endpoint.colour = [endpoint.colour[0]; 3];
+            }
+
+            endpoints.push(endpoint);
+            previous = endpoint;
+        }
+
+        // Selectors are either raw or xor coded against the previous one, a byte per row.
+        let mut reader = BitReader::new(selector_data);
+
+        if reader.bits(1)? != 0 || reader.bits(1)? != 0 {
+            return Err("ETC1S global and hybrid selector codebooks aren't supported".to_owned());
+        }
+
+        let is_raw = reader.bits(1)? != 0;
+        let delta_model = if is_raw {
+            None
+        } else {
+            Some(reader.huffman_table()?)
+        };
+
+        let mut selectors = Vec::with_capacity(selector_count);
+        let mut previous_rows = [0u8; 4];
+
+        for i in 0..selector_count {
+            let mut selector = [0; 16];
+
+            for (y, previous_row) in previous_rows.iter_mut().enumerate() {
+                let row = match &delta_model {
+                    Some(delta_model) if i > 0 => {
+                        delta_model.decode(&mut reader)? as u8 ^ *previous_row
+                    }
+                    _ => reader.bits(8)? as u8,
+                };
+
+                for x in 0..4 {
+                    selector[y * 4 + x] = (row >> (x * 2)) & 3;
+                }
+
+                *previous_row = row;
+            }
+
+            selectors.push(selector);
+        }
+
+        let mut reader = BitReader::new(table_data);
+        let endpoint_pred_model = reader.huffman_table()?;
+        let delta_endpoint_model = reader.huffman_table()?;
+        let selector_model = reader.huffman_table()?;
+        let selector_run_model = reader.huffman_table()?;
+        let selector_history_size = reader.bits(13)? as usize;
+
+        if selector_history_size == 0 {
+            return Err("ETC1S texture has no selector history".to_owned());
+        }
+
+        Ok(Self {
+            endpoints,
+            selectors,
+            endpoint_pred_model,
+            delta_endpoint_model,
+            selector_model,
+            selector_run_model,
+            selector_history_size,
+        })
+    }
+
+    // Decodes a slice's blocks into RGBA pixels, or only into their alpha, which alpha slices
+    // store in green.
+    fn decode_slice(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        is_alpha: bool,
+        rgba: &mut [u8],
+    ) -> Result<(), String> {
+        let blocks_x = width.div_ceil(4) as usize;
+        let blocks_y = height.div_ceil(4) as usize;
+        let selector_run_symbol = self.selectors.len() + self.selector_history_size;
+
+        let mut reader = BitReader::new(data);
+        let mut selector_history = SelectorHistory::new(self.selector_history_size);
+
+        // Endpoints are predicted from the block to the left, above or above and to the left, or
+        // delta coded. Each prediction symbol covers a 2x2 group of blocks.
+        let mut upper_endpoints = vec![0; blocks_x];
+        let mut endpoints = vec![0; blocks_x];
+        let mut lower_pred_bits = vec![0; blocks_x];
+        let mut pred_bits = 0;
+        let mut previous_pred_symbol = 0;
+        let mut pred_repeat_count = 0;
+        let mut previous_endpoint = 0;
+        let mut selector_run_count = 0;
+
+        for block_y in 0..blocks_y {
+            for block_x in 0..blocks_x {
+                if block_x % 2 == 0 {
+                    if block_y % 2 == 0 {
+                        if pred_repeat_count > 0 {
+                            pred_repeat_count -= 1;
+                            pred_bits = previous_pred_symbol;
+                        } else {
+                            pred_bits = self.endpoint_pred_model.decode(&mut reader)?;
+
+                            if pred_bits == ENDPOINT_PRED_REPEAT_SYMBOL {
+                                pred_repeat_count = reader.vlc(4)? + 2;
+                                pred_bits = previous_pred_symbol;
+                            } else {
+                                previous_pred_symbol = pred_bits;
+                            }
+                        }
+
+                        lower_pred_bits[block_x] = pred_bits >> 4;
+                    } else {
+                        pred_bits = lower_pred_bits[block_x];
+                    }
+                }
+
+                let endpoint = match pred_bits & 3 {
+                    0 if block_x > 0 => previous_endpoint,
+                    1 if block_y > 0 => upper_endpoints[block_x],
+                    2 if block_x > 0 && block_y > 0 => upper_endpoints[block_x - 1],
+                    3 => {
+                        let delta = self.delta_endpoint_model.decode(&mut reader)? as usize;
+
+                        (previous_endpoint + delta) % self.endpoints.len()
+                    }
+                    _ => return Err("Invalid ETC1S endpoint prediction".to_owned()),
+                };
+
+                pred_bits >>= 2;
+                endpoints[block_x] = endpoint;
+                previous_endpoint = endpoint;
+
+                // Selectors are coded directly, as an index into the history of recently used
+                // selectors, or as a run repeating the most recent one.
+                let selector = if selector_run_count > 0 {
+                    selector_run_count -= 1;
+                    selector_history.take(0)
+                } else {
+                    match self.selector_model.decode(&mut reader)? as usize {
+                        symbol if symbol < self.selectors.len() => {
+                            selector_history.add(symbol);
+                            symbol
+                        }
+                        symbol if symbol < selector_run_symbol => {
+                            selector_history.take(symbol - self.selectors.len())
+                        }
+                        symbol if symbol == selector_run_symbol => {
+                            selector_run_count =
+                                match self.selector_run_model.decode(&mut reader)? {
+                                    SELECTOR_RUN_LONG_SYMBOL => reader.vlc(7)? + 3,
+                                    run => run + 3,
+                                } as usize;
+
+                            if selector_run_count > blocks_x * blocks_y {
+                                return Err("Invalid ETC1S selector run".to_owned());
+                            }
+
+                            selector_run_count -= 1;
+                            selector_history.take(0)
+                        }
+                        _ => return Err("Invalid ETC1S selector".to_owned()),
+                    }
+                };
+
+                let selector = self
+                    .selectors
+                    .get(selector)
+                    .ok_or_else(|| "Invalid ETC1S selector".to_owned())?;
+                let colours = self.endpoints[endpoint].colours();
+
+                for (texel, &colour_index) in selector.iter().enumerate() {
+                    let x = block_x * 4 + texel % 4;
+                    let y = block_y * 4 + texel / 4;
+
+                    if x >= width as usize || y >= height as usize {
+                        continue;
+                    }
+
+                    let pixel = &mut rgba[(y * width as usize + x) * 4..][..4];
+                    let colour = colours[colour_index as usize];
+
+                    if is_alpha {
+                        pixel[3] = colour[1];
+                    } else {
+                        pixel[..3].copy_from_slice(&colour);
+                    }
+                }
+            }
+
+            std::mem::swap(&mut upper_endpoints, &mut endpoints);
+        }
+
+        Ok(())
+    }
+}
+
+// Where an image's slices are in its mip level's data.
+struct ImageDesc {
+    flags: u32,
+    rgb_slice: std::ops::Range<usize>,
+    alpha_slice: std::ops::Range<usize>,
+}
+
+// The supercompression global data of a KTX2 file supercompressed with BasisLZ: the codebooks
+// shared by every image, and where each image's slices are.
+pub struct BasisLzGlobalData {
+    image_descs: Vec<ImageDesc>,
+    codebooks: Etc1sCodebooks,
+}
+
+impl BasisLzGlobalData {
+    pub fn new(data: &[u8], image_count: usize) -> Result<Self, String> {
+        let u16_at = |offset: usize| -> Result<usize, String> {
+            data.get(offset..offset + 2)
+                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+                .ok_or_else(|| "BasisLZ global data is truncated".to_owned())
+        };
+
+        let u32_at = |offset: usize| -> Result<usize, String> {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+                .ok_or_else(|| "BasisLZ global data is truncated".to_owned())
+        };
+
+        let endpoint_count = u16_at(0)?;
+        let selector_count = u16_at(2)?;
+        let endpoints_length = u32_at(4)?;
+        let selectors_length = u32_at(8)?;
+        let tables_length = u32_at(12)?;
+
+        let image_descs = (0..image_count)
+            .map(|image| {
+                let offset = 20 + image * IMAGE_DESC_SIZE;
+                let rgb_offset = u32_at(offset + 4)?;
+                let alpha_offset = u32_at(offset + 12)?;
+
+                Ok(ImageDesc {
+                    flags: u32_at(offset)? as u32,
+                    rgb_slice: rgb_offset..rgb_offset + u32_at(offset + 8)?,
+                    alpha_slice: alpha_offset..alpha_offset + u32_at(offset + 16)?,
+                })
+            })
+            .collect::<Result<Vec<ImageDesc>, String>>()?;
+
+        let endpoints_start = 20 + image_count * IMAGE_DESC_SIZE;
+        let selectors_start = endpoints_start + endpoints_length;
+        let tables_start = selectors_start + selectors_length;
+
+        let slice = |range: std::ops::Range<usize>| {
+            data.get(range)
+                .ok_or_else(|| "BasisLZ global data is truncated".to_owned())
+        };
+
+        let codebooks = Etc1sCodebooks::new(
+            endpoint_count,
+            slice(endpoints_start..selectors_start)?,
+            selector_count,
+            slice(selectors_start..tables_start)?,
+            slice(tables_start..tables_start + tables_length)?,
+        )?;
+
+        Ok(Self {
+            image_descs,
+            codebooks,
+        })
+    }
+
+    // Decodes an image from its mip level's data to RGBA, taking alpha from the image's alpha
+    // slice if it has one.
+    pub fn decode_image(
+        &self,
+        image: usize,
+        level_data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, String> {
+        let image_desc = self
+            .image_descs
+            .get(image)
+            .ok_or_else(|| format!("BasisLZ image {} is missing", image))?;
+
+        // P-frames of video textures are predicted from the previous frame.
+        if image_desc.flags & IMAGE_IS_P_FRAME != 0 {
+            return Err("BasisLZ video frames aren't supported".to_owned());
+        }
+
+        let slice = |range: &std::ops::Range<usize>| {
+            level_data
+                .get(range.clone())
+                .ok_or_else(|| format!("BasisLZ image {} is truncated", image))
+        };
+
+        let mut rgba = vec![255; width as usize * height as usize * 4];
+
+        self.codebooks.decode_slice(
+            slice(&image_desc.rgb_slice)?,
+            width,
+            height,
+            false,
+            &mut rgba,
+        )?;
+
+        if !image_desc.alpha_slice.is_empty() {
+            self.codebooks.decode_slice(
+                slice(&image_desc.alpha_slice)?,
+                width,
+                height,
+                true,
+                &mut rgba,
+            )?;
+        }
+
+        Ok(rgba)
+    }
+}
+
+// ASTC's quantization ranges, as their number of bits per value and whether values also have a
+// trit or a quint.
+const ASTC_RANGES: [(u32, bool, bool); 21] = [
+    (1, false, false),
+    (0, true, false),
+    (2, false, false),
+    (0, false, true),
+    (1, true, false),
+    (3, false, false),
+    (1, false, true),
+    (2, true, false),
+    (4, false, false),
+    (2, false, true),
+    (3, true, false),
+    (5, false, false),
+    (3, false, true),
+    (4, true, false),
+    (6, false, false),
+    (4, false, true),
+    (5, true, false),
+    (7, false, false),
+    (5, false, true),
+    (6, true, false),
+    (8, false, false),
+];
+
+// The ASTC partition seeds of the partitions UASTC shares with BC7's two and three subset
+// patterns, and of the two subset partitions matching three subset BC7 patterns with two subsets
+// merged.
+const UASTC_PARTITIONS_2: [u32; 30] = [
+    28, 20, 16, 29, 91, 9, 107, 72, 149, 204, 50, 114, 496, 17, 78, 39, 252, 828, 43, 156, 116,
+    210, 476, 273, 684, 359, 246, 195, 694, 524,
+];
+const UASTC_PARTITIONS_3: [u32; 11] = [260, 74, 32, 156, 183, 15, 745, 0, 335, 902, 254];
+const UASTC_PARTITIONS_3_TO_2: [u32; 19] = [
+    36, 48, 61, 137, 161, 183, 226, 281, 302, 307, 479, 495, 593, 594, 605, 799, 812, 988, 993,
+];
+
+// The solid colour mode, which has no endpoints or weights.
+const UASTC_SOLID_MODE: usize = 8;
+
+// The layout of a UASTC mode's blocks.
+struct UastcMode {
+    // The mode's prefix code, read from the block's lowest bits.
+    code: u32,
+    code_bits: u32,
+    // Hints for transcoding to other formats, which RGBA doesn't need.
+    hint_bits: u32,
+    // Two for luminance and alpha, three for RGB and four for RGBA.
+    components: usize,
+    subsets: usize,
+    is_dual_plane: bool,
+    endpoint_range: usize,
+    weight_bits: u32,
+}
+
+const fn uastc_mode(
+    (code, code_bits): (u32, u32),
+    hint_bits: u32,
+    components: usize,
+    subsets: usize,
+    is_dual_plane: bool,
+    endpoint_range: usize,
+    weight_bits: u32,
+) -> UastcMode {
+    UastcMode {
+        code,
+        code_bits,
+        hint_bits,
+        components,
+        subsets,
+        is_dual_plane,
+        endpoint_range,
+        weight_bits,
+    }
+}
+
+const UASTC_MODES: [UastcMode; 19] = [
+    uastc_mode((0x1, 4), 15, 3, 1, false, 19, 4),
+    uastc_mode((0x35, 6), 15, 3, 1, false, 20, 2),
+    uastc_mode((0x1d, 5), 15, 3, 2, false, 8, 3),
+    uastc_mode((0x3, 5), 15, 3, 3, false, 7, 2),
+    uastc_mode((0x13, 5), 15, 3, 2, false, 12, 2),
+    uastc_mode((0xb, 5), 15, 3, 1, false, 20, 3),
+    uastc_mode((0x1b, 5), 15, 3, 1, true, 18, 2),
+    uastc_mode((0x7, 5), 15, 3, 2, false, 12, 2),
+    uastc_mode((0x17, 5), 0, 4, 0, false, 0, 0),
+    uastc_mode((0xf, 5), 23, 4, 2, false, 8, 2),
+    uastc_mode((0x2, 3), 17, 4, 1, false, 13, 4),
+    uastc_mode((0x0, 2), 17, 4, 1, true, 13, 2),
+    uastc_mode((0x6, 3), 17, 4, 1, false, 19, 3),
+    uastc_mode((0x1f, 5), 23, 4, 1, true, 20, 1),
+    uastc_mode((0xd, 5), 23, 2, 1, false, 20, 2),
+    uastc_mode((0x5, 7), 23, 2, 1, false, 20, 4),
+    uastc_mode((0x15, 6), 23, 2, 2, false, 20, 2),
+    uastc_mode((0x25, 6), 23, 2, 1, true, 20, 2),
+    uastc_mode((0x9, 4), 15, 3, 1, false, 11, 5),
+];
+
+// Reads a 128-bit UASTC block from its lowest bit.
+struct BlockReader {
+    bits: u128,
+    bit_offset: u32,
+}
+
+impl BlockReader {
+    fn bits(&mut self, count: u32) -> u32 {
+        let value = self.bits.checked_shr(self.bit_offset).unwrap_or(0) & ((1 << count) - 1);
+        self.bit_offset += count;
+
+        value as u32
+    }
+}
+
+// Decodes UASTC blocks, 16 bytes for each 4x4 texels, to RGBA.
+pub fn decode_uastc(data: &[u8], width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let blocks_x = width.div_ceil(4) as usize;
+    let blocks_y = height.div_ceil(4) as usize;
+
+    if data.len() < blocks_x * blocks_y * 16 {
+        return Err("UASTC data is truncated".to_owned());
+    }
+
+    let mut rgba = vec![0; width as usize * height as usize * 4];
+
+    for (block_index, block) in data.chunks_exact(16).take(blocks_x * blocks_y).enumerate() {
+        let mut bits = [0; 16];
+        bits.copy_from_slice(block);
+
+        let texels = decode_uastc_block(u128::from_le_bytes(bits))?;
+
+        for (texel, colour) in texels.iter().enumerate() {
+            let x = block_index % blocks_x * 4 + texel % 4;
+            let y = block_index / blocks_x * 4 + texel / 4;
+
+            if x < width as usize && y < height as usize {
+                rgba[(y * width as usize + x) * 4..][..4].copy_from_slice(colour);
+            }
+        }
+    }
+
+    Ok(rgba)
+}
+
+// UASTC blocks are ASTC blocks with a fixed set of layouts, so texels are decoded as ASTC would.
+fn decode_uastc_block(bits: u128) -> Result<[[u8; 4]; 16], String> {
+    let mode_index = UASTC_MODES
+        .iter()
+        .position(|mode| bits as u32 & ((1 << mode.code_bits) - 1) == mode.code)
+        .ok_or_else(|| "Invalid UASTC block mode".to_owned())?;
+    let mode = &UASTC_MODES[mode_index];
+
+    let mut reader = BlockReader {
+        bits,
+        bit_offset: mode.code_bits,
+    };
+
+    if mode_index == UASTC_SOLID_MODE {
+        let colour = [
+            reader.bits(8) as u8,
+            reader.bits(8) as u8,
+            reader.bits(8) as u8,
+            reader.bits(8) as u8,
+        ];
+
+        return Ok([colour; 16]);
+    }
+
+    reader.bit_offset += mode.hint_bits;
+
+    let partition_seed = match (mode.subsets, mode_index) {
+        (1, _) => None,
+        (2, 7) => UASTC_PARTITIONS_3_TO_2.get(reader.bits(5) as usize),
+        (2, _) => UASTC_PARTITIONS_2.get(reader.bits(5) as usize),
+        _ => UASTC_PARTITIONS_3.get(reader.bits(4) as usize),
+    };
+
+    let partitions: [usize; 16] = match partition_seed {
+        Some(&seed) => {
+            let mut partitions = [0; 16];
+
+            for (texel, partition) in partitions.iter_mut().enumerate() {
+                *partition = astc_partition(seed, texel % 4, texel / 4, mode.subsets);
+            }
+
+            partitions
+        }
+        None if mode.subsets == 1 => [0; 16],
+        None => return Err("Invalid UASTC partition pattern".to_owned()),
+    };
+
+    // The component weighted by the second plane: luminance and alpha blocks always use it for
+    // alpha.
+    let second_plane_component = match (mode.is_dual_plane, mode.components) {
+        (false, _) => None,
+        (true, 2) => Some(3),
+        (true, _) => Some(reader.bits(2) as usize),
+    };
+
+    // Endpoints are stored as trits or quints packed into whole bytes, followed by each value's
+    // remaining bits.
+    let (value_bits, has_trit, has_quint) = ASTC_RANGES[mode.endpoint_range];
+    let value_count = mode.components * 2 * mode.subsets;
+
+    let (bundle_size, base) = match (has_trit, has_quint) {
+        (true, _) => (5, 3u32),
+        (_, true) => (3, 5),
+        _ => (1, 1),
+    };
+
+    let mut bundles = Vec::new();
+
+    if base > 1 {
+        let bundle_count = value_count.div_ceil(bundle_size);
+
+        for bundle in 0..bundle_count {
+            let remaining = (value_count - bundle * bundle_size).min(bundle_size);
+            let bits = match (base, remaining) {
+                (3, 1) => 2,
+                (3, 2) => 4,
+                (3, 3) => 5,
+                (3, 4) => 7,
+                (3, _) => 8,
+                (_, 1) => 3,
+                (_, 2) => 5,
+                _ => 7,
+            };
+
+            bundles.push(reader.bits(bits));
+        }
+    }
+
+    let mut endpoint_values = [0; 16];
+
+    for (i, value) in endpoint_values[..value_count].iter_mut().enumerate() {
+        let mut packed = reader.bits(value_bits);
+
+        if base > 1 {
+            let digit = bundles[i / bundle_size] / base.pow((i % bundle_size) as u32) % base;
+            packed |= digit << value_bits;
+        }
+
+        *value = unquantize_endpoint(packed, mode.endpoint_range);
+    }
+
+    // The first texel of each subset, and of each plane, has an implied zero top weight bit.
+    let plane_count = if mode.is_dual_plane { 2 } else { 1 };
+    let mut weights = [[0; 2]; 16];
+
+    for texel in 0..16 {
+        let is_anchor = partitions[..texel]
+            .iter()
+            .all(|&partition| partition != partitions[texel]);
+
+        for weight in weights[texel][..plane_count].iter_mut() {
+            let bits = mode.weight_bits - is_anchor as u32;
+
+            *weight = unquantize_weight(reader.bits(bits), mode.weight_bits);
+        }
+    }
+
+    let subset_endpoints: Vec<[[u8; 4]; 2]> = endpoint_values[..value_count]
+        .chunks_exact(mode.components * 2)
+        .map(|values| astc_endpoints(values, mode.components))
+        .collect();
+
+    let mut texels = [[0; 4]; 16];
+
+    for (texel, colour) in texels.iter_mut().enumerate() {
+        let [low, high] = subset_endpoints[partitions[texel]];
+
+        for component in 0..4 {
+            let plane = (second_plane_component == Some(component)) as usize;
+            let weight = weights[texel][plane] as u32;
+
+            // Endpoints are expanded to 16 bits before interpolating, as ASTC's LDR decoding does.
+            let low = low[component] as u32 * 257;
+            let high = high[component] as u32 * 257;
+
+            colour[component] = (((low * (64 - weight) + high * weight + 32) >> 6) >> 8) as u8;
+        }
+    }
+
+    Ok(texels)
+}
+
+// The endpoints of a subset, from its luminance and alpha, RGB or RGBA values. RGB endpoints are
+// swapped and blue contracted when the second is darker, as ASTC encodes more precise colours.
+fn astc_endpoints(values: &[u8], components: usize) -> [[u8; 4]; 2] {
+    let v = |i: usize| values[i] as u32;
+    let alpha = |i: usize| if components == 4 { values[i] } else { 255 };
+
+    if components == 2 {
+        return [
+            [values[0], values[0], values[0], values[2]],
+            [values[1], values[1], values[1], values[3]],
+        ];
+    }
+
+    if v(1) + v(3) + v(5) >= v(0) + v(2) + v(4) {
+        return [
+            [values[0], values[2], values[4], alpha(6)],
+            [values[1], values[3], values[5], alpha(7)],
+        ];
+    }
+
+    let blue_contract = |r: usize, g: usize, b: usize, a: u8| {
+        [
+            ((v(r) + v(b)) >> 1) as u8,
+            ((v(g) + v(b)) >> 1) as u8,
+            values[b],
+            a,
+        ]
+    };
+
+    [
+        blue_contract(1, 3, 5, alpha(7)),
+        blue_contract(0, 2, 4, alpha(6)),
+    ]
+}
+
+// Repeats a value's bits until it fills the wider number of bits.
+fn replicate_bits(value: u32, bits: u32, wide_bits: u32) -> u32 {
+    let mut wide = 0;
+    let mut filled = 0;
+
+    while filled < wide_bits {
+        wide = (wide << bits) | value;
+        filled += bits;
+    }
+
+    wide >> (filled - wide_bits)
+}
+
+// Expands a value of an ASTC range, with any trit or quint above its bits, to 8 bits.
+fn unquantize_endpoint(value: u32, range: usize) -> u8 {
+    let (bits, has_trit, has_quint) = ASTC_RANGES[range];
+
+    if !has_trit && !has_quint {
+        return replicate_bits(value, bits, 8) as u8;
+    }
+
+    let digit = value >> bits;
+    let low_bit = if value & 1 != 0 { 0x1ff } else { 0 };
+    let x = (value & ((1 << bits) - 1)) >> 1;
+
+    // The scale of the trit or quint, and the remaining bits spread to fill the gaps between its
+    // values.
+    let (scale, spread) = match (has_trit, bits) {
+        (true, 1) => (204, 0),
+        (true, 2) => (93, (x << 8) | (x << 4) | (x << 2) | (x << 1)),
+        (true, 3) => (44, (x << 7) | (x << 2) | x),
+        (true, 4) => (22, (x << 6) | x),
+        (true, 5) => (11, (x << 5) | (x >> 2)),
+        (true, _) => (5, (x << 4) | (x >> 4)),
+        (false, 1) => (113, 0),
+        (false, 2) => (54, (x << 8) | (x << 3) | (x << 2)),
+        (false, 3) => (26, (x << 7) | (x << 1) | (x >> 1)),
+        (false, 4) => (13, (x << 6) | (x >> 1)),
+        (false, _) => (6, (x << 5) | (x >> 3)),
+    };
+
+    let unquantized = (digit * scale + spread) ^ low_bit;
+
+    ((low_bit & 0x80) | (unquantized >> 2)) as u8
+}
+
+// Expands a weight to ASTC's range of 0 to 64.
+fn unquantize_weight(value: u32, bits: u32) -> u8 {
+    let weight = replicate_bits(value, bits, 6);
+
+    (if weight > 32 { weight + 1 } else { weight }) as u8
+}
+
+// ASTC's partition function, which hashes the seed into a pattern for the texel's position.
+fn astc_partition(seed: u32, x: usize, y: usize, partition_count: usize) -> usize {
+    // Blocks with fewer than 31 texels use every other position.
+    let (x, y) = (x as u32 * 2, y as u32 * 2);
+
+    let seed = seed + (partition_count as u32 - 1) * 1024;
+
+    let mut rnum = seed;
+    rnum ^= rnum >> 15;
+    rnum = rnum.wrapping_mul(0xeede_0891);
+    rnum ^= rnum >> 5;
+    rnum = rnum.wrapping_add(rnum << 16);
+    rnum ^= rnum >> 7;
+    rnum ^= rnum >> 3;
+    rnum ^= rnum << 6;
+    rnum ^= rnum >> 17;
+
+    let mut seeds = [0u32; 8];
+
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s = (rnum >> (i * 4)) & 0xf;
+        *s *= *s;
+    }
+
+    let (shift1, shift2) = match (seed & 1 != 0, seed & 2 != 0, partition_count == 3) {
+        (true, true, three) => (4, if three { 6 } else { 5 }),
+        (true, false, three) => (5, if three { 6 } else { 5 }),
+        (false, true, three) => (if three { 6 } else { 5 }, 4),
+        (false, false, three) => (if three { 6 } else { 5 }, 5),
+    };
+
+    let shifts = [shift1, shift2];
+    let scaled = |i: usize| seeds[i] >> shifts[i % 2];
+
+    // The z terms of 3D blocks are left out.
+    let a = (scaled(0) * x + scaled(1) * y + (rnum >> 14)) & 0x3f;
+    let b = (scaled(2) * x + scaled(3) * y + (rnum >> 10)) & 0x3f;
+    let c = if partition_count >= 3 {
+        (scaled(4) * x + scaled(5) * y + (rnum >> 6)) & 0x3f
+    } else {
+        0
+    };
+
+    if a >= b && a >= c {
+        0
+    } else if b >= c {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes bits from the least significant bit of each byte.
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        bit_offset: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) {
+            for i in 0..count {
+                if self.bit_offset % 8 == 0 {
+                    self.data.push(0);
+                }
+
+                *self.data.last_mut().unwrap() |=
+                    (((value >> i) & 1) as u8) << (self.bit_offset % 8);
+                self.bit_offset += 1;
+            }
+        }
+
+        // Huffman codes are read from their most significant bit.
+        fn code(&mut self, code: u32, length: u32) {
+            for i in (0..length).rev() {
+                self.bits((code >> i) & 1, 1);
+            }
+        }
+
+        // A table giving every symbol a code of the same length, so each code is its symbol.
+        fn huffman_table(&mut self, symbol_count: u32, length: u32) {
+            self.bits(symbol_count, 14);
+            self.bits(21, 5);
+
+            // Code lengths 0 to 15 each have a 4-bit code length code.
+            for &code_length in CODE_LENGTH_ORDER.iter() {
+                self.bits(if code_length < 16 { 4 } else { 0 }, 3);
+            }
+
+            for _ in 0..symbol_count {
+                self.code(length, 4);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_huffman_tables() {
+        let mut writer = BitWriter::default();
+        writer.huffman_table(4, 2);
+        writer.code(2, 2);
+        writer.code(0, 2);
+        writer.code(3, 2);
+
+        let mut reader = BitReader::new(&writer.data);
+        let table = reader.huffman_table().unwrap();
+
+        assert_eq!(table.decode(&mut reader), Ok(2));
+        assert_eq!(table.decode(&mut reader), Ok(0));
+        assert_eq!(table.decode(&mut reader), Ok(3));
+
+        // More codes of a length than there's room for.
+        assert!(HuffmanTable::new(&[1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn decodes_etc1s_slices() {
+        let mut endpoints = BitWriter::default();
+
+        for _ in 0..3 {
+            endpoints.huffman_table(32, 5);
+        }
+
+        endpoints.huffman_table(8, 3);
+        endpoints.bits(0, 1);
+
+        // A single endpoint, moved from the initial colour of (16, 16, 16) to (31, 0, 16).
+        endpoints.code(0, 3);
+        endpoints.code(15, 5);
+        endpoints.code(16, 5);
+        endpoints.code(0, 5);
+
+        // Two raw selectors, the brightest and darkest colours for every texel.
+        let mut selectors = BitWriter::default();
+        selectors.bits(0b100, 3);
+        selectors.bits(0xffff_ffff, 32);
+        selectors.bits(0, 32);
+
+        let mut tables = BitWriter::default();
+        tables.huffman_table(4, 2);
+        tables.huffman_table(2, 1);
+        // Two selectors, a history of one and the run symbol.
+        tables.huffman_table(4, 2);
+        tables.huffman_table(64, 6);
+        tables.bits(1, 13);
+
+        let codebooks =
+            Etc1sCodebooks::new(1, &endpoints.data, 2, &selectors.data, &tables.data).unwrap();
+
+        // Three blocks: the first delta coded, the second predicted from the left. Then the first
+        // selector, the second and the history's most recent one.
+        let mut slice = BitWriter::default();
+        slice.code(3, 2);
+        slice.code(0, 1);
+        slice.code(0, 2);
+        slice.code(1, 2);
+        slice.code(0, 2);
+        slice.code(2, 2);
+
+        let mut rgba = vec![255; 10 * 2 * 4];
+        codebooks
+            .decode_slice(&slice.data, 10, 2, false, &mut rgba)
+            .unwrap();
+
+        // The base colour expands to (255, 0, 132), with the first intensity table's largest
+        // modifiers added and subtracted.
+        let bright = [255, 8, 140, 255];
+        let dark = [247, 0, 124, 255];
+
+        for y in 0..2 {
+            for x in 0..10 {
+                let expected = if x < 4 { bright } else { dark };
+
+                assert_eq!(rgba[(y * 10 + x) * 4..][..4], expected, "{}, {}", x, y);
+            }
+        }
+
+        // Alpha slices only write alpha, from green.
+        codebooks
+            .decode_slice(&slice.data, 10, 2, true, &mut rgba)
+            .unwrap();
+
+        assert_eq!(rgba[..4], [255, 8, 140, 8]);
+        assert_eq!(rgba[16..20], [247, 0, 124, 0]);
+    }
+
+    #[test]
+    fn rejects_invalid_etc1s_predictions() {
+        let mut endpoints = BitWriter::default();
+
+        for _ in 0..4 {
+            endpoints.huffman_table(2, 1);
+        }
+
+        endpoints.bits(1, 1);
+        endpoints.bits(0, 2);
+
+        let mut selectors = BitWriter::default();
+        selectors.bits(0b100, 3);
+        selectors.bits(0, 32);
+
+        let mut tables = BitWriter::default();
+        tables.huffman_table(4, 2);
+        tables.huffman_table(2, 1);
+        tables.huffman_table(4, 2);
+        tables.huffman_table(64, 6);
+        tables.bits(1, 13);
+
+        let codebooks =
+            Etc1sCodebooks::new(1, &endpoints.data, 1, &selectors.data, &tables.data).unwrap();
+
+        // The first block has no block to its left to predict from.
+        let mut slice = BitWriter::default();
+        slice.code(0, 2);
+        slice.code(0, 2);
+
+        let mut rgba = vec![0; 4 * 4 * 4];
+
+        assert!(codebooks
+            .decode_slice(&slice.data, 4, 4, false, &mut rgba)
+            .is_err());
+    }
+
+    #[test]
+    fn decodes_uastc_solid_colours() {
+        let mut block = BitWriter::default();
+        block.bits(0x17, 5);
+
+        for &channel in [10, 20, 30, 40].iter() {
+            block.bits(channel, 8);
+        }
+
+        block.data.resize(16, 0);
+
+        let rgba = decode_uastc(&block.data, 3, 2).unwrap();
+
+        assert_eq!(rgba.len(), 3 * 2 * 4);
+        assert!(rgba.chunks_exact(4).all(|pixel| pixel == [10, 20, 30, 40]));
+    }
+
+    #[test]
+    fn decodes_uastc_gradients() {
+        // Mode 18: RGB with 5-bit endpoints and 5-bit weights.
+        let mut block = BitWriter::default();
+        block.bits(0x9, 4);
+        block.bits(0, 15);
+
+        // Black to white.
+        for &value in [0, 31, 0, 31, 0, 31].iter() {
+            block.bits(value, 5);
+        }
+
+        // The first texel's weight has an implied zero top bit.
+        block.bits(0, 4);
+
+        for texel in 1..16 {
+            block.bits(texel * 2, 5);
+        }
+
+        let rgba = decode_uastc(&block.data, 4, 4).unwrap();
+
+        for (texel, pixel) in rgba.chunks_exact(4).enumerate() {
+            let weight = unquantize_weight(texel as u32 * 2, 5) as u32;
+            let expected = ((65535 * weight + 32) >> 6 >> 8) as u8;
+
+            assert_eq!(pixel, [expected, expected, expected, 255], "{}", texel);
+        }
+
+        assert_eq!(rgba[..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn rejects_truncated_uastc() {
+        assert!(decode_uastc(&[0; 16], 8, 4).is_err());
+    }
+
+    #[test]
+    fn unquantizes_every_endpoint_range() {
+        for (range, &(bits, has_trit, has_quint)) in ASTC_RANGES.iter().enumerate().skip(4) {
+            let levels = (1 << bits)
+                * if has_trit {
+                    3
+                } else if has_quint {
+                    5
+                } else {
+                    1
+                };
+
+            let mut values: Vec<u8> = (0..levels)
+                .map(|value| unquantize_endpoint(value, range))
+                .collect();
+            values.sort_unstable();
+            values.dedup();
+
+            // Every level is distinct and spread evenly from 0 to 255.
+            assert_eq!(values.len() as u32, levels, "{}", range);
+
+            for (level, &value) in values.iter().enumerate() {
+                let expected = (level as f32 * 255.0 / (levels - 1) as f32).round();
+
+                assert!(
+                    (value as f32 - expected).abs() <= 1.0,
+                    "{} {}",
+                    range,
+                    level
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn partitions_match_bc7() {
+        // The BC7 two subset patterns UASTC's partitions match, as a bit per texel set for the
+        // second subset, and whether ASTC's subsets are the other way around.
+        let bc7_patterns: [(u16, bool); 30] = [
+            (0xcccc, false),
+            (0x8888, false),
+            (0xeeee, true),
+            (0xecc8, false),
+            (0xc880, true),
+            (0xfeec, false),
+            (0xfec8, true),
+            (0xec80, true),
+            (0xc800, false),
+            (0xffec, true),
+            (0xfe80, false),
+            (0xe800, true),
+            (0xffe8, true),
+            (0xff00, true),
+            (0xfff0, false),
+            (0xf000, true),
+            (0x008e, true),
+            (0x7100, true),
+            (0x08ce, false),
+            (0x008c, false),
+            (0x7310, false),
+            (0x3100, true),
+            (0x8cce, true),
+            (0x088c, false),
+            (0x3110, true),
+            (0x6666, false),
+            (0x0ff0, true),
+            (0xaaaa, true),
+            (0xf0f0, true),
+            (0xc936, true),
+        ];
+
+        for (&seed, &(pattern, is_inverted)) in UASTC_PARTITIONS_2.iter().zip(bc7_patterns.iter()) {
+            for texel in 0..16 {
+                let bc7_subset = ((pattern >> texel) & 1) as usize ^ is_inverted as usize;
+
+                assert_eq!(astc_partition(seed, texel % 4, texel / 4, 2), bc7_subset);
+            }
+        }
+
+        // Three subset partitions match up to the order of their subsets.
+        for &seed in UASTC_PARTITIONS_3.iter() {
+            let subsets: Vec<usize> = (0..16)
+                .map(|texel| astc_partition(seed, texel % 4, texel / 4, 3))
+                .collect();
+
+            for subset in 0..3 {
+                assert!(subsets.contains(&subset));
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use super::*;
+use specs::prelude::*;
+
+// Spheres along each side of a layer, and layers stacked away from the camera.
+const GRID_SIZE: usize = 16;
+const LAYERS: usize = 8;
+const SPACING: f32 = 0.5;
+const MATERIALS: usize = 8;
+
+// Frames drawn before each configuration is measured, while pipelines are built and the settings
+// take effect, and the frames measured.
+const WARM_UP_FRAMES: u32 = 60;
+const MEASURED_FRAMES: u32 = 300;
+
+const CONFIGURATIONS: [(&str, RenderSettings); 3] = [
+    (
+        "Unsorted",
+        RenderSettings {
+            depth_prepass: false,
+            sort_draws: false,
+        },
+    ),
+    (
+        "Sorted",
+        RenderSettings {
+            depth_prepass: false,
+            sort_draws: true,
+        },
+    ),
+    (
+        "Sorted with depth pre-pass",
+        RenderSettings {
+            depth_prepass: true,
+            sort_draws: true,
+        },
+    ),
+];
+
+// Measures the average frame time of a scene with heavy overdraw under each render setting in
+// turn, printing the results. Frame times include waiting for the GPU, as the swap chain only
+// hands out a frame once an earlier one has been presented.
+#[derive(Default)]
+pub struct Benchmark {
+    is_scene_created: bool,
+    configuration: usize,
+    frame: u32,
+    measure_start: Option<std::time::Instant>,
+}
+
+impl Benchmark {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Called once per frame after the skybox has loaded, as the scene is lit by it.
+    pub fn update(&mut self, world: &mut World) {
+        if !self.is_scene_created {
+            create_benchmark_scene(world);
+            self.is_scene_created = true;
+        }
+
+        let (name, settings) = match CONFIGURATIONS.get(self.configuration) {
+            Some(configuration) => configuration,
+            None => return,
+        };
+
+        if self.frame == 0 {
+            *world.write_resource::<RenderSettings>() = *settings;
+        }
+
+        self.frame += 1;
+
+        if self.frame == WARM_UP_FRAMES {
+            self.measure_start = Some(std::time::Instant::now());
+        }
+
+        if self.frame < WARM_UP_FRAMES + MEASURED_FRAMES {
+            return;
+        }
+
+        let elapsed = self.measure_start.take().unwrap().elapsed();
+        let render_stats = world.read_resource::<RenderStats>();
+
+        println!(
+            "{}: {:.2} ms per frame, {} draw calls and {} in the depth pre-pass",
+            name,
+            elapsed.as_secs_f32() * 1000.0 / MEASURED_FRAMES as f32,
+            render_stats.draw_calls,
+            render_stats.depth_prepass_draw_calls
+        );
+
+        self.configuration += 1;
+        self.frame = 0;
+    }
+}
+
+// Fills the view with layers of PBR spheres, so most pixels are covered several times. The
+// layers are created from the back, so drawing objects in the order they're joined shades every
+// layer.
+fn create_benchmark_scene(world: &mut World) {
+    let renderables = {
+        let (render_state, asset_loader, mut asset_server): (
+            ReadExpect<RenderState>,
+            ReadExpect<AssetLoader>,
+            WriteExpect<AssetServer>,
+        ) = world.system_data();
+
+        let skybox = asset_loader.skybox.as_ref().unwrap();
+
+        (0..MATERIALS)
+            .map(|material_index| {
+                let t = material_index as f32 / (MATERIALS - 1) as f32;
+
+                let mut pbr_params = obj::create_pbr_params(
+                    &render_state.device,
+                    &render_state.queue,
+                    None,
+                    &obj::ObjTextures::default(),
+                    skybox,
+                    &mut asset_server,
+                );
+
+                pbr_params.albedo_property.factor = Some([1.0 - t * 0.5, 0.5, t, 1.0]);
+                pbr_params.metal_roughness_property.factor = Some([0.0, t, 1.0 - t * 0.8, 0.0]);
+
+                Renderable::new_pbr(
+                    &render_state.device,
+                    &render_state.pipeline_cache,
+                    &render_state.swap_chain_desc,
+                    vec![create_sphere(&render_state.device, SPACING * 0.7)],
+                    pbr_params,
+                    None,
+                )
+            })
+            .collect::<Vec<Renderable>>()
+    };
+
+    let offset = |index: usize, count: usize| (index as f32 - (count - 1) as f32 / 2.0) * SPACING;
+
+    for layer in (0..LAYERS).rev() {
+        for row in 0..GRID_SIZE {
+            for column in 0..GRID_SIZE {
+                let renderable = &renderables[(row + column + layer) % MATERIALS];
+
+                world
+                    .create_entity()
+                    .with(Renderable::new_shared(
+                        renderable.lod_handle().clone(),
+                        renderable.material.clone(),
+                    ))
+                    .with(Pose::new(nalgebra::Similarity3::from_parts(
+                        nalgebra::Translation3::new(
+                            offset(column, GRID_SIZE),
+                            offset(row, GRID_SIZE),
+                            -(layer as f32) * SPACING,
+                        ),
+                        nalgebra::UnitQuaternion::identity(),
+                        1.0,
+                    )))
+                    .build();
+            }
+        }
+    }
+
+    world
+        .create_entity()
+        .with(Light::default())
+        .with(Pose::new(nalgebra::Similarity3::from_parts(
+            nalgebra::Translation3::new(0.0, 2.0, 4.0),
+            nalgebra::UnitQuaternion::identity(),
+            1.0,
+        )))
+        .build();
+
+    let mut render_state = world.write_resource::<RenderState>();
+
+    // Look at the front layer, filling the view.
+    let aspect_ratio =
+        render_state.swap_chain_desc.width as f32 / render_state.swap_chain_desc.height as f32;
+    let fov_y = std::f32::consts::PI / 3.0;
+    let distance = GRID_SIZE as f32 * SPACING / 2.0 / (fov_y / 2.0).tan();
+
+    *world.write_resource::<Camera>() = Camera::new(
+        &nalgebra::Point3::new(0.0, 0.0, distance),
+        &nalgebra::Point3::origin(),
+        &nalgebra::Vector3::y(),
+        aspect_ratio,
+        fov_y,
+        0.1,
+        100.0,
+    );
+
+    // Frames aren't held back to the display's refresh rate, so they're timed by the renderer.
+    render_state.swap_chain_desc.present_mode = wgpu::PresentMode::Immediate;
+
+    let size = winit::dpi::PhysicalSize::new(
+        render_state.swap_chain_desc.width,
+        render_state.swap_chain_desc.height,
+    );
+
+    render_state.resize(size);
+}
+
+// A UV sphere, with normals, tangents and texture coordinates.
+fn create_sphere(device: &wgpu::Device, radius: f32) -> Mesh {
+    const RINGS: u32 = 32;
+    const SEGMENTS: u32 = 64;
+
+    let mut vertices = Vec::new();
+
+    for ring in 0..=RINGS {
+        let v = ring as f32 / RINGS as f32;
+        let theta = v * std::f32::consts::PI;
+
+        for segment in 0..=SEGMENTS {
+            let u = segment as f32 / SEGMENTS as f32;
+            let phi = u * std::f32::consts::PI * 2.0;
+
+            let normal = [
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ];
+
+            vertices.push(Vertex {
+                position: [normal[0] * radius, normal[1] * radius, normal[2] * radius],
+                normal,
+                tangent: [-phi.sin(), 0.0, phi.cos(), 1.0],
+                tex_coord: [u, v],
+                joints: [0; 4],
+                weights: [0.0; 4],
+                colour: [1.0; 4],
+            });
+        }
+    }
+
+    let mut indices = Vec::new();
+
+    for ring in 0..RINGS {
+        for segment in 0..SEGMENTS {
+            let a = ring * (SEGMENTS + 1) + segment;
+            let b = a + SEGMENTS + 1;
+
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    Mesh::new(device, &vertices, Some(&indices))
+}
@@ -1,8 +1,59 @@
 use nalgebra::*;
+use specs::Join;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Perspective(Perspective3<f32>),
+    Orthographic(Orthographic3<f32>),
+}
+
+impl Projection {
+    // The aspect ratio is used when the glTF camera doesn't define one.
+    pub fn new_from_gltf(gltf_camera: &gltf::Camera, aspect_ratio: f32) -> Self {
+        match gltf_camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => {
+                Projection::Perspective(Perspective3::new(
+                    perspective.aspect_ratio().unwrap_or(aspect_ratio),
+                    perspective.yfov(),
+                    perspective.znear(),
+                    // Infinite projections aren't supported.
+                    perspective.zfar().unwrap_or(1000.0),
+                ))
+            }
+            gltf::camera::Projection::Orthographic(orthographic) => {
+                Projection::Orthographic(Orthographic3::new(
+                    -orthographic.xmag(),
+                    orthographic.xmag(),
+                    -orthographic.ymag(),
+                    orthographic.ymag(),
+                    orthographic.znear(),
+                    orthographic.zfar(),
+                ))
+            }
+        }
+    }
+
+    pub fn to_homogeneous(self) -> Matrix4<f32> {
+        match self {
+            Projection::Perspective(perspective) => perspective.to_homogeneous(),
+            Projection::Orthographic(orthographic) => orthographic.to_homogeneous(),
+        }
+    }
+}
+
+// A named viewpoint. Its entity's pose places the camera, looking along -Z as in glTF.
+pub struct SceneCamera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+impl specs::Component for SceneCamera {
+    type Storage = specs::VecStorage<Self>;
+}
 
 pub struct Camera {
     pub view_matrix: Isometry3<f32>,
-    pub proj_matrix: Perspective3<f32>,
+    pub proj_matrix: Projection,
     camera_up: Vector3<f32>,
     camera_target: Vector3<f32>,
 
@@ -28,7 +79,8 @@ impl Camera {
         z_far: f32,
     ) -> Self {
         let view_matrix = Isometry3::look_at_rh(eye, target, up);
-        let proj_matrix = Perspective3::new(aspect_ratio, fov_y, z_near, z_far);
+        let proj_matrix =
+            Projection::Perspective(Perspective3::new(aspect_ratio, fov_y, z_near, z_far));
 
         Self {
             view_matrix,
@@ -46,6 +98,20 @@ impl Camera {
         }
     }
 
+    // Moves the camera to a scene camera's viewpoint. The orbit target is kept at the same
+    // distance in front of the camera.
+    pub fn set_active(&mut self, scene_camera: &SceneCamera, pose: &super::Pose) {
+        let target_distance =
+            (self.view_matrix.inverse().translation.vector - self.camera_target).norm();
+
+        let transform = pose.isometry();
+
+        self.camera_target =
+            transform.translation.vector + transform * -Vector3::z() * target_distance.max(1.0);
+        self.view_matrix = transform.inverse();
+        self.proj_matrix = scene_camera.projection;
+    }
+
     fn update_camera_zoom(&mut self, zoom_magnitude: f32) {
         let mut transform = self.view_matrix.inverse();
 
@@ -159,16 +225,36 @@ impl Camera {
 
                 self.last_mouse_pos = current_mouse_pos;
             }
-            winit::event::WindowEvent::MouseWheel { delta, .. } => match delta {
-                winit::event::MouseScrollDelta::PixelDelta(winit::dpi::LogicalPosition {
-                    y,
-                    ..
-                }) => {
-                    self.update_camera_zoom(*y as f32);
-                }
-                _ => {}
-            },
+            winit::event::WindowEvent::MouseWheel {
+                delta:
+                    winit::event::MouseScrollDelta::PixelDelta(winit::dpi::LogicalPosition {
+                        y, ..
+                    }),
+                ..
+            } => {
+                self.update_camera_zoom(*y as f32);
+            }
             _ => {}
         }
     }
 }
+
+// Makes the scene camera with the given name the active camera. Returns false if there is none.
+pub fn set_active_camera(world: &mut specs::World, name: &str) -> bool {
+    let (mut camera, scene_cameras, poses): (
+        specs::WriteExpect<Camera>,
+        specs::ReadStorage<SceneCamera>,
+        specs::ReadStorage<super::Pose>,
+    ) = world.system_data();
+
+    match (&scene_cameras, &poses)
+        .join()
+        .find(|(scene_camera, _)| scene_camera.name.as_deref() == Some(name))
+    {
+        Some((scene_camera, pose)) => {
+            camera.set_active(scene_camera, pose);
+            true
+        }
+        None => false,
+    }
+}
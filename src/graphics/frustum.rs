@@ -0,0 +1,216 @@
+use nalgebra::*;
+
+// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug)]
+pub struct BoundingBox {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl BoundingBox {
+    // Returns `None` if there are no points.
+    pub fn new_from_points<I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Point3<f32>>,
+    {
+        points.into_iter().fold(None, |bounding_box, point| {
+            Some(match bounding_box {
+                Some(bounding_box) => BoundingBox {
+                    min: bounding_box.min.inf(&point),
+                    max: bounding_box.max.sup(&point),
+                },
+                None => BoundingBox {
+                    min: point,
+                    max: point,
+                },
+            })
+        })
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn centre(&self) -> Point3<f32> {
+        center(&self.min, &self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    // The sphere enclosing the box, as its centre and radius.
+    pub fn bounding_sphere(&self) -> (Point3<f32>, f32) {
+        (self.centre(), self.half_extents().norm())
+    }
+
+    // The box enclosing this box once transformed.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingBox {
+        let centre = matrix.transform_point(&self.centre());
+
+        // Each axis of the transformed box spans the absolute projections of the half extents.
+        let rotation_scale = matrix.fixed_slice::<U3, U3>(0, 0).abs();
+        let half_extents = rotation_scale * self.half_extents();
+
+        BoundingBox {
+            min: centre - half_extents,
+            max: centre + half_extents,
+        }
+    }
+}
+
+// The planes bounding a camera's view volume, with their normals pointing inwards.
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Extracts the planes from a combined projection and view matrix.
+    pub fn new(view_proj_matrix: &Matrix4<f32>) -> Self {
+        let row = |index: usize| view_proj_matrix.row(index).transpose();
+
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) + row(2),
+            row(3) - row(2),
+        ];
+
+        Self {
+            planes: [
+                normalize_plane(planes[0]),
+                normalize_plane(planes[1]),
+                normalize_plane(planes[2]),
+                normalize_plane(planes[3]),
+                normalize_plane(planes[4]),
+                normalize_plane(planes[5]),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, centre: &Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| signed_distance(plane, centre) >= -radius)
+    }
+
+    pub fn intersects_box(&self, bounding_box: &BoundingBox) -> bool {
+        // Only the corner furthest along each plane's normal needs to be inside it.
+        self.planes.iter().all(|plane| {
+            let corner = Point3::new(
+                if plane.x >= 0.0 {
+                    bounding_box.max.x
+                } else {
+                    bounding_box.min.x
+                },
+                if plane.y >= 0.0 {
+                    bounding_box.max.y
+                } else {
+                    bounding_box.min.y
+                },
+                if plane.z >= 0.0 {
+                    bounding_box.max.z
+                } else {
+                    bounding_box.min.z
+                },
+            );
+
+            signed_distance(plane, &corner) >= 0.0
+        })
+    }
+}
+
+fn normalize_plane(plane: Vector4<f32>) -> Vector4<f32> {
+    plane / plane.xyz().norm()
+}
+
+fn signed_distance(plane: &Vector4<f32>, point: &Point3<f32>) -> f32 {
+    plane.xyz().dot(&point.coords) + plane.w
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A camera at the origin looking down -Z with a 90 degree field of view, so at a distance d
+    // the view spans -d to d on both axes, between depths of 1 and 100.
+    fn frustum() -> Frustum {
+        let proj = Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, 1.0, 100.0);
+
+        Frustum::new(&proj.to_homogeneous())
+    }
+
+    fn bounding_box(min: [f32; 3], max: [f32; 3]) -> BoundingBox {
+        BoundingBox {
+            min: Point3::from(min),
+            max: Point3::from(max),
+        }
+    }
+
+    #[test]
+    fn intersects_spheres() {
+        let frustum = frustum();
+
+        // Inside.
+        assert!(frustum.intersects_sphere(&Point3::new(0.0, 0.0, -10.0), 1.0));
+        assert!(frustum.intersects_sphere(&Point3::new(9.0, -9.0, -50.0), 0.5));
+
+        // Outside: behind the camera, past the far plane and to either side.
+        assert!(!frustum.intersects_sphere(&Point3::new(0.0, 0.0, 10.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Point3::new(0.0, 0.0, -150.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Point3::new(20.0, 0.0, -10.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Point3::new(0.0, -20.0, -10.0), 1.0));
+
+        // Centres 1/sqrt(2) and 3/sqrt(2) outside the right plane, straddling it and just past it.
+        assert!(frustum.intersects_sphere(&Point3::new(11.0, 0.0, -10.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Point3::new(13.0, 0.0, -10.0), 2.0));
+
+        // Straddling the near and far planes.
+        assert!(frustum.intersects_sphere(&Point3::new(0.0, 0.0, -0.5), 1.0));
+        assert!(frustum.intersects_sphere(&Point3::new(0.0, 0.0, -100.5), 1.0));
+    }
+
+    #[test]
+    fn intersects_boxes() {
+        let frustum = frustum();
+
+        // Inside.
+        assert!(frustum.intersects_box(&bounding_box([-1.0, -1.0, -11.0], [1.0, 1.0, -9.0])));
+
+        // Outside: behind the camera, past the far plane and to either side.
+        assert!(!frustum.intersects_box(&bounding_box([-1.0, -1.0, 5.0], [1.0, 1.0, 7.0])));
+        assert!(!frustum.intersects_box(&bounding_box([-1.0, -1.0, -120.0], [1.0, 1.0, -110.0])));
+        assert!(!frustum.intersects_box(&bounding_box([20.0, -1.0, -11.0], [22.0, 1.0, -9.0])));
+        assert!(!frustum.intersects_box(&bounding_box([-1.0, 20.0, -11.0], [1.0, 22.0, -9.0])));
+
+        // Straddling the left plane, the near plane and the far plane.
+        assert!(frustum.intersects_box(&bounding_box([-12.0, -1.0, -11.0], [-8.0, 1.0, -9.0])));
+        assert!(frustum.intersects_box(&bounding_box([-0.1, -0.1, -2.0], [0.1, 0.1, 0.5])));
+        assert!(frustum.intersects_box(&bounding_box([-1.0, -1.0, -110.0], [1.0, 1.0, -90.0])));
+
+        // Boxes surrounding the camera contain part of the view.
+        assert!(frustum.intersects_box(&bounding_box([-200.0; 3], [200.0; 3])));
+    }
+
+    #[test]
+    fn follows_the_view_matrix() {
+        // Looking down +X from (10, 0, 0) instead.
+        let proj = Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, 1.0, 100.0);
+        let view = Isometry3::look_at_rh(
+            &Point3::new(10.0, 0.0, 0.0),
+            &Point3::new(20.0, 0.0, 0.0),
+            &Vector3::y(),
+        );
+        let frustum = Frustum::new(&(proj.to_homogeneous() * view.to_homogeneous()));
+
+        assert!(frustum.intersects_sphere(&Point3::new(30.0, 0.0, 0.0), 1.0));
+        assert!(!frustum.intersects_sphere(&Point3::new(0.0, 0.0, -10.0), 1.0));
+        assert!(frustum.intersects_box(&bounding_box([29.0, -1.0, -1.0], [31.0, 1.0, 1.0])));
+        assert!(!frustum.intersects_box(&bounding_box([-1.0, -1.0, -11.0], [1.0, 1.0, -9.0])));
+    }
+}
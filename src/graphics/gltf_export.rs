@@ -0,0 +1,607 @@
+use super::*;
+use serde_json::json;
+use specs::prelude::*;
+use std::collections::HashMap;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+
+#[derive(Default)]
+struct GlbBuilder {
+    bin: Vec<u8>,
+    buffer_views: Vec<serde_json::Value>,
+    accessors: Vec<serde_json::Value>,
+    images: Vec<serde_json::Value>,
+    textures: Vec<serde_json::Value>,
+    materials: Vec<serde_json::Value>,
+    meshes: Vec<serde_json::Value>,
+    nodes: Vec<serde_json::Value>,
+    lights: Vec<serde_json::Value>,
+    extensions_used: Vec<String>,
+    // The indices of the materials and textures already written, by handle, so assets shared
+    // between entities, as the asset server shares them, are written once.
+    material_ids: HashMap<usize, usize>,
+    texture_ids: HashMap<usize, usize>,
+}
+
+impl GlbBuilder {
+    fn push_buffer_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        // Buffer views are aligned to 4 bytes, the largest component size written.
+        while self.bin.len() % 4 != 0 {
+            self.bin.push(0);
+        }
+
+        let mut buffer_view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len(),
+        });
+
+        if let Some(target) = target {
+            buffer_view["target"] = json!(target);
+        }
+
+        self.bin.extend_from_slice(data);
+        self.buffer_views.push(buffer_view);
+
+        self.buffer_views.len() - 1
+    }
+
+    fn push_float_accessor<T: Copy>(&mut self, data: &[T], accessor_type: &str) -> usize {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+
+        let buffer_view = self.push_buffer_view(bytes, Some(ARRAY_BUFFER));
+
+        self.accessors.push(json!({
+            "bufferView": buffer_view,
+            "componentType": FLOAT,
+            "count": data.len(),
+            "type": accessor_type,
+        }));
+
+        self.accessors.len() - 1
+    }
+
+    fn push_mesh(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderable: &Renderable,
+        material: usize,
+    ) -> Result<usize, String> {
+        let mut primitives = Vec::new();
+
+        for mesh in renderable.meshes() {
+            if mesh.morph_target_buffer.is_some() {
+                return Err("Meshes with morph targets can't be exported".to_owned());
+            }
+
+            let (vertices, indices) = mesh.read_back(device, queue)?;
+
+            if vertices.is_empty() {
+                continue;
+            }
+
+            let positions: Vec<[f32; 3]> = vertices.iter().map(|v| v.position).collect();
+            let normals: Vec<[f32; 3]> = vertices.iter().map(|v| v.normal).collect();
+            let tangents: Vec<[f32; 4]> = vertices.iter().map(|v| v.tangent).collect();
+            let tex_coords: Vec<[f32; 2]> = vertices.iter().map(|v| v.tex_coord).collect();
+
+            let position_accessor = self.push_float_accessor(&positions, "VEC3");
+
+            // Position accessors must have bounds.
+            let mut min = positions[0];
+            let mut max = positions[0];
+
+            for position in positions.iter() {
+                for i in 0..3 {
+                    min[i] = min[i].min(position[i]);
+                    max[i] = max[i].max(position[i]);
+                }
+            }
+
+            self.accessors[position_accessor]["min"] = json!(min);
+            self.accessors[position_accessor]["max"] = json!(max);
+
+            let mut primitive = json!({
+                "attributes": {
+                    "POSITION": position_accessor,
+                    "NORMAL": self.push_float_accessor(&normals, "VEC3"),
+                    "TANGENT": self.push_float_accessor(&tangents, "VEC4"),
+                    "TEXCOORD_0": self.push_float_accessor(&tex_coords, "VEC2"),
+                },
+                "material": material,
+                "mode": match mesh.primitive_topology {
+                    wgpu::PrimitiveTopology::PointList => 0,
+                    wgpu::PrimitiveTopology::LineList => 1,
+                    wgpu::PrimitiveTopology::LineStrip => 3,
+                    wgpu::PrimitiveTopology::TriangleList => 4,
+                    wgpu::PrimitiveTopology::TriangleStrip => 5,
+                },
+            });
+
+            // Meshes without vertex colours are stored as white, which needn't be written.
+            if vertices.iter().any(|v| v.colour != [1.0; 4]) {
+                let colours: Vec<[f32; 4]> = vertices.iter().map(|v| v.colour).collect();
+
+                primitive["attributes"]["COLOR_0"] =
+                    json!(self.push_float_accessor(&colours, "VEC4"));
+            }
+
+            if let Some(indices) = &indices {
+                let bytes = unsafe {
+                    std::slice::from_raw_parts(
+                        indices.as_ptr() as *const u8,
+                        std::mem::size_of_val(indices.as_slice()),
+                    )
+                };
+
+                let buffer_view = self.push_buffer_view(bytes, Some(ELEMENT_ARRAY_BUFFER));
+
+                self.accessors.push(json!({
+                    "bufferView": buffer_view,
+                    "componentType": UNSIGNED_INT,
+                    "count": indices.len(),
+                    "type": "SCALAR",
+                }));
+
+                primitive["indices"] = json!(self.accessors.len() - 1);
+            }
+
+            primitives.push(primitive);
+        }
+
+        self.meshes.push(json!({ "primitives": primitives }));
+
+        Ok(self.meshes.len() - 1)
+    }
+
+    fn push_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &Handle<Texture>,
+    ) -> Result<usize, String> {
+        if let Some(&texture_id) = self.texture_ids.get(&texture.id()) {
+            return Ok(texture_id);
+        }
+
+        let texture_data = texture.read_back(device, queue)?;
+        let mut png_data = Vec::new();
+
+        image::png::PNGEncoder::new(&mut png_data)
+            .encode(
+                &texture_data.rgba_data,
+                texture_data.width,
+                texture_data.height,
+                image::ColorType::Rgba8,
+            )
+            .map_err(|err| format!("Failed to encode texture: {}", err))?;
+
+        let buffer_view = self.push_buffer_view(&png_data, None);
+
+        self.images.push(json!({
+            "bufferView": buffer_view,
+            "mimeType": "image/png",
+        }));
+
+        self.textures.push(json!({
+            "sampler": 0,
+            "source": self.images.len() - 1,
+        }));
+
+        self.texture_ids
+            .insert(texture.id(), self.textures.len() - 1);
+
+        Ok(self.textures.len() - 1)
+    }
+
+    // Returns `None` for materials that can't be written, such as the skybox's.
+    fn push_material(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        material: &MaterialHandle,
+    ) -> Result<Option<usize>, String> {
+        if let Some(&material_id) = self.material_ids.get(&material.id()) {
+            return Ok(Some(material_id));
+        }
+
+        let material_id = if let Some(properties) = material.pbr_properties() {
+            self.push_pbr_material(device, queue, properties)?
+        } else if let Some(properties) = material.unlit_properties() {
+            self.push_unlit_material(device, queue, properties)?
+        } else {
+            return Ok(None);
+        };
+
+        self.material_ids.insert(material.id(), material_id);
+
+        Ok(Some(material_id))
+    }
+
+    fn push_pbr_material(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        properties: &PbrProperties,
+    ) -> Result<usize, String> {
+        let texture_info =
+            |builder: &mut GlbBuilder, property: &MaterialProperty| match property.texture_id {
+                Some(texture_id) => {
+                    let texture = &properties.textures[texture_id];
+
+                    Ok::<_, String>(Some(
+                        json!({ "index": builder.push_texture(device, queue, texture)? }),
+                    ))
+                }
+                None => Ok(None),
+            };
+
+        let mut pbr_metallic_roughness = json!({});
+        let mut material = json!({});
+
+        let albedo = properties.albedo_property.factor.unwrap_or([1.0; 4]);
+        pbr_metallic_roughness["baseColorFactor"] = json!(albedo);
+
+        if let Some(info) = texture_info(self, &properties.albedo_property)? {
+            pbr_metallic_roughness["baseColorTexture"] = info;
+        }
+
+        let metal_roughness = properties
+            .metal_roughness_property
+            .factor
+            .unwrap_or([0.0, 1.0, 1.0, 0.0]);
+        pbr_metallic_roughness["metallicFactor"] = json!(metal_roughness[1]);
+        pbr_metallic_roughness["roughnessFactor"] = json!(metal_roughness[2]);
+
+        if let Some(info) = texture_info(self, &properties.metal_roughness_property)? {
+            pbr_metallic_roughness["metallicRoughnessTexture"] = info;
+        }
+
+        material["pbrMetallicRoughness"] = pbr_metallic_roughness;
+
+        let emissive = properties.emissive_property.factor.unwrap_or([1.0; 4]);
+        material["emissiveFactor"] = json!([emissive[0], emissive[1], emissive[2]]);
+
+        if let Some(info) = texture_info(self, &properties.emissive_property)? {
+            material["emissiveTexture"] = info;
+        }
+
+        if let Some(info) = texture_info(self, &properties.normal_property)? {
+            material["normalTexture"] = info;
+        }
+
+        if let Some(info) = texture_info(self, &properties.ao_property)? {
+            material["occlusionTexture"] = info;
+        }
+
+        let extensions = &properties.extension_properties;
+        let mut material_extensions = json!({});
+
+        if let (Some(clearcoat), Some(roughness), Some(normal)) = (
+            &extensions.clearcoat_property,
+            &extensions.clearcoat_roughness_property,
+            &extensions.clearcoat_normal_property,
+        ) {
+            let mut extension = json!({
+                "clearcoatFactor": clearcoat.factor.map_or(1.0, |factor| factor[0]),
+                "clearcoatRoughnessFactor": roughness.factor.map_or(1.0, |factor| factor[1]),
+            });
+
+            if let Some(info) = texture_info(self, clearcoat)? {
+                extension["clearcoatTexture"] = info;
+            }
+
+            if let Some(info) = texture_info(self, roughness)? {
+                extension["clearcoatRoughnessTexture"] = info;
+            }
+
+            if let Some(info) = texture_info(self, normal)? {
+                extension["clearcoatNormalTexture"] = info;
+            }
+
+            material_extensions["KHR_materials_clearcoat"] = extension;
+        }
+
+        if let (Some(colour), Some(roughness)) = (
+            &extensions.sheen_colour_property,
+            &extensions.sheen_roughness_property,
+        ) {
+            let colour_factor = colour.factor.unwrap_or([1.0; 4]);
+
+            let mut extension = json!({
+                "sheenColorFactor": [colour_factor[0], colour_factor[1], colour_factor[2]],
+                "sheenRoughnessFactor": roughness.factor.map_or(1.0, |factor| factor[3]),
+            });
+
+            if let Some(info) = texture_info(self, colour)? {
+                extension["sheenColorTexture"] = info;
+            }
+
+            if let Some(info) = texture_info(self, roughness)? {
+                extension["sheenRoughnessTexture"] = info;
+            }
+
+            material_extensions["KHR_materials_sheen"] = extension;
+        }
+
+        if let Some(transmission) = &extensions.transmission_property {
+            let mut extension = json!({
+                "transmissionFactor": transmission.factor.map_or(1.0, |factor| factor[0]),
+            });
+
+            if let Some(info) = texture_info(self, transmission)? {
+                extension["transmissionTexture"] = info;
+            }
+
+            material_extensions["KHR_materials_transmission"] = extension;
+        }
+
+        if let (Some(specular), Some(colour)) = (
+            &extensions.specular_property,
+            &extensions.specular_colour_property,
+        ) {
+            let colour_factor = colour.factor.unwrap_or([1.0; 4]);
+
+            let mut extension = json!({
+                "specularFactor": specular.factor.map_or(1.0, |factor| factor[3]),
+                "specularColorFactor": [colour_factor[0], colour_factor[1], colour_factor[2]],
+            });
+
+            if let Some(info) = texture_info(self, specular)? {
+                extension["specularTexture"] = info;
+            }
+
+            if let Some(info) = texture_info(self, colour)? {
+                extension["specularColorTexture"] = info;
+            }
+
+            material_extensions["KHR_materials_specular"] = extension;
+        }
+
+        if let Some(anisotropy) = &extensions.anisotropy_property {
+            let factor = anisotropy.factor.unwrap_or([1.0, 0.0, 0.0, 0.0]);
+
+            let mut extension = json!({
+                "anisotropyStrength": factor[2],
+                "anisotropyRotation": factor[1].atan2(factor[0]),
+            });
+
+            if let Some(info) = texture_info(self, anisotropy)? {
+                extension["anisotropyTexture"] = info;
+            }
+
+            material_extensions["KHR_materials_anisotropy"] = extension;
+        }
+
+        if extensions.ior != 1.5 {
+            material_extensions["KHR_materials_ior"] = json!({ "ior": extensions.ior });
+        }
+
+        if extensions.emissive_strength != 1.0 {
+            material_extensions["KHR_materials_emissive_strength"] =
+                json!({ "emissiveStrength": extensions.emissive_strength });
+        }
+
+        if let Some(material_extensions) = material_extensions.as_object() {
+            for name in material_extensions.keys() {
+                if !self.extensions_used.contains(name) {
+                    self.extensions_used.push(name.clone());
+                }
+            }
+
+            if !material_extensions.is_empty() {
+                material["extensions"] = json!(material_extensions);
+            }
+        }
+
+        self.materials.push(material);
+
+        Ok(self.materials.len() - 1)
+    }
+
+    // Unlit materials are written with a metallic-roughness fallback for viewers without
+    // KHR_materials_unlit.
+    fn push_unlit_material(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        properties: &UnlitProperties,
+    ) -> Result<usize, String> {
+        let mut pbr_metallic_roughness = json!({
+            "baseColorFactor": properties.base_colour_factor,
+            "metallicFactor": 0.0,
+            "roughnessFactor": 0.9,
+        });
+
+        if let Some(texture) = &properties.base_colour_texture {
+            pbr_metallic_roughness["baseColorTexture"] =
+                json!({ "index": self.push_texture(device, queue, texture)? });
+        }
+
+        let name = "KHR_materials_unlit".to_owned();
+
+        if !self.extensions_used.contains(&name) {
+            self.extensions_used.push(name);
+        }
+
+        self.materials.push(json!({
+            "pbrMetallicRoughness": pbr_metallic_roughness,
+            "extensions": { "KHR_materials_unlit": {} },
+        }));
+
+        Ok(self.materials.len() - 1)
+    }
+
+    fn push_light(&mut self, light: &Light) -> usize {
+        let mut gltf_light = json!({
+            "color": light.colour,
+            "intensity": light.intensity,
+        });
+
+        match light.light_type {
+            LightType::Directional => gltf_light["type"] = json!("directional"),
+            LightType::Point => gltf_light["type"] = json!("point"),
+            LightType::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                gltf_light["type"] = json!("spot");
+                gltf_light["spot"] = json!({
+                    "innerConeAngle": inner_cone_angle,
+                    "outerConeAngle": outer_cone_angle,
+                });
+            }
+        }
+
+        if let Some(name) = &light.name {
+            gltf_light["name"] = json!(name);
+        }
+
+        // Directional lights have no range.
+        if let (Some(range), false) = (light.range, light.light_type == LightType::Directional) {
+            gltf_light["range"] = json!(range);
+        }
+
+        self.lights.push(gltf_light);
+
+        self.lights.len() - 1
+    }
+
+    fn to_glb(&self) -> Vec<u8> {
+        let mut root = json!({
+            "asset": {
+                "version": "2.0",
+                "generator": "rust-pbr",
+            },
+            "scene": 0,
+            "scenes": [{}],
+        });
+
+        if !self.nodes.is_empty() {
+            root["scenes"][0]["nodes"] = json!((0..self.nodes.len()).collect::<Vec<usize>>());
+        }
+
+        let arrays = [
+            ("nodes", &self.nodes),
+            ("bufferViews", &self.buffer_views),
+            ("accessors", &self.accessors),
+            ("images", &self.images),
+            ("textures", &self.textures),
+            ("materials", &self.materials),
+            ("meshes", &self.meshes),
+        ];
+
+        // Empty arrays aren't allowed by the glTF schema.
+        for (name, array) in arrays.iter() {
+            if !array.is_empty() {
+                root[*name] = json!(array);
+            }
+        }
+
+        if !self.textures.is_empty() {
+            root["samplers"] = json!([{ "wrapS": 10497, "wrapT": 10497 }]);
+        }
+
+        if !self.bin.is_empty() {
+            root["buffers"] = json!([{ "byteLength": self.bin.len() }]);
+        }
+
+        let mut extensions_used = self.extensions_used.clone();
+
+        if !self.lights.is_empty() {
+            extensions_used.push("KHR_lights_punctual".to_owned());
+            root["extensions"] = json!({
+                "KHR_lights_punctual": { "lights": self.lights },
+            });
+        }
+
+        if !extensions_used.is_empty() {
+            root["extensionsUsed"] = json!(extensions_used);
+        }
+
+        let json_data = serde_json::to_vec(&root).unwrap();
+
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                // Calculated when written.
+                length: 0,
+            },
+            json: std::borrow::Cow::Owned(json_data),
+            bin: if self.bin.is_empty() {
+                None
+            } else {
+                Some(std::borrow::Cow::Borrowed(&self.bin))
+            },
+        };
+
+        glb.to_vec().unwrap()
+    }
+}
+
+// Writes every entity with a `Pose` and a `Renderable` or `Light` to a binary glTF file. Only
+// renderables with PBR or unlit materials are written, so the skybox is left out. Meshes and
+// textures are read back from the GPU, stalling it. Skins and morph targets aren't written, so
+// scenes with them fail to export rather than losing their animation.
+pub fn export_glb(world: &World) -> Result<Vec<u8>, String> {
+    let (render_state, poses, renderables, lights): (
+        ReadExpect<RenderState>,
+        ReadStorage<Pose>,
+        ReadStorage<Renderable>,
+        ReadStorage<Light>,
+    ) = world.system_data();
+
+    let device = &render_state.device;
+    let queue = &render_state.queue;
+
+    let mut builder = GlbBuilder::default();
+
+    for (pose, renderable, light) in (&poses, renderables.maybe(), lights.maybe()).join() {
+        let mut mesh = None;
+
+        if let Some(renderable) = renderable {
+            if renderable.skin.is_some() {
+                return Err("Skinned meshes can't be exported".to_owned());
+            }
+
+            if let Some(material) = builder.push_material(device, queue, &renderable.material)? {
+                mesh = Some(builder.push_mesh(device, queue, renderable, material)?);
+            }
+        }
+
+        if mesh.is_none() && light.is_none() {
+            continue;
+        }
+
+        // glTF matrices are column major, as nalgebra stores them.
+        let mut node = json!({
+            "matrix": pose.model_matrix.matrix().as_slice(),
+        });
+
+        if let Some(mesh) = mesh {
+            node["mesh"] = json!(mesh);
+        }
+
+        if let Some(light) = light {
+            node["extensions"] = json!({
+                "KHR_lights_punctual": { "light": builder.push_light(light) },
+            });
+        }
+
+        builder.nodes.push(node);
+    }
+
+    Ok(builder.to_glb())
+}
+
+pub fn export_glb_to_path(world: &World, path: &std::path::Path) -> Result<(), String> {
+    std::fs::write(path, export_glb(world)?).map_err(|err| err.to_string())
+}
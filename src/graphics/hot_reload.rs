@@ -0,0 +1,202 @@
+use super::*;
+use specs::prelude::*;
+use std::path::{Path, PathBuf};
+
+enum WatchedFile {
+    Model(Entity),
+    Skybox(Entity),
+}
+
+// Watches the files assets were loaded from, reloading them when they change on disk. Models and
+// skyboxes reload into the entities they were loaded into, and edited shaders rebuild the
+// pipelines of every material using them.
+pub struct HotReloader {
+    watcher: notify::RecommendedWatcher,
+    receiver: std::sync::mpsc::Receiver<notify::DebouncedEvent>,
+    files: Vec<(PathBuf, WatchedFile)>,
+    shader_directory: Option<PathBuf>,
+}
+
+impl HotReloader {
+    pub fn new() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        // Editors often write a file in several steps, so events are debounced.
+        let watcher = notify::watcher(sender, std::time::Duration::from_millis(200)).unwrap();
+
+        Self {
+            watcher,
+            receiver,
+            files: Vec::new(),
+            shader_directory: None,
+        }
+    }
+
+    pub fn watch_model(&mut self, path: &Path, entity: Entity) {
+        self.watch_file(path, WatchedFile::Model(entity));
+    }
+
+    pub fn watch_skybox(&mut self, path: &Path, entity: Entity) {
+        self.watch_file(path, WatchedFile::Skybox(entity));
+    }
+
+    // Reloads shaders from a directory of sources named like the built-in shaders.
+    pub fn watch_shaders(&mut self, directory: &Path) {
+        let directory = directory
+            .canonicalize()
+            .unwrap_or_else(|_| directory.to_owned());
+
+        match self.watch_directory(&directory) {
+            Ok(()) => self.shader_directory = Some(directory),
+            Err(err) => println!("Failed to watch {:?}: {}", directory, err),
+        }
+    }
+
+    fn watch_file(&mut self, path: &Path, watched_file: WatchedFile) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+
+        // Files are replaced rather than written to by some editors and exporters, so the
+        // directory containing the file is watched.
+        let directory = path.parent().unwrap_or_else(|| Path::new("./")).to_owned();
+
+        match self.watch_directory(&directory) {
+            Ok(()) => self.files.push((path, watched_file)),
+            Err(err) => println!("Failed to watch {:?}: {}", path, err),
+        }
+    }
+
+    fn watch_directory(&mut self, directory: &Path) -> Result<(), notify::Error> {
+        use notify::Watcher;
+
+        self.watcher
+            .watch(directory, notify::RecursiveMode::NonRecursive)
+    }
+
+    // Reloads the files changed since the last update.
+    pub fn update(&mut self, world: &mut World) {
+        let mut changed_paths = Vec::new();
+
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                notify::DebouncedEvent::Write(path)
+                | notify::DebouncedEvent::Create(path)
+                | notify::DebouncedEvent::Rename(_, path)
+                    if !changed_paths.contains(&path) =>
+                {
+                    changed_paths.push(path);
+                }
+                notify::DebouncedEvent::Error(err, path) => {
+                    println!("Failed to watch {:?}: {}", path, err);
+                }
+                _ => {}
+            }
+        }
+
+        for path in changed_paths {
+            let path = path.canonicalize().unwrap_or(path);
+
+            if self.shader_directory.as_deref() == path.parent() {
+                reload_shader(world, &path);
+            }
+
+            for (file_path, watched_file) in &self.files {
+                if *file_path != path {
+                    continue;
+                }
+
+                match *watched_file {
+                    WatchedFile::Model(entity) => {
+                        println!("Reloading {:?}", path);
+
+                        reload_model_async(world, entity, &path);
+                    }
+                    WatchedFile::Skybox(entity) => {
+                        println!("Reloading {:?}", path);
+
+                        reload_skybox_async(world, entity, &path);
+
+                        // Materials bind the lighting maps baked from the skybox, so every model
+                        // is reloaded to be lit by the new one.
+                        for (model_path, watched_model) in &self.files {
+                            if let WatchedFile::Model(model_entity) = *watched_model {
+                                reload_model_async(world, model_entity, model_path);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for HotReloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Rebuilds the pipelines of every material using the shader. If any fail to compile, the error is
+// printed and the previous pipelines are kept.
+fn reload_shader(world: &mut World, path: &Path) {
+    let shader_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if shaders::is_shader(name) => name,
+        _ => return,
+    };
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            println!("Failed to read shader {:?}: {}", path, err);
+            return;
+        }
+    };
+
+    let (render_state, renderables): (ReadExpect<RenderState>, ReadStorage<Renderable>) =
+        world.system_data();
+
+    // Materials are shared between renderables, so each is rebuilt once.
+    let mut material_pipelines: Vec<&MaterialPipelines> = Vec::new();
+
+    for renderable in renderables.join() {
+        let pipelines = renderable.material.pipelines();
+
+        if pipelines.uses_shader(shader_name)
+            && !material_pipelines
+                .iter()
+                .any(|other| std::ptr::eq(*other, pipelines))
+        {
+            material_pipelines.push(pipelines);
+        }
+    }
+
+    let rebuilt_pipelines = material_pipelines
+        .iter()
+        .map(|pipelines| {
+            pipelines.rebuild(
+                &render_state.device,
+                &render_state.pipeline_cache,
+                shader_name,
+                &source,
+            )
+        })
+        .collect::<Result<Vec<RenderPipelines>, String>>();
+
+    match rebuilt_pipelines {
+        Ok(rebuilt_pipelines) => {
+            for (pipelines, render_pipelines) in material_pipelines.iter().zip(rebuilt_pipelines) {
+                pipelines.replace(render_pipelines);
+            }
+
+            // Materials created from now on use the new source too.
+            shaders::set_shader_source(shader_name, source);
+
+            println!("Reloaded shader {}", shader_name);
+        }
+        Err(err) => {
+            println!(
+                "Failed to compile shader {}, keeping the previous version:\n{}",
+                shader_name, err
+            );
+        }
+    }
+}
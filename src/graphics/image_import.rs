@@ -0,0 +1,402 @@
+use super::*;
+use std::borrow::Cow;
+use std::io::Read;
+
+const KTX2_IDENTIFIER: &[u8] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+// Decoded 8-bit pixels, with every mip level stored in the image's container, largest first.
+// Images have one, two or four channels.
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    pub mip_levels: Vec<Vec<u8>>,
+}
+
+impl ImageData {
+    // A single white pixel, used in place of images that couldn't be decoded.
+    pub fn placeholder() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            channels: 4,
+            mip_levels: vec![vec![255; 4]],
+        }
+    }
+
+    // The format and mip levels to upload for a texture wanted in an RGBA format. One and two
+    // channel images are kept as they are in linear textures, which sample their missing
+    // channels the same as the expanded image would. There are no one or two channel sRGB
+    // formats, so those images are expanded to RGBA.
+    pub fn texture_levels(
+        &self,
+        image_format: wgpu::TextureFormat,
+    ) -> (wgpu::TextureFormat, Cow<'_, [Vec<u8>]>) {
+        match (self.channels, image_format) {
+            (1, wgpu::TextureFormat::Rgba8Unorm) => (
+                wgpu::TextureFormat::R8Unorm,
+                Cow::Borrowed(&self.mip_levels),
+            ),
+            (2, wgpu::TextureFormat::Rgba8Unorm) => (
+                wgpu::TextureFormat::Rg8Unorm,
+                Cow::Borrowed(&self.mip_levels),
+            ),
+            (4, _) => (image_format, Cow::Borrowed(&self.mip_levels)),
+            (channels, _) => (
+                image_format,
+                Cow::Owned(
+                    self.mip_levels
+                        .iter()
+                        .map(|level| expand_to_rgba(level, channels, false))
+                        .collect(),
+                ),
+            ),
+        }
+    }
+}
+
+// Fills in missing channels with zero and alpha with 255, and swaps blue and red for BGR data.
+pub fn expand_to_rgba(data: &[u8], channels: usize, is_bgr: bool) -> Vec<u8> {
+    let mut rgba_data = Vec::with_capacity(data.len() / channels * 4);
+
+    for pixel in data.chunks_exact(channels) {
+        let mut rgba = [0, 0, 0, 255];
+        rgba[..channels].copy_from_slice(pixel);
+
+        if is_bgr {
+            rgba.swap(0, 2);
+        }
+
+        rgba_data.extend_from_slice(&rgba);
+    }
+
+    rgba_data
+}
+
+// Everything read from a glTF file before it's uploaded to the GPU. Nothing here touches the
+// device, so files can be imported on a worker thread.
+pub struct GltfImport {
+    // Identifies the file in an `AssetServer`, from its path or contents.
+    pub key: u64,
+    pub document: gltf::Document,
+    pub buffers: Vec<gltf::buffer::Data>,
+    pub images: Vec<Option<ImageData>>,
+    pub extensions: GltfExtensions,
+    // The primitives drawn at each level of detail, from most to least detailed.
+    pub lods: Vec<LodData>,
+}
+
+// Like `gltf::import`, but also decodes KTX2 images. Images that can't be decoded are left out
+// rather than failing the import, so textures can fall back to another source.
+pub fn import_gltf(path: &std::path::Path) -> Result<GltfImport, String> {
+    // The file is read once, for the document and for the extensions the glTF crate drops.
+    let data = std::fs::read(path).map_err(|err| err.to_string())?;
+    let gltf::Gltf { document, blob } =
+        gltf::Gltf::from_slice(&data).map_err(|err| err.to_string())?;
+
+    let base = path.parent().unwrap_or_else(|| std::path::Path::new("./"));
+
+    let buffers = import_buffers(&document, Some(base), blob)?;
+    let images = import_images(&document, Some(base), &buffers);
+    let extensions = GltfExtensions::new_from_slice(&data);
+    let lods = lod::import_lods(&document, &extensions, import_meshes(&document, &buffers)?);
+
+    Ok(GltfImport {
+        key: AssetServer::path_key(path),
+        document,
+        buffers,
+        images,
+        extensions,
+        lods,
+    })
+}
+
+fn import_buffers(
+    document: &gltf::Document,
+    base: Option<&std::path::Path>,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>, String> {
+    document
+        .buffers()
+        .map(|buffer| {
+            let mut data = match buffer.source() {
+                gltf::buffer::Source::Bin => blob
+                    .take()
+                    .ok_or_else(|| "Missing binary glTF chunk".to_owned())?,
+                gltf::buffer::Source::Uri(uri) => read_uri(base, uri)?,
+            };
+
+            if data.len() < buffer.length() {
+                return Err(format!("Buffer {} is too short", buffer.index()));
+            }
+
+            // Accessors may read up to 4 bytes at a time.
+            while data.len() % 4 != 0 {
+                data.push(0);
+            }
+
+            Ok(gltf::buffer::Data(data))
+        })
+        .collect()
+}
+
+fn import_images(
+    document: &gltf::Document,
+    base: Option<&std::path::Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<Option<ImageData>> {
+    document
+        .images()
+        .map(|image| {
+            let encoded_image = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    let buffer = &buffers[view.buffer().index()];
+
+                    Ok(buffer[view.offset()..view.offset() + view.length()].to_vec())
+                }
+                gltf::image::Source::Uri { uri, .. } => read_uri(base, uri),
+            };
+
+            match encoded_image.and_then(|data| decode_image(&data)) {
+                Ok(image_data) => Some(image_data),
+                Err(err) => {
+                    println!("Failed to load image {}: {}", image.index(), err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Every primitive becomes its own mesh, as each has its own vertices and topology.
+fn import_meshes(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<Vec<MeshData>>, String> {
+    document
+        .meshes()
+        .map(|mesh| {
+            mesh.primitives()
+                .map(|primitive| {
+                    Renderable::read_primitive(&primitive, buffers).map_err(|err| {
+                        format!(
+                            "Failed to read primitive {} of mesh {}: {}",
+                            primitive.index(),
+                            mesh.index(),
+                            err
+                        )
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn read_uri(base: Option<&std::path::Path>, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data_uri) = uri.strip_prefix("data:") {
+        let (_, data) = data_uri
+            .split_once(";base64,")
+            .ok_or_else(|| "Unsupported data URI".to_owned())?;
+
+        return base64::decode(data).map_err(|err| err.to_string());
+    }
+
+    match base {
+        Some(base) => std::fs::read(base.join(uri)).map_err(|err| err.to_string()),
+        None => Err("External file referenced from a binary glTF slice".to_owned()),
+    }
+}
+
+// Decodes a KTX2 image or any format supported by the `image` crate.
+pub fn decode_image(data: &[u8]) -> Result<ImageData, String> {
+    if data.starts_with(KTX2_IDENTIFIER) {
+        return read_ktx2(data);
+    }
+
+    let image = image::load_from_memory(data)
+        .map_err(|err| err.to_string())?
+        .into_rgba();
+
+    Ok(ImageData {
+        width: image.width(),
+        height: image.height(),
+        channels: 4,
+        mip_levels: vec![image.into_raw()],
+    })
+}
+
+// Reads the mip levels of a KTX2 image in an 8-bit format. One and two channel formats are kept
+// as they are, and three channel and BGR formats expanded to RGBA, which wgpu can sample. Basis
+// Universal payloads are transcoded to RGBA.
+pub fn read_ktx2(data: &[u8]) -> Result<ImageData, String> {
+    let reader = ktx2::Reader::new(data).map_err(|err| format!("Invalid KTX2 data: {:?}", err))?;
+
+    let header = reader.header();
+
+    let width = header.pixel_width;
+    let height = header.pixel_height.max(1);
+
+    if header.supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ) {
+        return read_basis_lz(&reader);
+    }
+
+    // UASTC textures have no Vulkan format, and are told apart by their data format descriptor.
+    let format = match header.format {
+        Some(format) => format,
+        None if is_uastc(&reader) => {
+            let mip_levels = reader
+                .levels()
+                .enumerate()
+                .map(|(level, level_data)| {
+                    let level_data = ktx2_level_data(&header, level_data)?;
+
+                    basis::decode_uastc(
+                        &level_data,
+                        (width >> level).max(1),
+                        (height >> level).max(1),
+                    )
+                })
+                .collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+            return ktx2_image(width, height, 4, mip_levels);
+        }
+        None => return Err("KTX2 image has no format".to_owned()),
+    };
+
+    let (channels, is_bgr) = match format {
+        ktx2::Format::R8_UNORM | ktx2::Format::R8_SRGB => (1, false),
+        ktx2::Format::R8G8_UNORM | ktx2::Format::R8G8_SRGB => (2, false),
+        ktx2::Format::R8G8B8_UNORM | ktx2::Format::R8G8B8_SRGB => (3, false),
+        ktx2::Format::B8G8R8_UNORM | ktx2::Format::B8G8R8_SRGB => (3, true),
+        ktx2::Format::R8G8B8A8_UNORM | ktx2::Format::R8G8B8A8_SRGB => (4, false),
+        ktx2::Format::B8G8R8A8_UNORM | ktx2::Format::B8G8R8A8_SRGB => (4, true),
+        _ => return Err(format!("Unsupported KTX2 format {:?}", format)),
+    };
+
+    let mip_levels = reader
+        .levels()
+        .enumerate()
+        .map(|(level, level_data)| {
+            let level_data = ktx2_level_data(&header, level_data)?;
+
+            let num_pixels = ((width >> level).max(1) * (height >> level).max(1)) as usize;
+
+            if level_data.len() < num_pixels * channels {
+                return Err(format!("KTX2 mip level {} is truncated", level));
+            }
+
+            // Only the first layer and face are used.
+            let pixels = &level_data[..num_pixels * channels];
+
+            if channels >= 3 {
+                Ok(expand_to_rgba(pixels, channels, is_bgr))
+            } else {
+                Ok(pixels.to_vec())
+            }
+        })
+        .collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+    let channels = if channels >= 3 { 4 } else { channels };
+
+    ktx2_image(width, height, channels, mip_levels)
+}
+
+fn ktx2_image(
+    width: u32,
+    height: u32,
+    channels: usize,
+    mip_levels: Vec<Vec<u8>>,
+) -> Result<ImageData, String> {
+    if mip_levels.is_empty() {
+        return Err("KTX2 image has no mip levels".to_owned());
+    }
+
+    Ok(ImageData {
+        width,
+        height,
+        channels,
+        mip_levels,
+    })
+}
+
+// A mip level's data, decompressed if the KTX2 file is supercompressed with Zstandard.
+fn ktx2_level_data<'a>(
+    header: &ktx2::Header,
+    level_data: &'a [u8],
+) -> Result<Cow<'a, [u8]>, String> {
+    match header.supercompression_scheme {
+        None => Ok(Cow::Borrowed(level_data)),
+        Some(ktx2::SupercompressionScheme::Zstandard) => {
+            let mut source = level_data;
+            let mut decoder = ruzstd::StreamingDecoder::new(&mut source)?;
+
+            let mut decompressed = Vec::new();
+            decoder
+                .read_to_end(&mut decompressed)
+                .map_err(|err| err.to_string())?;
+
+            Ok(Cow::Owned(decompressed))
+        }
+        Some(scheme) => Err(format!("Unsupported KTX2 supercompression {:?}", scheme)),
+    }
+}
+
+fn is_uastc(reader: &ktx2::Reader<&[u8]>) -> bool {
+    reader.data_format_descriptors().any(|descriptor| {
+        descriptor.header == ktx2::DataFormatDescriptorHeader::BASIC
+            && ktx2::BasicDataFormatDescriptor::parse(descriptor.data)
+                .map(|descriptor| descriptor.color_model == Some(ktx2::ColorModel::UASTC))
+                .unwrap_or(false)
+    })
+}
+
+// BasisLZ files store ETC1S slices, with the codebooks they share in the supercompression global
+// data. Only the first layer, face and depth slice of each level is used.
+fn read_basis_lz(reader: &ktx2::Reader<&[u8]>) -> Result<ImageData, String> {
+    let header = reader.header();
+
+    let width = header.pixel_width;
+    let height = header.pixel_height.max(1);
+
+    let level_images = |level: u32| {
+        (header.layer_count.max(1) * header.face_count * (header.pixel_depth >> level).max(1))
+            as usize
+    };
+
+    let image_count = (0..header.level_count.max(1)).map(level_images).sum();
+    let global_data =
+        basis::BasisLzGlobalData::new(reader.supercompression_global_data(), image_count)?;
+
+    let mut first_image = 0;
+
+    let mip_levels = reader
+        .levels()
+        .enumerate()
+        .map(|(level, level_data)| {
+            let image = first_image;
+            first_image += level_images(level as u32);
+
+            global_data.decode_image(
+                image,
+                level_data,
+                (width >> level).max(1),
+                (height >> level).max(1),
+            )
+        })
+        .collect::<Result<Vec<Vec<u8>>, String>>()?;
+
+    ktx2_image(width, height, 4, mip_levels)
+}
+
+// The image a glTF texture samples, preferring its KHR_texture_basisu source when it could be
+// decoded.
+pub fn texture_image<'a>(
+    texture: &gltf::Texture,
+    extensions: &GltfExtensions,
+    images: &'a [Option<ImageData>],
+) -> Option<&'a ImageData> {
+    extensions
+        .texture_basisu_source(texture.index())
+        .and_then(|index| images.get(index)?.as_ref())
+        .or_else(|| images.get(texture.source().index())?.as_ref())
+}
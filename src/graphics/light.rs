@@ -1,9 +1,64 @@
 use specs::prelude::*;
 
-pub struct Light;
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightType {
+    Directional,
+    Point,
+    // Cone angles are in radians from the light's direction.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// Lights shine along their pose's -Z axis, as in glTF.
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub name: Option<String>,
+    pub light_type: LightType,
+    pub colour: [f32; 3],
+    // Candela for point and spot lights, lux for directional lights.
+    pub intensity: f32,
+    // Point and spot lights have no cutoff without a range.
+    pub range: Option<f32>,
+}
 
 impl Component for Light {
     type Storage = VecStorage<Self>;
 }
 
-impl Light {}
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            name: None,
+            light_type: LightType::Point,
+            colour: [1.0, 0.908, 0.886],
+            intensity: 23.47,
+            range: None,
+        }
+    }
+}
+
+impl Light {
+    pub fn new_from_gltf(gltf_light: &gltf::khr_lights_punctual::Light) -> Self {
+        Self {
+            name: gltf_light.name().map(str::to_owned),
+            light_type: match gltf_light.kind() {
+                gltf::khr_lights_punctual::Kind::Directional => LightType::Directional,
+                gltf::khr_lights_punctual::Kind::Point => LightType::Point,
+                gltf::khr_lights_punctual::Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => LightType::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                },
+            },
+            colour: gltf_light.color(),
+            intensity: gltf_light.intensity(),
+            range: gltf_light.range(),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::*;
+use nalgebra::Point3;
+
+// How far past a level's minimum screen size, as a fraction of it, a renderable's size has to
+// move before the level changes, so objects sitting at a threshold don't flicker between levels.
+const LOD_HYSTERESIS: f32 = 0.1;
+
+// Levels generated for files without MSFT_lod, each with about half the triangles of the last.
+const MAX_GENERATED_LODS: usize = 3;
+
+// Meshes with fewer triangles are cheap enough to draw at any distance.
+const MIN_SIMPLIFIED_TRIANGLES: usize = 512;
+
+// The meshes drawn while a renderable's projected size is at least the minimum screen size, as a
+// fraction of the viewport's height.
+pub struct Lod {
+    pub meshes: Vec<Mesh>,
+    pub min_screen_size: f32,
+}
+
+impl Lod {
+    // A level drawn at any size.
+    pub fn new(meshes: Vec<Mesh>) -> Self {
+        Self {
+            meshes,
+            min_screen_size: 0.0,
+        }
+    }
+}
+
+// A level's meshes before they're uploaded.
+pub struct LodData {
+    pub meshes: Vec<MeshData>,
+    pub min_screen_size: f32,
+}
+
+// Levels without a screen size of their own halve it from a fifth of the viewport.
+pub fn default_min_screen_size(level: usize) -> f32 {
+    0.2 * 0.5f32.powi(level as i32)
+}
+
+// The height of a bounding sphere on screen, as a fraction of the viewport's height.
+pub fn projected_screen_size(camera: &Camera, centre: &Point3<f32>, radius: f32) -> f32 {
+    // The projection scales view space heights by 1 / tan(fov / 2), or 1 / half the height for
+    // orthographic cameras.
+    let vertical_scale = camera.proj_matrix.to_homogeneous()[(1, 1)];
+
+    match camera.proj_matrix {
+        Projection::Perspective(_) => {
+            let distance = (camera.view_matrix * centre).coords.norm();
+
+            if distance <= radius {
+                return f32::INFINITY;
+            }
+
+            radius * vertical_scale / distance
+        }
+        Projection::Orthographic(_) => radius * vertical_scale,
+    }
+}
+
+// Picks the most detailed level the screen size reaches, falling back to the last level. The
+// previous frame's level is kept until the size moves past a threshold by the hysteresis band.
+pub fn select_lod(lods: &[Lod], screen_size: f32, previous_level: Option<usize>) -> usize {
+    let level = |threshold_scale: f32| {
+        lods.iter()
+            .position(|lod| screen_size >= lod.min_screen_size * threshold_scale)
+            .unwrap_or_else(|| lods.len().saturating_sub(1))
+    };
+
+    match previous_level {
+        Some(previous_level) => {
+            previous_level.clamp(level(1.0 - LOD_HYSTERESIS), level(1.0 + LOD_HYSTERESIS))
+        }
+        None => level(1.0),
+    }
+}
+
+// Reads the levels of detail of a glTF file. The mesh of the first node with MSFT_lod is replaced
+// by its levels' meshes, and the file's other meshes are drawn at every level. Files without
+// MSFT_lod have levels generated by simplifying their triangles.
+pub fn import_lods(
+    document: &gltf::Document,
+    extensions: &GltfExtensions,
+    meshes: Vec<Vec<MeshData>>,
+) -> Vec<LodData> {
+    let lod_node = document.nodes().find_map(|node| {
+        let node_lod = extensions.node_lod(node.index())?;
+
+        Some((node.mesh()?.index(), node_lod))
+    });
+
+    let (base_mesh, node_lod) = match lod_node {
+        Some(lod_node) => lod_node,
+        None => {
+            return generate_lods(LodData {
+                meshes: meshes.into_iter().flatten().collect(),
+                min_screen_size: default_min_screen_size(0),
+            })
+        }
+    };
+
+    // The mesh drawn in place of the node's own at each level.
+    let level_meshes: Vec<usize> = std::iter::once(base_mesh)
+        .chain(
+            node_lod
+                .ids
+                .iter()
+                .filter_map(|&id| document.nodes().nth(id)?.mesh())
+                .map(|mesh| mesh.index()),
+        )
+        .collect();
+
+    level_meshes
+        .iter()
+        .enumerate()
+        .map(|(level, &level_mesh)| LodData {
+            meshes: (0..meshes.len())
+                .filter_map(|index| {
+                    if index == base_mesh {
+                        Some(&meshes[level_mesh])
+                    } else if level_meshes.contains(&index) {
+                        None
+                    } else {
+                        Some(&meshes[index])
+                    }
+                })
+                .flatten()
+                .cloned()
+                .collect(),
+            min_screen_size: node_lod
+                .screen_coverages
+                .get(level)
+                .copied()
+                .unwrap_or_else(|| default_min_screen_size(level)),
+        })
+        .collect()
+}
+
+// Appends levels simplified from the base level, stopping once the meshes are small or
+// simplification can't remove enough triangles to be worth another level.
+fn generate_lods(base: LodData) -> Vec<LodData> {
+    let triangle_count = |lod: &LodData| -> usize {
+        lod.meshes
+            .iter()
+            .filter(|mesh| mesh.primitive_topology == wgpu::PrimitiveTopology::TriangleList)
+            .map(|mesh| mesh.indices.len() / 3)
+            .sum()
+    };
+
+    let mut lods = vec![base];
+
+    while lods.len() <= MAX_GENERATED_LODS {
+        let last_lod = lods.last().unwrap();
+        let last_triangle_count = triangle_count(last_lod);
+
+        if last_triangle_count < MIN_SIMPLIFIED_TRIANGLES {
+            break;
+        }
+
+        let lod = LodData {
+            meshes: last_lod
+                .meshes
+                .iter()
+                .map(|mesh| simplify_mesh_data(mesh, 0.5))
+                .collect(),
+            min_screen_size: default_min_screen_size(lods.len()),
+        };
+
+        if triangle_count(&lod) * 4 > last_triangle_count * 3 {
+            break;
+        }
+
+        lods.push(lod);
+    }
+
+    lods
+}
+
+// Simplifies a triangle list to about the fraction of its indices, dropping the vertices no
+// longer used. Lines and points are kept as they are.
+fn simplify_mesh_data(mesh_data: &MeshData, index_ratio: f32) -> MeshData {
+    if mesh_data.primitive_topology != wgpu::PrimitiveTopology::TriangleList {
+        return mesh_data.clone();
+    }
+
+    let indices = mesh_processing::simplify(
+        &mesh_data.vertices,
+        &mesh_data.indices,
+        (mesh_data.indices.len() as f32 * index_ratio) as usize,
+    );
+
+    let mut is_used = vec![false; mesh_data.vertices.len()];
+
+    for &index in indices.iter() {
+        is_used[index as usize] = true;
+    }
+
+    // The vertices still in use keep their order.
+    let kept_vertices: Vec<usize> = (0..is_used.len()).filter(|&v| is_used[v]).collect();
+
+    let mut remap = vec![0; is_used.len()];
+
+    for (new_index, &vertex) in kept_vertices.iter().enumerate() {
+        remap[vertex] = new_index as u32;
+    }
+
+    let keep = |values: &[[f32; 3]]| -> Vec<[f32; 3]> {
+        kept_vertices
+            .iter()
+            .filter_map(|&vertex| values.get(vertex).copied())
+            .collect()
+    };
+
+    MeshData {
+        vertices: kept_vertices
+            .iter()
+            .map(|&vertex| mesh_data.vertices[vertex])
+            .collect(),
+        indices: indices.iter().map(|&index| remap[index as usize]).collect(),
+        primitive_topology: mesh_data.primitive_topology,
+        morph_targets: mesh_data
+            .morph_targets
+            .iter()
+            .map(|target| MorphTarget {
+                positions: keep(&target.positions),
+                normals: keep(&target.normals),
+                tangents: keep(&target.tangents),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lods(min_screen_sizes: &[f32]) -> Vec<Lod> {
+        min_screen_sizes
+            .iter()
+            .map(|&min_screen_size| Lod {
+                meshes: Vec::new(),
+                min_screen_size,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_level_by_screen_size() {
+        let lods = lods(&[0.2, 0.1, 0.05]);
+
+        assert_eq!(select_lod(&lods, 0.5, None), 0);
+        assert_eq!(select_lod(&lods, 0.2, None), 0);
+        assert_eq!(select_lod(&lods, 0.19, None), 1);
+        assert_eq!(select_lod(&lods, 0.1, None), 1);
+        assert_eq!(select_lod(&lods, 0.09, None), 2);
+        assert_eq!(select_lod(&lods, 0.05, None), 2);
+
+        // Anything smaller than the last level's minimum still draws the last level.
+        assert_eq!(select_lod(&lods, 0.01, None), 2);
+        assert_eq!(select_lod(&lods, 0.0, None), 2);
+    }
+
+    #[test]
+    fn keeps_level_within_hysteresis() {
+        let lods = lods(&[0.2, 0.1, 0.05]);
+
+        // Growing past the threshold between levels 0 and 1 switches only beyond 10% above it.
+        assert_eq!(select_lod(&lods, 0.21, Some(1)), 1);
+        assert_eq!(select_lod(&lods, 0.219, Some(1)), 1);
+        assert_eq!(select_lod(&lods, 0.221, Some(1)), 0);
+
+        // Shrinking below it switches only beyond 10% below it.
+        assert_eq!(select_lod(&lods, 0.19, Some(0)), 0);
+        assert_eq!(select_lod(&lods, 0.181, Some(0)), 0);
+        assert_eq!(select_lod(&lods, 0.179, Some(0)), 1);
+
+        // Large changes skip levels.
+        assert_eq!(select_lod(&lods, 0.5, Some(2)), 0);
+        assert_eq!(select_lod(&lods, 0.01, Some(0)), 2);
+
+        // Levels that no longer exist are clamped to the available ones.
+        assert_eq!(select_lod(&lods, 0.01, Some(5)), 2);
+    }
+}
@@ -1,81 +1,613 @@
 use super::*;
 
 pub trait MaterialBase {
-    fn begin_render_pass<'a>(
+    fn pipelines(&self) -> &MaterialPipelines;
+
+    // Sets the frame's and material's bind groups for drawing an object, whose uniforms are at
+    // the offsets in the frame's object buffer. The pipeline is set per mesh, by its primitive
+    // topology.
+    fn set_bind_groups<'a>(
         &'a self,
-        device: &wgpu::Device,
-        encoder: &'a mut wgpu::CommandEncoder,
-        rp_desc: &'a wgpu::RenderPassDescriptor,
-        transform_data: &TransformBindGroup,
-        lighting_data: &LightingBindGroup,
-    ) -> wgpu::RenderPass<'a>;
+        render_pass: &mut wgpu::RenderPass<'a>,
+        frame_bind_group: &'a FrameBindGroup,
+        offsets: &ObjectOffsets,
+    );
+
+    // Only PBR and unlit materials can be exported.
+    fn pbr_properties(&self) -> Option<&PbrProperties> {
+        None
+    }
+
+    fn unlit_properties(&self) -> Option<&UnlitProperties> {
+        None
+    }
 }
 
-pub struct TransformBindGroup {
-    pub model_matrix: nalgebra::Matrix4<f32>,
+// The camera, uploaded once per frame. Shaders find the camera's position from the view matrix.
+#[repr(C)]
+pub struct CameraBindGroup {
     pub view_matrix: nalgebra::Matrix4<f32>,
     pub proj_matrix: nalgebra::Matrix4<f32>,
-    pub camera_world_position: nalgebra::Vector3<f32>,
 }
 
-pub struct LightingBindGroup {
+// An instance's vertex data, read per instance from the frame's instance buffer.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct InstanceData {
+    pub model_matrix: nalgebra::Matrix4<f32>,
+}
+
+// Where an object's uniforms are in the frame's object buffer.
+#[derive(Copy, Clone)]
+pub struct ObjectOffsets {
+    pub joints: Option<wgpu::DynamicOffset>,
+    pub morph_weights: Option<wgpu::DynamicOffset>,
+}
+
+// The uniforms of every object drawn in a frame, each aligned so it can be bound by a dynamic
+// offset into one buffer.
+#[derive(Default)]
+pub struct ObjectUniforms {
+    pub data: Vec<u8>,
+}
+
+impl ObjectUniforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<T>(&mut self, uniform_data: &T) -> wgpu::DynamicOffset {
+        let offset = self.data.len();
+
+        self.data.extend_from_slice(as_bytes(uniform_data));
+
+        // The alignment is a power of two.
+        let alignment = wgpu::BIND_BUFFER_ALIGNMENT as usize;
+        let aligned_len = (self.data.len() + alignment - 1) & !(alignment - 1);
+
+        self.data.resize(aligned_len, 0);
+
+        offset as wgpu::DynamicOffset
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct LightData {
     pub position: nalgebra::Vector3<f32>,
-    pub _padding: u32,
+    pub light_type: u32,
+    pub direction: nalgebra::Vector3<f32>,
+    // Zero for lights without a range.
+    pub range: f32,
+    pub colour: nalgebra::Vector3<f32>,
+    pub intensity: f32,
+    pub inner_cone_cos: f32,
+    pub outer_cone_cos: f32,
+    pub _padding: [u32; 2],
+}
+
+impl LightData {
+    pub fn new(light: &Light, pose: &Pose) -> Self {
+        let (light_type, inner_cone_cos, outer_cone_cos) = match light.light_type {
+            LightType::Directional => (0, 1.0, 1.0),
+            LightType::Point => (1, -1.0, -1.0),
+            LightType::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (2, inner_cone_angle.cos(), outer_cone_angle.cos()),
+        };
+
+        Self {
+            position: pose.isometry().translation.vector,
+            light_type,
+            direction: pose.isometry().rotation * -nalgebra::Vector3::z(),
+            range: light.range.unwrap_or(0.0),
+            colour: light.colour.into(),
+            intensity: light.intensity,
+            inner_cone_cos,
+            outer_cone_cos,
+            _padding: [0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+pub struct LightingBindGroup {
+    pub lights: [LightData; MAX_LIGHTS],
+    pub num_lights: u32,
+    pub _padding: [u32; 3],
+}
+
+impl LightingBindGroup {
+    pub fn new(lights: &[LightData]) -> Self {
+        let mut light_data = [LightData {
+            position: nalgebra::Vector3::zeros(),
+            light_type: 0,
+            direction: nalgebra::Vector3::zeros(),
+            range: 0.0,
+            colour: nalgebra::Vector3::zeros(),
+            intensity: 0.0,
+            inner_cone_cos: 0.0,
+            outer_cone_cos: 0.0,
+            _padding: [0; 2],
+        }; MAX_LIGHTS];
+
+        let num_lights = lights.len().min(MAX_LIGHTS);
+
+        light_data[..num_lights].copy_from_slice(&lights[..num_lights]);
+
+        Self {
+            lights: light_data,
+            num_lights: num_lights as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// The object buffer starts large enough to bind the biggest uniforms at offset 0, as the joint
+// and morph weight bind groups are bound even when no object in the frame uses them.
+const MIN_OBJECT_BUFFER_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+const MIN_INSTANCE_BUFFER_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+// The camera and lights, bound to set 0 of every material so they're only uploaded once a frame,
+// and the frame's object and instance buffers. Objects' joints and morph weights are bound from
+// set 2 by dynamic offsets into the object buffer, and their transforms are read per instance
+// from vertex buffer 1.
+pub struct FrameBindGroup {
+    pub bind_group: wgpu::BindGroup,
+    pub camera_uniform_buffer: wgpu::Buffer,
+    pub lighting_uniform_buffer: wgpu::Buffer,
+    pub object_uniform_buffer: wgpu::Buffer,
+    pub instance_buffer: wgpu::Buffer,
+
+    object_buffer_size: wgpu::BufferAddress,
+    instance_buffer_size: wgpu::BufferAddress,
+    // Indexed by whether they bind joints, then morph weights.
+    object_bind_groups: Vec<wgpu::BindGroup>,
+
+    // Kept so materials created later share the layouts the bind groups were created with.
+    _bind_group_layouts: Vec<Handle<CachedBindGroupLayout>>,
+}
+
+impl FrameBindGroup {
+    pub fn new(device: &wgpu::Device, pipeline_cache: &PipelineCache) -> Self {
+        Self::new_with_buffer_sizes(
+            device,
+            pipeline_cache,
+            MIN_OBJECT_BUFFER_SIZE,
+            MIN_INSTANCE_BUFFER_SIZE,
+        )
+    }
+
+    fn new_with_buffer_sizes(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        object_buffer_size: wgpu::BufferAddress,
+        instance_buffer_size: wgpu::BufferAddress,
+    ) -> Self {
+        let camera_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera_uniform_buffer"),
+            size: std::mem::size_of::<CameraBindGroup>() as u64,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let lighting_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lighting_uniform_buffer"),
+            size: std::mem::size_of::<LightingBindGroup>() as u64,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let object_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("object_uniform_buffer"),
+            size: object_buffer_size,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let bind_group_layout = frame_bind_group_layout(device, pipeline_cache);
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout.layout,
+            bindings: &[
+                wgpu::Binding {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &camera_uniform_buffer,
+                        range: 0..std::mem::size_of::<CameraBindGroup>() as wgpu::BufferAddress,
+                    },
+                },
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer {
+                        buffer: &lighting_uniform_buffer,
+                        range: 0..std::mem::size_of::<LightingBindGroup>() as wgpu::BufferAddress,
+                    },
+                },
+            ],
+            label: Some("frame_bind_group"),
+        });
+
+        let mut bind_group_layouts = vec![bind_group_layout];
+        let mut object_bind_groups = Vec::new();
+
+        for &has_morph_targets in &[false, true] {
+            for &is_skinned in &[false, true] {
+                let layout =
+                    object_bind_group_layout(device, pipeline_cache, is_skinned, has_morph_targets);
+
+                let mut bindings = Vec::new();
+
+                if is_skinned {
+                    bindings.push(wgpu::Binding {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer {
+                            buffer: &object_uniform_buffer,
+                            range: 0..std::mem::size_of::<JointMatrices>() as wgpu::BufferAddress,
+                        },
+                    });
+                }
+
+                if has_morph_targets {
+                    bindings.push(wgpu::Binding {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Buffer {
+                            buffer: &object_uniform_buffer,
+                            range: 0..std::mem::size_of::<PackedMorphWeights>()
+                                as wgpu::BufferAddress,
+                        },
+                    });
+                }
+
+                object_bind_groups.push(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &layout.layout,
+                    bindings: bindings.as_slice(),
+                    label: Some("object_bind_group"),
+                }));
+
+                bind_group_layouts.push(layout);
+            }
+        }
+
+        Self {
+            bind_group,
+            camera_uniform_buffer,
+            lighting_uniform_buffer,
+            object_uniform_buffer,
+            instance_buffer: create_instance_buffer(device, instance_buffer_size),
+            object_buffer_size,
+            instance_buffer_size,
+            object_bind_groups,
+            _bind_group_layouts: bind_group_layouts,
+        }
+    }
+
+    // Uploads the frame's uniforms and instances, growing the object and instance buffers to fit
+    // them.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        upload_ring: &mut UploadRing,
+        camera_data: &CameraBindGroup,
+        lighting_data: &LightingBindGroup,
+        object_uniforms: &ObjectUniforms,
+        instances: &[InstanceData],
+    ) {
+        let object_data_size = object_uniforms.data.len() as wgpu::BufferAddress;
+
+        // The joint and morph weight bind groups are recreated along with the object buffer.
+        if object_data_size > self.object_buffer_size {
+            *self = Self::new_with_buffer_sizes(
+                device,
+                pipeline_cache,
+                object_data_size.next_power_of_two(),
+                self.instance_buffer_size,
+            );
+        }
+
+        let instance_data_size = std::mem::size_of_val(instances) as wgpu::BufferAddress;
+
+        if instance_data_size > self.instance_buffer_size {
+            self.instance_buffer_size = instance_data_size.next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.instance_buffer_size);
+        }
+
+        let instance_data = unsafe {
+            std::slice::from_raw_parts(instances.as_ptr() as *const u8, instance_data_size as usize)
+        };
+
+        upload_ring.upload(
+            device,
+            encoder,
+            &[
+                (&self.camera_uniform_buffer, as_bytes(camera_data)),
+                (&self.lighting_uniform_buffer, as_bytes(lighting_data)),
+                (&self.object_uniform_buffer, object_uniforms.data.as_slice()),
+                (&self.instance_buffer, instance_data),
+            ],
+        );
+    }
+
+    // The bind group for a material's joints and morph weights, with its dynamic offsets.
+    pub fn object_bind_group(
+        &self,
+        is_skinned: bool,
+        has_morph_targets: bool,
+        offsets: &ObjectOffsets,
+    ) -> (&wgpu::BindGroup, Vec<wgpu::DynamicOffset>) {
+        let mut dynamic_offsets = Vec::new();
+
+        if is_skinned {
+            dynamic_offsets.push(offsets.joints.unwrap_or(0));
+        }
+
+        if has_morph_targets {
+            dynamic_offsets.push(offsets.morph_weights.unwrap_or(0));
+        }
+
+        let index = is_skinned as usize + 2 * has_morph_targets as usize;
+
+        (&self.object_bind_groups[index], dynamic_offsets)
+    }
+}
+
+pub fn frame_bind_group_layout(
+    device: &wgpu::Device,
+    pipeline_cache: &PipelineCache,
+) -> Handle<CachedBindGroupLayout> {
+    pipeline_cache.bind_group_layout(
+        device,
+        &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            },
+        ],
+    )
+}
+
+fn create_instance_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("instance_buffer"),
+        size,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    })
+}
+
+// Joints at binding 0 and morph weights at binding 1, each only bound when used.
+pub fn object_bind_group_layout(
+    device: &wgpu::Device,
+    pipeline_cache: &PipelineCache,
+    is_skinned: bool,
+    has_morph_targets: bool,
+) -> Handle<CachedBindGroupLayout> {
+    let mut entries = Vec::new();
+
+    if is_skinned {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::VERTEX,
+            ty: wgpu::BindingType::UniformBuffer { dynamic: true },
+        });
+    }
+
+    if has_morph_targets {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::VERTEX,
+            ty: wgpu::BindingType::UniformBuffer { dynamic: true },
+        });
+    }
+
+    pipeline_cache.bind_group_layout(device, entries.as_slice())
+}
+
+// The per-object and per-mesh bind group layouts used by materials drawing `Mesh` vertices, with
+// joints and morph weights enabled for skinned and morphed meshes.
+pub struct VertexBindGroups {
+    pub object_bind_group_layout: Handle<CachedBindGroupLayout>,
+    pub morph_target_bind_group_layout: Handle<CachedBindGroupLayout>,
+    pub default_morph_target_bind_group: wgpu::BindGroup,
+
+    pub vertex_defines: shaders::ShaderDefines,
+}
+
+impl VertexBindGroups {
+    pub fn new(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        is_skinned: bool,
+        has_morph_targets: bool,
+    ) -> Self {
+        let mut vertex_defines = shaders::ShaderDefines::new();
+
+        if is_skinned {
+            vertex_defines.add("SKINNED");
+            vertex_defines.add_value("MAX_JOINTS", MAX_JOINTS);
+        }
+
+        if has_morph_targets {
+            vertex_defines.add("MORPH_TARGETS");
+            vertex_defines.add_value("MAX_MORPH_TARGETS", MAX_MORPH_TARGETS);
+        }
+
+        // Per-object bind group, bound from the frame's object buffer.
+        let object_bind_group_layout =
+            object_bind_group_layout(device, pipeline_cache, is_skinned, has_morph_targets);
+
+        // Per-mesh bind group. Meshes without their own morph targets use the default bind group.
+        let morph_target_bind_group_layout = pipeline_cache.bind_group_layout(
+            device,
+            if has_morph_targets {
+                &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::StorageBuffer {
+                        dynamic: false,
+                        readonly: true,
+                    },
+                }]
+            } else {
+                &[]
+            },
+        );
+
+        let default_morph_target_bind_group = if has_morph_targets {
+            let data = morph::pack_morph_targets(0, &[]);
+            let buffer = morph::create_morph_target_buffer(device, data.as_slice());
+
+            morph::create_morph_target_bind_group(
+                device,
+                &morph_target_bind_group_layout.layout,
+                &buffer,
+                std::mem::size_of_val(data.as_slice()) as wgpu::BufferAddress,
+            )
+        } else {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &morph_target_bind_group_layout.layout,
+                bindings: &[],
+                label: Some("morph_target_bind_group"),
+            })
+        };
+
+        Self {
+            object_bind_group_layout,
+            morph_target_bind_group_layout,
+            default_morph_target_bind_group,
+            vertex_defines,
+        }
+    }
+}
+
+// Attributes of `InstanceData`, after the vertex attributes. The model matrix is read as columns.
+pub fn instance_attributes() -> Vec<wgpu::VertexAttributeDescriptor> {
+    (0..4)
+        .map(|column| wgpu::VertexAttributeDescriptor {
+            offset: (std::mem::size_of::<f32>() * 4 * column) as wgpu::BufferAddress,
+            shader_location: 6 + column as u32,
+            format: wgpu::VertexFormat::Float4,
+        })
+        .collect()
+}
+
+// Attributes of `Vertex`, with joints and weights only read by skinned materials.
+pub fn vertex_attributes(is_skinned: bool) -> Vec<wgpu::VertexAttributeDescriptor> {
+    let mut vertex_attributes = vec![
+        wgpu::VertexAttributeDescriptor {
+            // Position
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float3,
+        },
+        wgpu::VertexAttributeDescriptor {
+            // Normal
+            offset: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float3,
+        },
+        wgpu::VertexAttributeDescriptor {
+            // Tangent
+            offset: (std::mem::size_of::<f32>() * 6) as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float4,
+        },
+        wgpu::VertexAttributeDescriptor {
+            // Tex Coord
+            offset: (std::mem::size_of::<f32>() * 10) as wgpu::BufferAddress,
+            shader_location: 3,
+            format: wgpu::VertexFormat::Float2,
+        },
+        wgpu::VertexAttributeDescriptor {
+            // Colour, after the instance attributes.
+            offset: (std::mem::size_of::<f32>() * 20) as wgpu::BufferAddress,
+            shader_location: 10,
+            format: wgpu::VertexFormat::Float4,
+        },
+    ];
+
+    if is_skinned {
+        vertex_attributes.extend_from_slice(&[
+            wgpu::VertexAttributeDescriptor {
+                // Joints
+                offset: (std::mem::size_of::<f32>() * 12) as wgpu::BufferAddress,
+                shader_location: 4,
+                format: wgpu::VertexFormat::Uint4,
+            },
+            wgpu::VertexAttributeDescriptor {
+                // Weights
+                offset: (std::mem::size_of::<f32>() * 16) as wgpu::BufferAddress,
+                shader_location: 5,
+                format: wgpu::VertexFormat::Float4,
+            },
+        ]);
+    }
+
+    vertex_attributes
 }
 
+// Builds a pipeline from shaders in the library without defines, panicking with the compile
+// errors if they don't compile.
+#[allow(clippy::too_many_arguments)]
 pub fn build_render_pipeline(
     device: &wgpu::Device,
-    vertex_shader_src: &str,
-    fragment_shader_src: &str,
+    vertex_shader: &str,
+    fragment_shader: &str,
     bind_group_layouts: &[&wgpu::BindGroupLayout],
     vertex_state_desc: wgpu::VertexStateDescriptor,
+    primitive_topology: wgpu::PrimitiveTopology,
     colour_states: &[wgpu::ColorStateDescriptor],
     depth_state: Option<wgpu::DepthStencilStateDescriptor>,
 ) -> wgpu::RenderPipeline {
-    // Init shaders.
-    let mut compiler = shaderc::Compiler::new().unwrap();
-    let options = shaderc::CompileOptions::new().unwrap();
-
-    let vs_spirv = compiler
-        .compile_into_spirv(
-            vertex_shader_src,
-            shaderc::ShaderKind::Vertex,
-            "vertex",
-            "main",
-            Some(&options),
-        )
-        .unwrap();
-    let fs_spirv = compiler
-        .compile_into_spirv(
-            fragment_shader_src,
-            shaderc::ShaderKind::Fragment,
-            "fragment",
-            "main",
-            Some(&options),
-        )
-        .unwrap();
-
-    let vs_buffer = std::io::Cursor::new(vs_spirv.as_binary_u8());
-    let fs_buffer = std::io::Cursor::new(fs_spirv.as_binary_u8());
+    let render_pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor { bind_group_layouts });
 
-    let vs_data = wgpu::read_spirv(vs_buffer).unwrap();
-    let fs_data = wgpu::read_spirv(fs_buffer).unwrap();
+    let defines = shaders::ShaderDefines::new();
 
-    let vs_module = device.create_shader_module(&vs_data);
-    let fs_module = device.create_shader_module(&fs_data);
+    let compile = |name, shader_kind| {
+        create_shader_module(device, name, shader_kind, &defines, None)
+            .unwrap_or_else(|err| panic!("{}", err))
+    };
 
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        bind_group_layouts: bind_group_layouts,
-    });
+    create_render_pipeline(
+        device,
+        &render_pipeline_layout,
+        &compile(vertex_shader, shaderc::ShaderKind::Vertex),
+        Some(&compile(fragment_shader, shaderc::ShaderKind::Fragment)),
+        vertex_state_desc,
+        primitive_topology,
+        colour_states,
+        depth_state,
+    )
+}
 
+// Pipelines without a fragment shader only write depth.
+#[allow(clippy::too_many_arguments)]
+pub fn create_render_pipeline(
+    device: &wgpu::Device,
+    render_pipeline_layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: Option<&wgpu::ShaderModule>,
+    vertex_state_desc: wgpu::VertexStateDescriptor,
+    primitive_topology: wgpu::PrimitiveTopology,
+    colour_states: &[wgpu::ColorStateDescriptor],
+    depth_state: Option<wgpu::DepthStencilStateDescriptor>,
+) -> wgpu::RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        layout: &render_pipeline_layout,
+        layout: render_pipeline_layout,
         vertex_stage: wgpu::ProgrammableStageDescriptor {
-            module: &vs_module,
+            module: vs_module,
             entry_point: "main",
         },
-        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-            module: &fs_module,
+        fragment_stage: fs_module.map(|fs_module| wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
             entry_point: "main",
         }),
         rasterization_state: Some(wgpu::RasterizationStateDescriptor {
@@ -85,7 +617,7 @@ pub fn build_render_pipeline(
             depth_bias_slope_scale: 0.0,
             depth_bias_clamp: 0.0,
         }),
-        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        primitive_topology,
         color_states: colour_states,
         depth_stencil_state: depth_state,
         vertex_state: vertex_state_desc,
@@ -95,6 +627,260 @@ pub fn build_render_pipeline(
     })
 }
 
+// Compiles a permutation of a shader in the library, reusing its SPIR-V if it's been compiled
+// before.
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    name: &str,
+    shader_kind: shaderc::ShaderKind,
+    defines: &shaders::ShaderDefines,
+    replaced: Option<(&str, &str)>,
+) -> Result<wgpu::ShaderModule, String> {
+    let spirv = shaders::compile_shader(name, shader_kind, defines, replaced)?;
+
+    Ok(device.create_shader_module(&spirv))
+}
+
+// A shader pipeline of a material, for one primitive topology.
+pub struct PipelineVariant {
+    pub primitive_topology: wgpu::PrimitiveTopology,
+    pub vertex_defines: shaders::ShaderDefines,
+    pub fragment_defines: shaders::ShaderDefines,
+}
+
+pub type TopologyPipelines = Vec<(wgpu::PrimitiveTopology, Handle<wgpu::RenderPipeline>)>;
+
+// A material's pipelines for each primitive topology. Materials drawn in the depth pre-pass also
+// have depth-only pipelines, built from the same vertex shaders so they write the same depths.
+#[derive(Clone)]
+pub struct RenderPipelines {
+    pub colour: TopologyPipelines,
+    pub depth: TopologyPipelines,
+}
+
+// Depth-only pipelines write the depth of the nearest surfaces before they're shaded.
+pub fn depth_prepass_state() -> wgpu::DepthStencilStateDescriptor {
+    wgpu::DepthStencilStateDescriptor {
+        format: wgpu::TextureFormat::Depth32Float,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Less,
+        stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+        stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+        stencil_read_mask: 0,
+        stencil_write_mask: 0,
+    }
+}
+
+// A material's pipelines, kept with everything needed to rebuild them when their shaders are
+// reloaded. The pipelines are swapped in place, as materials are shared between renderables.
+// Materials with the same permutation share their pipelines through the pipeline cache.
+pub struct MaterialPipelines {
+    bind_group_layouts: Vec<Handle<CachedBindGroupLayout>>,
+    vertex_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+    // Read from the instance buffer, if the shaders are instanced.
+    instance_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+    colour_states: Vec<wgpu::ColorStateDescriptor>,
+    depth_state: Option<wgpu::DepthStencilStateDescriptor>,
+    vertex_shader: &'static str,
+    fragment_shader: &'static str,
+    variants: Vec<PipelineVariant>,
+    has_depth_prepass: bool,
+    render_pipelines: std::sync::RwLock<RenderPipelines>,
+}
+
+impl MaterialPipelines {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        vertex_shader: &'static str,
+        fragment_shader: &'static str,
+        bind_group_layouts: Vec<Handle<CachedBindGroupLayout>>,
+        vertex_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+        instance_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+        colour_states: Vec<wgpu::ColorStateDescriptor>,
+        depth_state: Option<wgpu::DepthStencilStateDescriptor>,
+        variants: Vec<PipelineVariant>,
+        has_depth_prepass: bool,
+    ) -> Self {
+        let mut pipelines = Self {
+            bind_group_layouts,
+            vertex_attributes,
+            instance_attributes,
+            colour_states,
+            depth_state,
+            vertex_shader,
+            fragment_shader,
+            variants,
+            has_depth_prepass,
+            render_pipelines: std::sync::RwLock::new(RenderPipelines {
+                colour: Vec::new(),
+                depth: Vec::new(),
+            }),
+        };
+
+        let render_pipelines = pipelines
+            .build(device, pipeline_cache, None)
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        pipelines.render_pipelines = std::sync::RwLock::new(render_pipelines);
+
+        pipelines
+    }
+
+    pub fn read(&self) -> std::sync::RwLockReadGuard<'_, RenderPipelines> {
+        self.render_pipelines.read().unwrap()
+    }
+
+    // Whether the material's shaders are the file or include it.
+    pub fn uses_shader(&self, name: &str) -> bool {
+        shaders::depends_on(self.vertex_shader, name)
+            || shaders::depends_on(self.fragment_shader, name)
+    }
+
+    // Builds the pipelines with a new source for one of the files their shaders use, to be
+    // swapped in with `replace` once every material using the file has compiled.
+    pub fn rebuild(
+        &self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        shader_name: &str,
+        source: &str,
+    ) -> Result<RenderPipelines, String> {
+        self.build(device, pipeline_cache, Some((shader_name, source)))
+    }
+
+    pub fn replace(&self, render_pipelines: RenderPipelines) {
+        *self.render_pipelines.write().unwrap() = render_pipelines;
+    }
+
+    fn build(
+        &self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        replaced: Option<(&str, &str)>,
+    ) -> Result<RenderPipelines, String> {
+        let mut render_pipelines = RenderPipelines {
+            colour: Vec::new(),
+            depth: Vec::new(),
+        };
+
+        for variant in self.variants.iter() {
+            let vertex_spirv = shaders::compile_shader(
+                self.vertex_shader,
+                shaderc::ShaderKind::Vertex,
+                &variant.vertex_defines,
+                replaced,
+            )?;
+            let fragment_spirv = shaders::compile_shader(
+                self.fragment_shader,
+                shaderc::ShaderKind::Fragment,
+                &variant.fragment_defines,
+                replaced,
+            )?;
+
+            render_pipelines.colour.push((
+                variant.primitive_topology,
+                self.render_pipeline(
+                    device,
+                    pipeline_cache,
+                    variant.primitive_topology,
+                    &vertex_spirv,
+                    Some(&fragment_spirv),
+                    &self.colour_states,
+                    self.depth_state.clone(),
+                ),
+            ));
+
+            if self.has_depth_prepass {
+                render_pipelines.depth.push((
+                    variant.primitive_topology,
+                    self.render_pipeline(
+                        device,
+                        pipeline_cache,
+                        variant.primitive_topology,
+                        &vertex_spirv,
+                        None,
+                        &[],
+                        Some(depth_prepass_state()),
+                    ),
+                ));
+            }
+        }
+
+        Ok(render_pipelines)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_pipeline(
+        &self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        primitive_topology: wgpu::PrimitiveTopology,
+        vertex_spirv: &[u32],
+        fragment_spirv: Option<&[u32]>,
+        colour_states: &[wgpu::ColorStateDescriptor],
+        depth_state: Option<wgpu::DepthStencilStateDescriptor>,
+    ) -> Handle<wgpu::RenderPipeline> {
+        let key = RenderPipelineKey {
+            bind_group_layouts: self
+                .bind_group_layouts
+                .iter()
+                .map(|layout| layout.key.clone())
+                .collect(),
+            vertex_spirv: vertex_spirv.to_vec(),
+            fragment_spirv: fragment_spirv.map(<[u32]>::to_vec),
+            vertex_attributes: self.vertex_attributes.clone(),
+            instance_attributes: self.instance_attributes.clone(),
+            primitive_topology,
+            colour_states: colour_states.to_vec(),
+            depth_state: depth_state.clone(),
+        };
+
+        pipeline_cache.render_pipeline(key, || {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &self
+                    .bind_group_layouts
+                    .iter()
+                    .map(|layout| &layout.layout)
+                    .collect::<Vec<&wgpu::BindGroupLayout>>(),
+            });
+
+            let mut vertex_buffers = vec![wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: self.vertex_attributes.as_slice(),
+            }];
+
+            if !self.instance_attributes.is_empty() {
+                vertex_buffers.push(wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<InstanceData>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: self.instance_attributes.as_slice(),
+                });
+            }
+
+            let vertex_state_desc = wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint32,
+                vertex_buffers: vertex_buffers.as_slice(),
+            };
+
+            create_render_pipeline(
+                device,
+                &layout,
+                &device.create_shader_module(vertex_spirv),
+                fragment_spirv
+                    .map(|fragment_spirv| device.create_shader_module(fragment_spirv))
+                    .as_ref(),
+                vertex_state_desc,
+                primitive_topology,
+                colour_states,
+                depth_state,
+            )
+        })
+    }
+}
+
 pub fn create_uniform_buffer<T>(
     device: &wgpu::Device,
     visibility: wgpu::ShaderStage,
@@ -129,21 +915,23 @@ pub fn create_uniform_buffer<T>(
     (buffer, bind_group, bind_group_layout)
 }
 
+// The raw bytes of plain uniform data.
+pub fn as_bytes<T>(uniform_data: &T) -> &[u8] {
+    unsafe {
+        let len = std::mem::size_of_val(uniform_data);
+        let ptr = (uniform_data as *const _) as *const u8;
+        std::slice::from_raw_parts(ptr, len)
+    }
+}
+
 pub fn update_uniform_buffer<T>(
     device: &wgpu::Device,
     uniform_buffer: &wgpu::Buffer,
     encoder: &mut wgpu::CommandEncoder,
     uniform_data: &T,
 ) {
-    // TODO: Replace this with a function.
-    let uniform_data_bytes = unsafe {
-        let len = std::mem::size_of_val(uniform_data);
-        let ptr = (uniform_data as *const _) as *const u8;
-        std::slice::from_raw_parts(ptr, len)
-    };
-
     let staging_buffer =
-        device.create_buffer_with_data(uniform_data_bytes, wgpu::BufferUsage::COPY_SRC);
+        device.create_buffer_with_data(as_bytes(uniform_data), wgpu::BufferUsage::COPY_SRC);
 
     encoder.copy_buffer_to_buffer(
         &staging_buffer,
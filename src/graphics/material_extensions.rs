@@ -0,0 +1,261 @@
+// Material, texture and node extensions the glTF crate doesn't parse, read from the document's raw
+// JSON.
+pub struct GltfExtensions {
+    materials: Vec<serde_json::Value>,
+    // The KTX2 image of every texture with KHR_texture_basisu.
+    texture_basisu_sources: Vec<Option<usize>>,
+    node_lods: Vec<Option<NodeLod>>,
+}
+
+// A node's MSFT_lod levels of detail.
+#[derive(Clone, Debug)]
+pub struct NodeLod {
+    // The nodes drawn in place of this one, from most to least detailed.
+    pub ids: Vec<usize>,
+    // The minimum screen coverage of this node and each of its levels, from MSFT_screencoverage
+    // in the node's extras. Empty if the node has none.
+    pub screen_coverages: Vec<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ExtensionTexture {
+    // Index into the document's textures.
+    pub index: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Clearcoat {
+    pub factor: f32,
+    pub texture: Option<ExtensionTexture>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<ExtensionTexture>,
+    pub normal_texture: Option<ExtensionTexture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Sheen {
+    pub colour_factor: [f32; 3],
+    pub colour_texture: Option<ExtensionTexture>,
+    pub roughness_factor: f32,
+    pub roughness_texture: Option<ExtensionTexture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Transmission {
+    pub factor: f32,
+    pub texture: Option<ExtensionTexture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Specular {
+    pub factor: f32,
+    pub texture: Option<ExtensionTexture>,
+    pub colour_factor: [f32; 3],
+    pub colour_texture: Option<ExtensionTexture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Anisotropy {
+    pub strength: f32,
+    // Rotation of the anisotropy direction from the tangent, in radians.
+    pub rotation: f32,
+    pub texture: Option<ExtensionTexture>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialExtensions {
+    pub clearcoat: Option<Clearcoat>,
+    pub sheen: Option<Sheen>,
+    pub transmission: Option<Transmission>,
+    pub specular: Option<Specular>,
+    pub anisotropy: Option<Anisotropy>,
+    pub ior: f32,
+    pub emissive_strength: f32,
+}
+
+impl Default for MaterialExtensions {
+    fn default() -> Self {
+        Self {
+            clearcoat: None,
+            sheen: None,
+            transmission: None,
+            specular: None,
+            anisotropy: None,
+            ior: 1.5,
+            emissive_strength: 1.0,
+        }
+    }
+}
+
+impl GltfExtensions {
+    // Reads the extensions from the contents of a .gltf or .glb file.
+    pub fn new_from_slice(data: &[u8]) -> Self {
+        let json_data = if data.starts_with(b"glTF") {
+            match gltf::binary::Glb::from_slice(data) {
+                Ok(glb) => glb.json.into_owned(),
+                Err(_) => Vec::new(),
+            }
+        } else {
+            data.to_vec()
+        };
+
+        let root: serde_json::Value = serde_json::from_slice(&json_data).unwrap_or_default();
+
+        let materials = match root.get("materials") {
+            Some(serde_json::Value::Array(materials)) => materials
+                .iter()
+                .map(|material| material.get("extensions").cloned().unwrap_or_default())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let texture_basisu_sources = match root.get("textures") {
+            Some(serde_json::Value::Array(textures)) => textures
+                .iter()
+                .map(|texture| {
+                    texture
+                        .pointer("/extensions/KHR_texture_basisu/source")
+                        .and_then(serde_json::Value::as_u64)
+                        .map(|source| source as usize)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let node_lods = match root.get("nodes") {
+            Some(serde_json::Value::Array(nodes)) => nodes.iter().map(read_node_lod).collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            materials,
+            texture_basisu_sources,
+            node_lods,
+        }
+    }
+
+    pub fn node_lod(&self, node_index: usize) -> Option<&NodeLod> {
+        self.node_lods.get(node_index).and_then(Option::as_ref)
+    }
+
+    pub fn texture_basisu_source(&self, texture_index: usize) -> Option<usize> {
+        self.texture_basisu_sources
+            .get(texture_index)
+            .copied()
+            .flatten()
+    }
+
+    pub fn material(&self, material_index: Option<usize>) -> MaterialExtensions {
+        let extensions = match material_index.and_then(|index| self.materials.get(index)) {
+            Some(extensions) => extensions,
+            None => return MaterialExtensions::default(),
+        };
+
+        let float = |extension: &serde_json::Value, name: &str, default: f32| {
+            extension
+                .get(name)
+                .and_then(serde_json::Value::as_f64)
+                .map_or(default, |value| value as f32)
+        };
+
+        let colour = |extension: &serde_json::Value, name: &str, default: [f32; 3]| match extension
+            .get(name)
+            .and_then(serde_json::Value::as_array)
+        {
+            Some(values) if values.len() == 3 => {
+                let mut colour = default;
+
+                for (c, value) in colour.iter_mut().zip(values) {
+                    *c = value.as_f64().unwrap_or(0.0) as f32;
+                }
+
+                colour
+            }
+            _ => default,
+        };
+
+        let texture = |extension: &serde_json::Value, name: &str| {
+            extension
+                .get(name)
+                .and_then(|texture| texture.get("index"))
+                .and_then(serde_json::Value::as_u64)
+                .map(|index| ExtensionTexture {
+                    index: index as usize,
+                })
+        };
+
+        MaterialExtensions {
+            clearcoat: extensions
+                .get("KHR_materials_clearcoat")
+                .map(|extension| Clearcoat {
+                    factor: float(extension, "clearcoatFactor", 0.0),
+                    texture: texture(extension, "clearcoatTexture"),
+                    roughness_factor: float(extension, "clearcoatRoughnessFactor", 0.0),
+                    roughness_texture: texture(extension, "clearcoatRoughnessTexture"),
+                    normal_texture: texture(extension, "clearcoatNormalTexture"),
+                }),
+            sheen: extensions
+                .get("KHR_materials_sheen")
+                .map(|extension| Sheen {
+                    colour_factor: colour(extension, "sheenColorFactor", [0.0; 3]),
+                    colour_texture: texture(extension, "sheenColorTexture"),
+                    roughness_factor: float(extension, "sheenRoughnessFactor", 0.0),
+                    roughness_texture: texture(extension, "sheenRoughnessTexture"),
+                }),
+            transmission: extensions
+                .get("KHR_materials_transmission")
+                .map(|extension| Transmission {
+                    factor: float(extension, "transmissionFactor", 0.0),
+                    texture: texture(extension, "transmissionTexture"),
+                }),
+            specular: extensions
+                .get("KHR_materials_specular")
+                .map(|extension| Specular {
+                    factor: float(extension, "specularFactor", 1.0),
+                    texture: texture(extension, "specularTexture"),
+                    colour_factor: colour(extension, "specularColorFactor", [1.0; 3]),
+                    colour_texture: texture(extension, "specularColorTexture"),
+                }),
+            anisotropy: extensions
+                .get("KHR_materials_anisotropy")
+                .map(|extension| Anisotropy {
+                    strength: float(extension, "anisotropyStrength", 0.0),
+                    rotation: float(extension, "anisotropyRotation", 0.0),
+                    texture: texture(extension, "anisotropyTexture"),
+                }),
+            ior: extensions
+                .get("KHR_materials_ior")
+                .map_or(1.5, |extension| float(extension, "ior", 1.5)),
+            emissive_strength: extensions
+                .get("KHR_materials_emissive_strength")
+                .map_or(1.0, |extension| float(extension, "emissiveStrength", 1.0)),
+        }
+    }
+}
+
+fn read_node_lod(node: &serde_json::Value) -> Option<NodeLod> {
+    let ids = node
+        .pointer("/extensions/MSFT_lod/ids")?
+        .as_array()?
+        .iter()
+        .filter_map(serde_json::Value::as_u64)
+        .map(|id| id as usize)
+        .collect();
+
+    let screen_coverages = match node
+        .pointer("/extras/MSFT_screencoverage")
+        .and_then(serde_json::Value::as_array)
+    {
+        Some(coverages) => coverages
+            .iter()
+            .filter_map(serde_json::Value::as_f64)
+            .map(|coverage| coverage as f32)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    Some(NodeLod {
+        ids,
+        screen_coverages,
+    })
+}
@@ -52,7 +52,7 @@ impl HdrCvtMaterial {
         // Init bind groups.
         // Transform buffers.
         let (transform_bind_group_buffer, transform_bind_group, transform_bind_group_layout) =
-            material_base::create_uniform_buffer::<TransformBindGroup>(
+            material_base::create_uniform_buffer::<HdrTransformBindGroup>(
                 device,
                 wgpu::ShaderStage::VERTEX,
             );
@@ -110,10 +110,11 @@ impl HdrCvtMaterial {
 
         let render_pipeline = material_base::build_render_pipeline(
             device,
-            include_str!("./shaders/hdr.vert"),
-            include_str!("./shaders/hdr_cvt.frag"),
+            "hdr.vert",
+            "hdr_cvt.frag",
             &[&transform_bind_group_layout, &cvt_bind_group_layout],
             vertex_state_desc,
+            wgpu::PrimitiveTopology::TriangleList,
             &colour_states,
             None,
         );
@@ -132,7 +133,7 @@ impl HdrConvolveDiffuseMaterial {
         // Init bind groups.
         // Transform buffers.
         let (transform_bind_group_buffer, transform_bind_group, transform_bind_group_layout) =
-            material_base::create_uniform_buffer::<TransformBindGroup>(
+            material_base::create_uniform_buffer::<HdrTransformBindGroup>(
                 device,
                 wgpu::ShaderStage::VERTEX,
             );
@@ -190,10 +191,11 @@ impl HdrConvolveDiffuseMaterial {
 
         let render_pipeline = material_base::build_render_pipeline(
             device,
-            include_str!("./shaders/hdr.vert"),
-            include_str!("./shaders/hdr_convolve_diffuse.frag"),
+            "hdr.vert",
+            "hdr_convolve_diffuse.frag",
             &[&transform_bind_group_layout, &convolve_bind_group_layout],
             vertex_state_desc,
+            wgpu::PrimitiveTopology::TriangleList,
             &colour_states,
             None,
         );
@@ -211,7 +213,7 @@ impl HdrConvolveSpecularMaterial {
     pub fn new(device: &wgpu::Device, params: &HdrConvolveSpecularBindGroup) -> Self {
         // Init bind groups.
         let (transform_bind_group_buffer, transform_bind_group, transform_bind_group_layout) =
-            material_base::create_uniform_buffer::<TransformBindGroup>(
+            material_base::create_uniform_buffer::<HdrTransformBindGroup>(
                 device,
                 wgpu::ShaderStage::VERTEX,
             );
@@ -272,14 +274,15 @@ impl HdrConvolveSpecularMaterial {
 
         let render_pipeline = material_base::build_render_pipeline(
             device,
-            include_str!("./shaders/hdr.vert"),
-            include_str!("./shaders/hdr_convolve_specular.frag"),
+            "hdr.vert",
+            "hdr_convolve_specular.frag",
             &[
                 &transform_bind_group_layout,
                 &convolve_bind_group_layout,
                 &roughness_bind_group_layout,
             ],
             vertex_state_desc,
+            wgpu::PrimitiveTopology::TriangleList,
             &colour_states,
             None,
         );
@@ -299,7 +302,7 @@ impl HdrConvolveBrdfMaterial {
     pub fn new(device: &wgpu::Device) -> Self {
         // Init bind groups.
         let (transform_bind_group_buffer, transform_bind_group, transform_bind_group_layout) =
-            material_base::create_uniform_buffer::<TransformBindGroup>(
+            material_base::create_uniform_buffer::<HdrTransformBindGroup>(
                 device,
                 wgpu::ShaderStage::VERTEX,
             );
@@ -349,10 +352,11 @@ impl HdrConvolveBrdfMaterial {
 
         let render_pipeline = material_base::build_render_pipeline(
             device,
-            include_str!("./shaders/hdr.vert"),
-            include_str!("./shaders/hdr_convolve_brdf.frag"),
+            "hdr.vert",
+            "hdr_convolve_brdf.frag",
             &[&transform_bind_group_layout],
             vertex_state_desc,
+            wgpu::PrimitiveTopology::TriangleList,
             &colour_states,
             None,
         );
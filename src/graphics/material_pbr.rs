@@ -1,17 +1,22 @@
 use super::*;
 
 pub struct PbrBindGroup<'a> {
-    pub textures: Vec<Texture>,
+    pub textures: Vec<Handle<Texture>>,
 
     pub ao_property: MaterialProperty,
     pub albedo_property: MaterialProperty,
     pub emissive_property: MaterialProperty,
     pub metal_roughness_property: MaterialProperty,
     pub normal_property: MaterialProperty,
+    pub extension_properties: PbrExtensionProperties,
 
     pub irradiance_map: &'a Texture,
     pub prefiltered_environment_map: &'a Texture,
     pub brdf_lut: &'a Texture,
+
+    pub is_skinned: bool,
+    pub has_morph_targets: bool,
+    pub primitive_topologies: Vec<wgpu::PrimitiveTopology>,
 }
 
 #[derive(Copy, Clone)]
@@ -20,66 +25,106 @@ pub struct MaterialProperty {
     pub texture_id: Option<usize>,
 }
 
+// Properties from the KHR_materials_* extensions. Each layer is only shaded when present.
+#[derive(Copy, Clone)]
+pub struct PbrExtensionProperties {
+    pub clearcoat_property: Option<MaterialProperty>,
+    pub clearcoat_roughness_property: Option<MaterialProperty>,
+    pub clearcoat_normal_property: Option<MaterialProperty>,
+    pub sheen_colour_property: Option<MaterialProperty>,
+    pub sheen_roughness_property: Option<MaterialProperty>,
+    pub transmission_property: Option<MaterialProperty>,
+    pub specular_property: Option<MaterialProperty>,
+    pub specular_colour_property: Option<MaterialProperty>,
+    pub anisotropy_property: Option<MaterialProperty>,
+    pub ior: f32,
+    // Scales the emissive factor or texture beyond the [0, 1] range.
+    pub emissive_strength: f32,
+}
+
+impl Default for PbrExtensionProperties {
+    fn default() -> Self {
+        Self {
+            clearcoat_property: None,
+            clearcoat_roughness_property: None,
+            clearcoat_normal_property: None,
+            sheen_colour_property: None,
+            sheen_roughness_property: None,
+            transmission_property: None,
+            specular_property: None,
+            specular_colour_property: None,
+            anisotropy_property: None,
+            ior: 1.5,
+            emissive_strength: 1.0,
+        }
+    }
+}
+
+// The material parameters, kept so the material can be exported.
+#[derive(Clone)]
+pub struct PbrProperties {
+    pub ao_property: MaterialProperty,
+    pub albedo_property: MaterialProperty,
+    pub emissive_property: MaterialProperty,
+    pub metal_roughness_property: MaterialProperty,
+    pub normal_property: MaterialProperty,
+    pub extension_properties: PbrExtensionProperties,
+
+    // Also keeps the textures cached while the material uses them.
+    pub textures: Vec<Handle<Texture>>,
+}
+
 pub struct PbrMaterial {
-    pub render_pipeline: wgpu::RenderPipeline,
-    pub transform_bind_group: wgpu::BindGroup,
-    pub lighting_bind_group: wgpu::BindGroup,
-    pub pbr_factor_bind_group: wgpu::BindGroup,
-    pub pbr_texture_bind_group: wgpu::BindGroup,
-
-    pub transform_uniform_buffer: wgpu::Buffer,
-    pub lighting_uniform_buffer: wgpu::Buffer,
+    pub pipelines: MaterialPipelines,
+    pub default_morph_target_bind_group: wgpu::BindGroup,
+    pub pbr_bind_group: wgpu::BindGroup,
+
+    pub morph_target_bind_group_layout: Handle<CachedBindGroupLayout>,
+
+    pub is_skinned: bool,
+    pub has_morph_targets: bool,
+
+    pub properties: PbrProperties,
 }
 
 impl PbrMaterial {
     pub fn new(
         device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
         swap_chain_desc: &wgpu::SwapChainDescriptor,
         params: &PbrBindGroup,
     ) -> Self {
         // Init bind groups.
 
-        // Transform buffers.
-        let transform_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: std::mem::size_of::<TransformBindGroup>() as u64,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
-
-        let transform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-                label: Some("transform_bind_group_layout"),
-            });
-
-        let transform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &transform_bind_group_layout,
-            bindings: &[wgpu::Binding {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer {
-                    buffer: &transform_uniform_buffer,
-                    // FYI: you can share a single buffer between bindings.
-                    range: 0..std::mem::size_of::<TransformBindGroup>() as wgpu::BufferAddress,
-                },
-            }],
-            label: Some("transform_bind_group"),
-        });
+        let VertexBindGroups {
+            object_bind_group_layout,
+            morph_target_bind_group_layout,
+            default_morph_target_bind_group,
+            vertex_defines,
+        } = VertexBindGroups::new(
+            device,
+            pipeline_cache,
+            params.is_skinned,
+            params.has_morph_targets,
+        );
 
-        // Material bind group.
+        // Material bind group, with the factors at binding 0 followed by the textures.
         let mut pbr_factor_values = Vec::new();
-        let mut pbr_texture_binding_entries = Vec::new();
-        let mut pbr_texture_bindings = Vec::new();
-        let mut pbr_defines = "".to_owned();
+        let mut pbr_binding_entries = Vec::new();
+        let mut pbr_bindings = Vec::new();
+        let mut pbr_defines = shaders::ShaderDefines::new();
+        pbr_defines.add_value("MAX_LIGHTS", MAX_LIGHTS);
 
-        // Add constant texture bindings.
-        pbr_texture_binding_entries.extend_from_slice(&[
+        // Add constant bindings.
+        pbr_binding_entries.extend_from_slice(&[
             wgpu::BindGroupLayoutEntry {
                 binding: 0,
                 visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::SampledTexture {
                     dimension: wgpu::TextureViewDimension::Cube,
                     component_type: wgpu::TextureComponentType::Float,
@@ -87,12 +132,12 @@ impl PbrMaterial {
                 },
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 1,
+                binding: 2,
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::Sampler { comparison: false },
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 2,
+                binding: 3,
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::SampledTexture {
                     dimension: wgpu::TextureViewDimension::Cube,
@@ -101,12 +146,12 @@ impl PbrMaterial {
                 },
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 3,
+                binding: 4,
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::Sampler { comparison: false },
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 4,
+                binding: 5,
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::SampledTexture {
                     dimension: wgpu::TextureViewDimension::D2,
@@ -115,44 +160,44 @@ impl PbrMaterial {
                 },
             },
             wgpu::BindGroupLayoutEntry {
-                binding: 5,
+                binding: 6,
                 visibility: wgpu::ShaderStage::FRAGMENT,
                 ty: wgpu::BindingType::Sampler { comparison: false },
             },
         ]);
 
-        pbr_texture_bindings.extend_from_slice(&[
+        pbr_bindings.extend_from_slice(&[
             wgpu::Binding {
-                binding: 0,
+                binding: 1,
                 resource: wgpu::BindingResource::TextureView(&params.irradiance_map.view),
             },
             wgpu::Binding {
-                binding: 1,
+                binding: 2,
                 resource: wgpu::BindingResource::Sampler(&params.irradiance_map.sampler),
             },
             wgpu::Binding {
-                binding: 2,
+                binding: 3,
                 resource: wgpu::BindingResource::TextureView(
                     &params.prefiltered_environment_map.view,
                 ),
             },
             wgpu::Binding {
-                binding: 3,
+                binding: 4,
                 resource: wgpu::BindingResource::Sampler(
                     &params.prefiltered_environment_map.sampler,
                 ),
             },
             wgpu::Binding {
-                binding: 4,
+                binding: 5,
                 resource: wgpu::BindingResource::TextureView(&params.brdf_lut.view),
             },
             wgpu::Binding {
-                binding: 5,
+                binding: 6,
                 resource: wgpu::BindingResource::Sampler(&params.brdf_lut.sampler),
             },
         ]);
 
-        let pbr_properties = [
+        let mut pbr_properties = vec![
             ("AO", params.ao_property),
             ("ALBEDO", params.albedo_property),
             ("EMISSIVE", params.emissive_property),
@@ -160,64 +205,100 @@ impl PbrMaterial {
             ("NORMAL", params.normal_property),
         ];
 
-        for (prop_name, property) in pbr_properties.iter() {
-            match property.texture_id {
-                None => pbr_factor_values.push(property.factor.unwrap()),
-                Some(texture_id) => {
-                    pbr_texture_binding_entries.push(wgpu::BindGroupLayoutEntry {
-                        binding: pbr_texture_binding_entries.len() as u32,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::SampledTexture {
-                            dimension: wgpu::TextureViewDimension::D2,
-                            component_type: wgpu::TextureComponentType::Float,
-                            multisampled: false,
-                        },
-                    });
-                    pbr_texture_binding_entries.push(wgpu::BindGroupLayoutEntry {
-                        binding: pbr_texture_binding_entries.len() as u32,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler { comparison: false },
-                    });
-
-                    pbr_texture_bindings.push(wgpu::Binding {
-                        binding: pbr_texture_bindings.len() as u32,
-                        resource: wgpu::BindingResource::TextureView(
-                            &params.textures[texture_id].view,
-                        ),
-                    });
-                    pbr_texture_bindings.push(wgpu::Binding {
-                        binding: pbr_texture_bindings.len() as u32,
-                        resource: wgpu::BindingResource::Sampler(
-                            &params.textures[texture_id].sampler,
-                        ),
-                    });
-
-                    pbr_defines = format!(
-                        "{}#define {}_TEXTURE_BINDING {}\n",
-                        pbr_defines,
-                        prop_name,
-                        pbr_texture_bindings.len() - 2
-                    );
-                }
+        let extensions = &params.extension_properties;
+
+        // Extension layers, in the order the shader declares their factors.
+        let extension_layers = [
+            (
+                "MATERIAL_CLEARCOAT",
+                vec![
+                    ("CLEARCOAT", extensions.clearcoat_property),
+                    (
+                        "CLEARCOAT_ROUGHNESS",
+                        extensions.clearcoat_roughness_property,
+                    ),
+                    ("CLEARCOAT_NORMAL", extensions.clearcoat_normal_property),
+                ],
+            ),
+            (
+                "MATERIAL_SHEEN",
+                vec![
+                    ("SHEEN_COLOUR", extensions.sheen_colour_property),
+                    ("SHEEN_ROUGHNESS", extensions.sheen_roughness_property),
+                ],
+            ),
+            (
+                "MATERIAL_TRANSMISSION",
+                vec![("TRANSMISSION", extensions.transmission_property)],
+            ),
+            (
+                "MATERIAL_SPECULAR",
+                vec![
+                    ("SPECULAR", extensions.specular_property),
+                    ("SPECULAR_COLOUR", extensions.specular_colour_property),
+                ],
+            ),
+            (
+                "MATERIAL_ANISOTROPY",
+                vec![("ANISOTROPY", extensions.anisotropy_property)],
+            ),
+        ];
+
+        for (layer_name, layer_properties) in extension_layers.iter() {
+            if layer_properties
+                .iter()
+                .all(|(_, property)| property.is_some())
+            {
+                pbr_defines.add(layer_name);
+
+                pbr_properties.extend(
+                    layer_properties
+                        .iter()
+                        .map(|(prop_name, property)| (*prop_name, property.unwrap())),
+                );
             }
         }
 
-        // Push a dumm
-        if pbr_factor_values.len() == 0 {
-            pbr_factor_values.push([0.0; 4]);
-        }
+        // Properties normally have either a factor or a texture, but some, like anisotropy, scale
+        // their texture by a factor and have both.
+        for (prop_name, property) in pbr_properties.iter() {
+            if let Some(factor) = property.factor {
+                pbr_factor_values.push(factor);
+            }
 
-        let pbr_texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: pbr_texture_binding_entries.as_slice(),
-                label: None,
-            });
+            if let Some(texture_id) = property.texture_id {
+                let binding = pbr_binding_entries.len() as u32;
 
-        let pbr_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &pbr_texture_bind_group_layout,
-            bindings: pbr_texture_bindings.as_slice(),
-            label: None,
-        });
+                pbr_binding_entries.push(wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                    },
+                });
+                pbr_binding_entries.push(wgpu::BindGroupLayoutEntry {
+                    binding: binding + 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                });
+
+                pbr_bindings.push(wgpu::Binding {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(&params.textures[texture_id].view),
+                });
+                pbr_bindings.push(wgpu::Binding {
+                    binding: binding + 1,
+                    resource: wgpu::BindingResource::Sampler(&params.textures[texture_id].sampler),
+                });
+
+                pbr_defines.add_value(&format!("{}_TEXTURE_BINDING", prop_name), binding);
+            }
+        }
+
+        // Scalar factors are always last, which also keeps the factor buffer from being empty.
+        pbr_factor_values.push([extensions.ior, extensions.emissive_strength, 0.0, 0.0]);
 
         let pbr_factor_data_bytes = unsafe {
             let len = std::mem::size_of_val(pbr_factor_values.as_slice());
@@ -228,172 +309,135 @@ impl PbrMaterial {
         let pbr_factor_uniform_buffer =
             device.create_buffer_with_data(pbr_factor_data_bytes, wgpu::BufferUsage::UNIFORM);
 
-        let pbr_factor_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-            });
-
-        let pbr_factor_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &pbr_factor_bind_group_layout,
-            bindings: &[wgpu::Binding {
+        pbr_bindings.insert(
+            0,
+            wgpu::Binding {
                 binding: 0,
                 resource: wgpu::BindingResource::Buffer {
                     buffer: &pbr_factor_uniform_buffer,
-                    // FYI: you can share a single buffer between bindings.
                     range: 0..pbr_factor_data_bytes.len() as wgpu::BufferAddress,
                 },
-            }],
-            label: Some("transform_bind_group"),
-        });
+            },
+        );
 
-        // Lighting bind group.
-        let lighting_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: std::mem::size_of::<LightingBindGroup>() as u64,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
+        // Materials with the same textures and factors enabled share a layout, and so pipelines.
+        let pbr_bind_group_layout =
+            pipeline_cache.bind_group_layout(device, pbr_binding_entries.as_slice());
 
-        let lighting_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-                label: Some("lighting_bind_group_layout"),
-            });
-
-        let lighting_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &lighting_bind_group_layout,
-            bindings: &[wgpu::Binding {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer {
-                    buffer: &lighting_uniform_buffer,
-                    // FYI: you can share a single buffer between bindings.
-                    range: 0..std::mem::size_of::<LightingBindGroup>() as wgpu::BufferAddress,
-                },
-            }],
-            label: Some("lighting_bind_group"),
+        let pbr_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pbr_bind_group_layout.layout,
+            bindings: pbr_bindings.as_slice(),
+            label: Some("pbr_bind_group"),
         });
 
         // Init pipeline.
-        let vertex_state_desc = wgpu::VertexStateDescriptor {
-            index_format: wgpu::IndexFormat::Uint32,
-            vertex_buffers: &[wgpu::VertexBufferDescriptor {
-                stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Vertex,
-                attributes: &[
-                    wgpu::VertexAttributeDescriptor {
-                        // Position
-                        offset: 0,
-                        shader_location: 0,
-                        format: wgpu::VertexFormat::Float3,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Normal
-                        offset: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
-                        shader_location: 1,
-                        format: wgpu::VertexFormat::Float3,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Tangent
-                        offset: (std::mem::size_of::<f32>() * 6) as wgpu::BufferAddress,
-                        shader_location: 2,
-                        format: wgpu::VertexFormat::Float4,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Tex Coord
-                        offset: (std::mem::size_of::<f32>() * 10) as wgpu::BufferAddress,
-                        shader_location: 3,
-                        format: wgpu::VertexFormat::Float2,
-                    },
-                ],
-            }],
-        };
+        let vertex_attributes = material_base::vertex_attributes(params.is_skinned);
 
-        let colour_states = [wgpu::ColorStateDescriptor {
+        let colour_states = vec![wgpu::ColorStateDescriptor {
             format: swap_chain_desc.format,
             color_blend: wgpu::BlendDescriptor::REPLACE,
             alpha_blend: wgpu::BlendDescriptor::REPLACE,
             write_mask: wgpu::ColorWrite::ALL,
         }];
 
+        // Surfaces already written by the depth pre-pass are drawn at the same depth.
         let depth_state = Some(wgpu::DepthStencilStateDescriptor {
             format: wgpu::TextureFormat::Depth32Float,
             depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
+            depth_compare: wgpu::CompareFunction::LessEqual,
             stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
             stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
             stencil_read_mask: 0,
             stencil_write_mask: 0,
         });
 
-        let render_pipeline = material_base::build_render_pipeline(
+        // Build a pipeline for every primitive topology drawn with this material.
+        // Lines and points are drawn unlit, as they have no surface to shade.
+        let variants = params
+            .primitive_topologies
+            .iter()
+            .map(|&primitive_topology| {
+                let mut variant = PipelineVariant {
+                    primitive_topology,
+                    vertex_defines: vertex_defines.clone(),
+                    fragment_defines: pbr_defines.clone(),
+                };
+
+                match primitive_topology {
+                    wgpu::PrimitiveTopology::PointList => {
+                        variant.vertex_defines.add("POINTS");
+                        variant.fragment_defines.add("UNLIT");
+                    }
+                    wgpu::PrimitiveTopology::LineList | wgpu::PrimitiveTopology::LineStrip => {
+                        variant.fragment_defines.add("UNLIT");
+                    }
+                    _ => {}
+                }
+
+                variant
+            })
+            .collect();
+
+        let pipelines = MaterialPipelines::new(
             device,
-            include_str!("shaders/pbr.vert"),
-            &format!(
-                "#version 450\n\n{}\n{}",
-                pbr_defines,
-                include_str!("shaders/pbr.frag")
-            ),
-            &[
-                &transform_bind_group_layout,
-                &lighting_bind_group_layout,
-                &pbr_factor_bind_group_layout,
-                &pbr_texture_bind_group_layout,
+            pipeline_cache,
+            "pbr.vert",
+            "pbr.frag",
+            vec![
+                material_base::frame_bind_group_layout(device, pipeline_cache),
+                pbr_bind_group_layout,
+                object_bind_group_layout,
+                morph_target_bind_group_layout.clone(),
             ],
-            vertex_state_desc,
-            &colour_states,
+            vertex_attributes,
+            material_base::instance_attributes(),
+            colour_states,
             depth_state,
+            variants,
+            true,
         );
 
         Self {
-            render_pipeline,
-            transform_bind_group,
-            lighting_bind_group,
-            pbr_factor_bind_group,
-            pbr_texture_bind_group,
-            transform_uniform_buffer,
-            lighting_uniform_buffer,
+            pipelines,
+            default_morph_target_bind_group,
+            pbr_bind_group,
+            morph_target_bind_group_layout,
+            is_skinned: params.is_skinned,
+            has_morph_targets: params.has_morph_targets,
+            properties: PbrProperties {
+                ao_property: params.ao_property,
+                albedo_property: params.albedo_property,
+                emissive_property: params.emissive_property,
+                metal_roughness_property: params.metal_roughness_property,
+                normal_property: params.normal_property,
+                extension_properties: params.extension_properties,
+                textures: params.textures.clone(),
+            },
         }
     }
 }
 
 impl MaterialBase for PbrMaterial {
-    fn begin_render_pass<'a>(
-        &'a self,
-        device: &wgpu::Device,
-        encoder: &'a mut wgpu::CommandEncoder,
-        rp_desc: &'a wgpu::RenderPassDescriptor,
-        transform_data: &TransformBindGroup,
-        lighting_data: &LightingBindGroup,
-    ) -> wgpu::RenderPass<'a> {
-        material_base::update_uniform_buffer(
-            device,
-            &self.lighting_uniform_buffer,
-            encoder,
-            lighting_data,
-        );
-        material_base::update_uniform_buffer(
-            device,
-            &self.transform_uniform_buffer,
-            encoder,
-            transform_data,
-        );
-
-        let mut render_pass = encoder.begin_render_pass(rp_desc);
+    fn pipelines(&self) -> &MaterialPipelines {
+        &self.pipelines
+    }
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.transform_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.lighting_bind_group, &[]);
-        render_pass.set_bind_group(2, &self.pbr_factor_bind_group, &[]);
-        render_pass.set_bind_group(3, &self.pbr_texture_bind_group, &[]);
+    fn pbr_properties(&self) -> Option<&PbrProperties> {
+        Some(&self.properties)
+    }
 
-        render_pass
+    fn set_bind_groups<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        frame_bind_group: &'a FrameBindGroup,
+        offsets: &ObjectOffsets,
+    ) {
+        let (object_bind_group, object_offsets) =
+            frame_bind_group.object_bind_group(self.is_skinned, self.has_morph_targets, offsets);
+
+        render_pass.set_bind_group(0, &frame_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.pbr_bind_group, &[]);
+        render_pass.set_bind_group(2, object_bind_group, &object_offsets);
+        render_pass.set_bind_group(3, &self.default_morph_target_bind_group, &[]);
     }
 }
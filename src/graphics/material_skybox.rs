@@ -5,10 +5,7 @@ pub struct SkyboxBindGroup {
 }
 
 pub struct SkyboxMaterial {
-    pub render_pipeline: wgpu::RenderPipeline,
-
-    pub transform_bind_group: wgpu::BindGroup,
-    pub transform_uniform_buffer: wgpu::Buffer,
+    pub pipelines: MaterialPipelines,
 
     pub params_bind_group: wgpu::BindGroup,
 }
@@ -16,65 +13,39 @@ pub struct SkyboxMaterial {
 impl SkyboxMaterial {
     pub fn new(
         device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
         sc_desc: &wgpu::SwapChainDescriptor,
         params: &SkyboxBindGroup,
     ) -> Self {
         // Init bind groups.
 
-        // Transform buffers.
-        let transform_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: std::mem::size_of::<TransformBindGroup>() as u64,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
-
-        let transform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::UniformBuffer { dynamic: false },
-                }],
-                label: Some("transform_bind_group_layout"),
-            });
-
-        let transform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &transform_bind_group_layout,
-            bindings: &[wgpu::Binding {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer {
-                    buffer: &transform_uniform_buffer,
-                    // FYI: you can share a single buffer between bindings.
-                    range: 0..std::mem::size_of::<TransformBindGroup>() as wgpu::BufferAddress,
-                },
-            }],
-            label: Some("transform_bind_group"),
-        });
+        // The camera is read from the frame bind group.
+        let frame_bind_group_layout =
+            material_base::frame_bind_group_layout(device, pipeline_cache);
 
         // Material bind group.
-        let params_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                bindings: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::SampledTexture {
-                            dimension: wgpu::TextureViewDimension::Cube,
-                            component_type: wgpu::TextureComponentType::Float,
-                            multisampled: false,
-                        },
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler { comparison: false },
+        let params_bind_group_layout = pipeline_cache.bind_group_layout(
+            device,
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::Cube,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
                     },
-                ],
-                label: None,
-            });
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                },
+            ],
+        );
 
         let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &params_bind_group_layout,
+            layout: &params_bind_group_layout.layout,
             bindings: &[
                 wgpu::Binding {
                     binding: 0,
@@ -89,41 +60,34 @@ impl SkyboxMaterial {
         });
 
         // Init pipeline.
-        let vertex_state_desc = wgpu::VertexStateDescriptor {
-            index_format: wgpu::IndexFormat::Uint32,
-            vertex_buffers: &[wgpu::VertexBufferDescriptor {
-                stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::InputStepMode::Vertex,
-                attributes: &[
-                    wgpu::VertexAttributeDescriptor {
-                        // Position
-                        offset: 0,
-                        shader_location: 0,
-                        format: wgpu::VertexFormat::Float3,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Normal
-                        offset: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
-                        shader_location: 1,
-                        format: wgpu::VertexFormat::Float3,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Tangent
-                        offset: (std::mem::size_of::<f32>() * 6) as wgpu::BufferAddress,
-                        shader_location: 2,
-                        format: wgpu::VertexFormat::Float4,
-                    },
-                    wgpu::VertexAttributeDescriptor {
-                        // Tex Coord
-                        offset: (std::mem::size_of::<f32>() * 10) as wgpu::BufferAddress,
-                        shader_location: 3,
-                        format: wgpu::VertexFormat::Float2,
-                    },
-                ],
-            }],
-        };
-
-        let colour_states = [wgpu::ColorStateDescriptor {
+        let vertex_attributes = vec![
+            wgpu::VertexAttributeDescriptor {
+                // Position
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttributeDescriptor {
+                // Normal
+                offset: (std::mem::size_of::<f32>() * 3) as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float3,
+            },
+            wgpu::VertexAttributeDescriptor {
+                // Tangent
+                offset: (std::mem::size_of::<f32>() * 6) as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float4,
+            },
+            wgpu::VertexAttributeDescriptor {
+                // Tex Coord
+                offset: (std::mem::size_of::<f32>() * 10) as wgpu::BufferAddress,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Float2,
+            },
+        ];
+
+        let colour_states = vec![wgpu::ColorStateDescriptor {
             format: sc_desc.format,
             color_blend: wgpu::BlendDescriptor::REPLACE,
             alpha_blend: wgpu::BlendDescriptor::REPLACE,
@@ -140,47 +104,45 @@ impl SkyboxMaterial {
             stencil_write_mask: 0,
         });
 
-        let render_pipeline = material_base::build_render_pipeline(
+        // The skybox shaders declare their own version.
+        let pipelines = MaterialPipelines::new(
             device,
-            include_str!("shaders/skybox.vert"),
-            include_str!("shaders/skybox.frag"),
-            &[&transform_bind_group_layout, &params_bind_group_layout],
-            vertex_state_desc,
-            &colour_states,
+            pipeline_cache,
+            "skybox.vert",
+            "skybox.frag",
+            vec![frame_bind_group_layout, params_bind_group_layout],
+            vertex_attributes,
+            Vec::new(),
+            colour_states,
             depth_state,
+            vec![PipelineVariant {
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                vertex_defines: shaders::ShaderDefines::new(),
+                fragment_defines: shaders::ShaderDefines::new(),
+            }],
+            false,
         );
 
         Self {
-            render_pipeline,
-            transform_bind_group,
-            transform_uniform_buffer,
+            pipelines,
             params_bind_group,
         }
     }
 }
 
 impl MaterialBase for SkyboxMaterial {
-    fn begin_render_pass<'a>(
-        &'a self,
-        device: &wgpu::Device,
-        encoder: &'a mut wgpu::CommandEncoder,
-        rp_desc: &'a wgpu::RenderPassDescriptor,
-        transform_data: &TransformBindGroup,
-        _lighting_data: &LightingBindGroup,
-    ) -> wgpu::RenderPass<'a> {
-        material_base::update_uniform_buffer(
-            device,
-            &self.transform_uniform_buffer,
-            encoder,
-            transform_data,
-        );
-
-        let mut render_pass = encoder.begin_render_pass(rp_desc);
+    fn pipelines(&self) -> &MaterialPipelines {
+        &self.pipelines
+    }
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.transform_bind_group, &[]);
+    fn set_bind_groups<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        frame_bind_group: &'a FrameBindGroup,
+        _offsets: &ObjectOffsets,
+    ) {
+        // The skybox is drawn around the camera, so it isn't instanced.
+        render_pass.set_bind_group(0, &frame_bind_group.bind_group, &[]);
         render_pass.set_bind_group(1, &self.params_bind_group, &[]);
-
-        render_pass
     }
 }
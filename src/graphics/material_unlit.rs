@@ -0,0 +1,220 @@
+use super::*;
+
+pub struct UnlitBindGroup {
+    pub base_colour_factor: [f32; 4],
+    pub base_colour_texture: Option<Handle<Texture>>,
+
+    pub is_skinned: bool,
+    pub has_morph_targets: bool,
+    pub primitive_topologies: Vec<wgpu::PrimitiveTopology>,
+}
+
+// The material parameters, kept so the material can be exported.
+#[derive(Clone)]
+pub struct UnlitProperties {
+    pub base_colour_factor: [f32; 4],
+
+    // Also keeps the texture cached while the material uses it.
+    pub base_colour_texture: Option<Handle<Texture>>,
+}
+
+// Draws the base colour without any lighting, for KHR_materials_unlit materials.
+pub struct UnlitMaterial {
+    pub pipelines: MaterialPipelines,
+    pub default_morph_target_bind_group: wgpu::BindGroup,
+    pub unlit_bind_group: wgpu::BindGroup,
+
+    pub morph_target_bind_group_layout: Handle<CachedBindGroupLayout>,
+
+    pub is_skinned: bool,
+    pub has_morph_targets: bool,
+
+    pub properties: UnlitProperties,
+}
+
+impl UnlitMaterial {
+    pub fn new(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        swap_chain_desc: &wgpu::SwapChainDescriptor,
+        params: &UnlitBindGroup,
+    ) -> Self {
+        // Init bind groups.
+        let VertexBindGroups {
+            object_bind_group_layout,
+            morph_target_bind_group_layout,
+            default_morph_target_bind_group,
+            vertex_defines,
+        } = VertexBindGroups::new(
+            device,
+            pipeline_cache,
+            params.is_skinned,
+            params.has_morph_targets,
+        );
+
+        // Material bind group, with the factor at binding 0 followed by the texture.
+        let factor_data_bytes = unsafe {
+            std::slice::from_raw_parts(
+                params.base_colour_factor.as_ptr() as *const u8,
+                std::mem::size_of_val(&params.base_colour_factor),
+            )
+        };
+
+        let factor_uniform_buffer =
+            device.create_buffer_with_data(factor_data_bytes, wgpu::BufferUsage::UNIFORM);
+
+        let mut binding_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+        }];
+
+        let mut bindings = vec![wgpu::Binding {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer {
+                buffer: &factor_uniform_buffer,
+                range: 0..factor_data_bytes.len() as wgpu::BufferAddress,
+            },
+        }];
+
+        let mut fragment_defines = shaders::ShaderDefines::new();
+
+        if let Some(texture) = &params.base_colour_texture {
+            binding_entries.extend_from_slice(&[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                    },
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                },
+            ]);
+
+            bindings.extend_from_slice(&[
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::Binding {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ]);
+
+            fragment_defines.add_value("BASE_COLOUR_TEXTURE_BINDING", 1);
+        }
+
+        let unlit_bind_group_layout =
+            pipeline_cache.bind_group_layout(device, binding_entries.as_slice());
+
+        let unlit_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &unlit_bind_group_layout.layout,
+            bindings: bindings.as_slice(),
+            label: Some("unlit_bind_group"),
+        });
+
+        // Init pipeline.
+        let vertex_attributes = material_base::vertex_attributes(params.is_skinned);
+
+        let colour_states = vec![wgpu::ColorStateDescriptor {
+            format: swap_chain_desc.format,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }];
+
+        // Surfaces already written by the depth pre-pass are drawn at the same depth.
+        let depth_state = Some(wgpu::DepthStencilStateDescriptor {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+            stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+            stencil_read_mask: 0,
+            stencil_write_mask: 0,
+        });
+
+        let variants = params
+            .primitive_topologies
+            .iter()
+            .map(|&primitive_topology| {
+                let mut variant = PipelineVariant {
+                    primitive_topology,
+                    vertex_defines: vertex_defines.clone(),
+                    fragment_defines: fragment_defines.clone(),
+                };
+
+                if primitive_topology == wgpu::PrimitiveTopology::PointList {
+                    variant.vertex_defines.add("POINTS");
+                }
+
+                variant
+            })
+            .collect();
+
+        // The vertex shader is shared with the PBR material.
+        let pipelines = MaterialPipelines::new(
+            device,
+            pipeline_cache,
+            "pbr.vert",
+            "unlit.frag",
+            vec![
+                material_base::frame_bind_group_layout(device, pipeline_cache),
+                unlit_bind_group_layout,
+                object_bind_group_layout,
+                morph_target_bind_group_layout.clone(),
+            ],
+            vertex_attributes,
+            material_base::instance_attributes(),
+            colour_states,
+            depth_state,
+            variants,
+            true,
+        );
+
+        Self {
+            pipelines,
+            default_morph_target_bind_group,
+            unlit_bind_group,
+            morph_target_bind_group_layout,
+            is_skinned: params.is_skinned,
+            has_morph_targets: params.has_morph_targets,
+            properties: UnlitProperties {
+                base_colour_factor: params.base_colour_factor,
+                base_colour_texture: params.base_colour_texture.clone(),
+            },
+        }
+    }
+}
+
+impl MaterialBase for UnlitMaterial {
+    fn pipelines(&self) -> &MaterialPipelines {
+        &self.pipelines
+    }
+
+    fn unlit_properties(&self) -> Option<&UnlitProperties> {
+        Some(&self.properties)
+    }
+
+    fn set_bind_groups<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        frame_bind_group: &'a FrameBindGroup,
+        offsets: &ObjectOffsets,
+    ) {
+        let (object_bind_group, object_offsets) =
+            frame_bind_group.object_bind_group(self.is_skinned, self.has_morph_targets, offsets);
+
+        render_pass.set_bind_group(0, &frame_bind_group.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.unlit_bind_group, &[]);
+        render_pass.set_bind_group(2, object_bind_group, &object_offsets);
+        render_pass.set_bind_group(3, &self.default_morph_target_bind_group, &[]);
+    }
+}
@@ -1,8 +1,25 @@
+use super::*;
+use std::ops::Range;
+
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: Option<wgpu::Buffer>,
     pub num_vertices: u32,
     pub num_indices: u32,
+    pub primitive_topology: wgpu::PrimitiveTopology,
+    pub morph_target_buffer: Option<(wgpu::Buffer, wgpu::BufferAddress)>,
+    pub morph_target_bind_group: Option<wgpu::BindGroup>,
+    // Bounds of the vertices, before skinning and morphing.
+    pub bounding_box: Option<BoundingBox>,
+}
+
+// CPU-side mesh data, ready to be uploaded.
+#[derive(Clone)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub primitive_topology: wgpu::PrimitiveTopology,
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 #[repr(C)]
@@ -12,6 +29,10 @@ pub struct Vertex {
     pub normal: [f32; 3],
     pub tangent: [f32; 4], // tangent vector + bitangent sign.
     pub tex_coord: [f32; 2],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+    // Linear RGBA, multiplied with the material's base colour.
+    pub colour: [f32; 4],
 }
 
 impl Mesh {
@@ -23,8 +44,10 @@ impl Mesh {
             std::slice::from_raw_parts(ptr, len)
         };
 
-        let vertex_buffer =
-            device.create_buffer_with_data(vertex_data_bytes, wgpu::BufferUsage::VERTEX);
+        let vertex_buffer = device.create_buffer_with_data(
+            vertex_data_bytes,
+            wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_SRC,
+        );
 
         // Upload index buffer if it exists.
         let index_buffer = match index_data {
@@ -36,8 +59,10 @@ impl Mesh {
                     std::slice::from_raw_parts(ptr, len)
                 };
 
-                let buffer =
-                    device.create_buffer_with_data(index_data_bytes, wgpu::BufferUsage::INDEX);
+                let buffer = device.create_buffer_with_data(
+                    index_data_bytes,
+                    wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_SRC,
+                );
 
                 Some(buffer)
             }
@@ -53,19 +78,101 @@ impl Mesh {
             index_buffer: index_buffer,
             num_vertices: vertex_data.len() as u32,
             num_indices: num_indices as u32,
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            morph_target_buffer: None,
+            morph_target_bind_group: None,
+            bounding_box: BoundingBox::new_from_points(
+                vertex_data
+                    .iter()
+                    .map(|vertex| nalgebra::Point3::from(vertex.position)),
+            ),
+        }
+    }
+
+    pub fn new_from_data(device: &wgpu::Device, mesh_data: &MeshData) -> Self {
+        let mut mesh = Mesh::new(
+            device,
+            mesh_data.vertices.as_slice(),
+            Some(mesh_data.indices.as_slice()),
+        );
+
+        mesh.primitive_topology = mesh_data.primitive_topology;
+
+        if !mesh_data.morph_targets.is_empty() {
+            mesh.set_morph_targets(device, mesh_data.morph_targets.as_slice());
+        }
+
+        mesh
+    }
+
+    pub fn set_morph_targets(&mut self, device: &wgpu::Device, targets: &[MorphTarget]) {
+        let data = morph::pack_morph_targets(self.num_vertices as usize, targets);
+
+        let buffer = morph::create_morph_target_buffer(device, data.as_slice());
+
+        self.morph_target_buffer = Some((
+            buffer,
+            std::mem::size_of_val(data.as_slice()) as wgpu::BufferAddress,
+        ));
+        self.morph_target_bind_group = None;
+    }
+
+    // Copies the vertices and indices back from the GPU, for exporting.
+    pub fn read_back(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(Vec<Vertex>, Option<Vec<u32>>), String> {
+        let read_buffer = |buffer: &wgpu::Buffer, size: wgpu::BufferAddress| {
+            readback::read_back(device, queue, size, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(buffer, 0, staging_buffer, 0, size)
+            })
+        };
+
+        let vertex_size = std::mem::size_of::<Vertex>() as wgpu::BufferAddress;
+        let vertices = read_buffer(&self.vertex_buffer, self.num_vertices as u64 * vertex_size)?;
+
+        let indices = match &self.index_buffer {
+            Some(index_buffer) => Some(read_buffer(index_buffer, self.num_indices as u64 * 4)?),
+            None => None,
+        };
+
+        Ok((
+            readback::from_bytes(&vertices),
+            indices.map(|indices| readback::from_bytes(&indices)),
+        ))
+    }
+
+    // The bind group layout is owned by the material the mesh is drawn with.
+    pub fn create_morph_target_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) {
+        if let Some((buffer, size)) = &self.morph_target_buffer {
+            self.morph_target_bind_group = Some(morph::create_morph_target_bind_group(
+                device, layout, buffer, *size,
+            ));
         }
     }
 
-    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    // Draws the instances in the range, whose data is read from the bound instance buffer by
+    // instanced pipelines.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
         render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
 
+        // Morph targets are bound to the per-mesh set of the PBR pipeline.
+        if let Some(bind_group) = &self.morph_target_bind_group {
+            render_pass.set_bind_group(3, bind_group, &[]);
+        }
+
         match &self.index_buffer {
             Some(index_buffer) => {
                 render_pass.set_index_buffer(&index_buffer, 0, 0);
-                render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+                render_pass.draw_indexed(0..self.num_indices, 0, instances);
             }
             None => {
-                render_pass.draw(0..self.num_vertices, 0..1);
+                render_pass.draw(0..self.num_vertices, instances);
             }
         }
     }
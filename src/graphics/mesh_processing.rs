@@ -0,0 +1,795 @@
+use super::*;
+
+// Returns the list topology a glTF primitive mode is drawn with. Triangle strips and fans are
+// drawn as triangle lists, and line strips and loops as line lists.
+pub fn list_topology(mode: gltf::mesh::Mode) -> wgpu::PrimitiveTopology {
+    match mode {
+        gltf::mesh::Mode::Points => wgpu::PrimitiveTopology::PointList,
+        gltf::mesh::Mode::Lines | gltf::mesh::Mode::LineStrip | gltf::mesh::Mode::LineLoop => {
+            wgpu::PrimitiveTopology::LineList
+        }
+        gltf::mesh::Mode::Triangles
+        | gltf::mesh::Mode::TriangleStrip
+        | gltf::mesh::Mode::TriangleFan => wgpu::PrimitiveTopology::TriangleList,
+    }
+}
+
+// Converts the indices of a glTF primitive into the list topology returned by `list_topology`.
+pub fn convert_to_list(mode: gltf::mesh::Mode, indices: &[u32]) -> Vec<u32> {
+    match mode {
+        gltf::mesh::Mode::Points => indices.to_vec(),
+        gltf::mesh::Mode::Lines => indices[..indices.len() - indices.len() % 2].to_vec(),
+        gltf::mesh::Mode::LineStrip => line_strip_to_list(indices, false),
+        gltf::mesh::Mode::LineLoop => line_strip_to_list(indices, true),
+        gltf::mesh::Mode::Triangles => indices[..indices.len() - indices.len() % 3].to_vec(),
+        gltf::mesh::Mode::TriangleStrip => triangle_strip_to_list(indices),
+        gltf::mesh::Mode::TriangleFan => triangle_fan_to_list(indices),
+    }
+}
+
+pub fn line_strip_to_list(indices: &[u32], is_loop: bool) -> Vec<u32> {
+    let mut list = Vec::new();
+
+    for pair in indices.windows(2) {
+        list.extend_from_slice(pair);
+    }
+
+    if is_loop && indices.len() > 2 {
+        list.push(indices[indices.len() - 1]);
+        list.push(indices[0]);
+    }
+
+    list
+}
+
+pub fn triangle_strip_to_list(indices: &[u32]) -> Vec<u32> {
+    let mut list = Vec::new();
+
+    // Every other triangle is flipped to keep a consistent winding order.
+    for (i, tri) in indices.windows(3).enumerate() {
+        if i % 2 == 0 {
+            list.extend_from_slice(&[tri[0], tri[1], tri[2]]);
+        } else {
+            list.extend_from_slice(&[tri[0], tri[2], tri[1]]);
+        }
+    }
+
+    list
+}
+
+pub fn triangle_fan_to_list(indices: &[u32]) -> Vec<u32> {
+    let mut list = Vec::new();
+
+    if indices.len() < 3 {
+        return list;
+    }
+
+    for pair in indices[1..].windows(2) {
+        list.extend_from_slice(&[indices[0], pair[0], pair[1]]);
+    }
+
+    list
+}
+
+struct TangentGeometry<'a> {
+    vertices: &'a [Vertex],
+    indices: &'a [u32],
+    corner_tangents: Vec<[f32; 4]>,
+}
+
+impl<'a> TangentGeometry<'a> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl<'a> bevy_mikktspace::Geometry for TangentGeometry<'a> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coord
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = tangent;
+    }
+}
+
+// Generates MikkTSpace tangents for a triangle list, so normal maps baked by other tools match.
+// MikkTSpace works per face corner, so vertices shared by corners with different tangents are
+// split. Returns the original vertex of every vertex appended this way, so other per-vertex data
+// can be split to match.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    let mut geometry = TangentGeometry {
+        vertices,
+        indices,
+        corner_tangents: vec![[0.0; 4]; indices.len() - indices.len() % 3],
+    };
+
+    let is_generated = bevy_mikktspace::generate_tangents(&mut geometry);
+
+    let corner_tangents = geometry.corner_tangents;
+
+    let split_vertices = if is_generated {
+        split_corners(vertices, indices, &corner_tangents, |vertex, tangent| {
+            vertex.tangent = tangent
+        })
+    } else {
+        Vec::new()
+    };
+
+    // Degenerate UVs or normals can leave tangents undefined, so fall back to any tangent
+    // perpendicular to the normal.
+    for vertex in vertices.iter_mut() {
+        let tangent =
+            nalgebra::Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+
+        if !tangent.iter().all(|x| x.is_finite()) || tangent.norm_squared() < f32::EPSILON {
+            let tangent = perpendicular_tangent(vertex.normal.into());
+
+            vertex.tangent = [tangent.x, tangent.y, tangent.z, 1.0];
+        }
+    }
+
+    split_vertices
+}
+
+fn perpendicular_tangent(normal: nalgebra::Vector3<f32>) -> nalgebra::Vector3<f32> {
+    // Cross with the axis least aligned with the normal.
+    let axis = if normal.x.abs() < 0.9 {
+        nalgebra::Vector3::x()
+    } else {
+        nalgebra::Vector3::y()
+    };
+
+    match normal.cross(&axis).try_normalize(f32::EPSILON) {
+        Some(tangent) => tangent,
+        None => nalgebra::Vector3::x(),
+    }
+}
+
+// Writes a value per face corner into the vertices, splitting vertices shared by corners with
+// different values. Returns the original vertex of every vertex appended.
+fn split_corners<T: Copy + PartialEq>(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut [u32],
+    corner_values: &[T],
+    set_value: impl Fn(&mut Vertex, T),
+) -> Vec<u32> {
+    let mut vertex_values: Vec<Vec<(T, u32)>> = vec![Vec::new(); vertices.len()];
+    let mut split_vertices = Vec::new();
+
+    for (index, value) in indices.iter_mut().zip(corner_values) {
+        let i = *index as usize;
+
+        if let Some((_, split_index)) = vertex_values[i].iter().find(|(v, _)| v == value) {
+            *index = *split_index;
+        } else if vertex_values[i].is_empty() {
+            set_value(&mut vertices[i], *value);
+            vertex_values[i].push((*value, *index));
+        } else {
+            let mut vertex = vertices[i];
+            set_value(&mut vertex, *value);
+
+            *index = vertices.len() as u32;
+            vertex_values[i].push((*value, *index));
+
+            vertices.push(vertex);
+            split_vertices.push(i as u32);
+        }
+    }
+
+    split_vertices
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum NormalMode {
+    // Every triangle has its own normal, as the glTF spec requires for missing normals.
+    Flat,
+    // Normals are averaged across triangles sharing a position, weighted by the triangle's angle
+    // at the vertex. Triangles meeting at more than the crease angle, in radians, stay sharp.
+    Smooth { crease_angle: f32 },
+}
+
+// Generates normals for a triangle list. Like `generate_tangents`, vertices are split where
+// corners need different normals and the original vertex of every appended vertex is returned.
+pub fn generate_normals(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut [u32],
+    normal_mode: NormalMode,
+) -> Vec<u32> {
+    let num_corners = indices.len() - indices.len() % 3;
+
+    let position = |corner: usize| -> nalgebra::Vector3<f32> {
+        vertices[indices[corner] as usize].position.into()
+    };
+
+    let face_normals: Vec<nalgebra::Vector3<f32>> = (0..num_corners / 3)
+        .map(|face| {
+            let p0 = position(face * 3);
+            let p1 = position(face * 3 + 1);
+            let p2 = position(face * 3 + 2);
+
+            (p1 - p0)
+                .cross(&(p2 - p0))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(nalgebra::Vector3::zeros)
+        })
+        .collect();
+
+    let corner_normals: Vec<[f32; 3]> = match normal_mode {
+        NormalMode::Flat => (0..num_corners)
+            .map(|corner| face_normals[corner / 3])
+            .map(normal_or_up)
+            .collect(),
+        NormalMode::Smooth { crease_angle } => {
+            let min_cos = crease_angle.cos();
+
+            let corner_angle = |corner: usize| -> f32 {
+                let face = corner / 3 * 3;
+                let p = position(corner);
+                let e1 = position(face + (corner + 1) % 3) - p;
+                let e2 = position(face + (corner + 2) % 3) - p;
+
+                e1.angle(&e2)
+            };
+
+            // Group corners by position, so normals are smoothed across UV seams.
+            let mut position_corners: std::collections::HashMap<[u32; 3], Vec<usize>> =
+                std::collections::HashMap::new();
+
+            for corner in 0..num_corners {
+                let p = vertices[indices[corner] as usize].position;
+
+                position_corners
+                    .entry([p[0].to_bits(), p[1].to_bits(), p[2].to_bits()])
+                    .or_default()
+                    .push(corner);
+            }
+
+            (0..num_corners)
+                .map(|corner| {
+                    let p = vertices[indices[corner] as usize].position;
+                    let face_normal = face_normals[corner / 3];
+
+                    let normal = position_corners
+                        [&[p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]]
+                        .iter()
+                        .filter(|c| face_normals[**c / 3].dot(&face_normal) >= min_cos)
+                        .fold(nalgebra::Vector3::zeros(), |normal, c| {
+                            normal + face_normals[*c / 3] * corner_angle(*c)
+                        });
+
+                    match normal.try_normalize(f32::EPSILON) {
+                        Some(normal) => normal_or_up(normal),
+                        None => normal_or_up(face_normal),
+                    }
+                })
+                .collect()
+        }
+    };
+
+    split_corners(vertices, indices, &corner_normals, |vertex, normal| {
+        vertex.normal = normal
+    })
+}
+
+fn normal_or_up(normal: nalgebra::Vector3<f32>) -> [f32; 3] {
+    // Degenerate triangles have no normal, so give them one that won't produce NaNs when shaded.
+    if normal.norm_squared() > 0.0 {
+        [normal.x, normal.y, normal.z]
+    } else {
+        [0.0, 1.0, 0.0]
+    }
+}
+
+// Simplifies a triangle list towards the target number of indices, collapsing the edges with the
+// least quadric error first (Garland and Heckbert). Each collapse moves a vertex onto one of its
+// neighbours, so the vertices themselves are unchanged and skins and morph targets still apply.
+// Vertices on open edges are never moved, which also keeps UV and normal seams from cracking, as
+// their vertices are split.
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_index_count: usize) -> Vec<u32> {
+    let position = |vertex: u32| -> nalgebra::Vector3<f64> {
+        let p = vertices[vertex as usize].position;
+
+        nalgebra::Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+    };
+
+    let face_normal = |triangle: &[u32; 3]| -> nalgebra::Vector3<f64> {
+        let p0 = position(triangle[0]);
+
+        (position(triangle[1]) - p0).cross(&(position(triangle[2]) - p0))
+    };
+
+    let is_degenerate = |t: &[u32; 3]| -> bool { t[0] == t[1] || t[1] == t[2] || t[2] == t[0] };
+
+    let mut triangles: Vec<[u32; 3]> = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .filter(|t| !is_degenerate(t))
+        .collect();
+
+    // Each vertex's quadric sums the squared distances to the planes of its triangles, weighted
+    // by their area.
+    let mut quadrics = vec![nalgebra::Matrix4::<f64>::zeros(); vertices.len()];
+
+    for triangle in triangles.iter() {
+        let normal = face_normal(triangle);
+        let double_area = normal.norm();
+
+        if double_area <= 0.0 {
+            continue;
+        }
+
+        let normal = normal / double_area;
+        let plane = normal.push(-normal.dot(&position(triangle[0])));
+        let quadric = plane * plane.transpose() * (double_area * 0.5);
+
+        for &vertex in triangle.iter() {
+            quadrics[vertex as usize] += quadric;
+        }
+    }
+
+    // Edges that aren't shared by exactly two triangles are open or non-manifold.
+    let mut edge_counts: std::collections::HashMap<(u32, u32), u32> =
+        std::collections::HashMap::new();
+
+    for triangle in triangles.iter() {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+
+            *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+
+    let mut is_locked = vec![false; vertices.len()];
+
+    for (&(a, b), &count) in edge_counts.iter() {
+        if count != 2 {
+            is_locked[a as usize] = true;
+            is_locked[b as usize] = true;
+        }
+    }
+
+    let target_triangle_count = target_index_count / 3;
+
+    // Each pass collapses the cheapest edges whose neighbourhoods don't overlap, until the target
+    // is met or no edge can be collapsed.
+    while triangles.len() > target_triangle_count {
+        let mut vertex_triangles = vec![Vec::new(); vertices.len()];
+
+        for (index, triangle) in triangles.iter().enumerate() {
+            for &vertex in triangle.iter() {
+                vertex_triangles[vertex as usize].push(index);
+            }
+        }
+
+        let mut collapses = Vec::new();
+
+        for triangle in triangles.iter() {
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+
+                // Interior edges are visited once from each side.
+                if a > b {
+                    continue;
+                }
+
+                for &(from, to) in [(a, b), (b, a)].iter() {
+                    if !is_locked[from as usize] {
+                        let p = position(to).push(1.0);
+                        let quadric = quadrics[from as usize] + quadrics[to as usize];
+
+                        collapses.push((p.dot(&(quadric * p)), from, to));
+                    }
+                }
+            }
+        }
+
+        collapses.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut remap: Vec<u32> = (0..vertices.len() as u32).collect();
+        let mut is_touched = vec![false; vertices.len()];
+        let mut triangle_count = triangles.len();
+
+        for &(_, from, to) in collapses.iter() {
+            if triangle_count <= target_triangle_count {
+                break;
+            }
+
+            if is_touched[from as usize] || is_touched[to as usize] {
+                continue;
+            }
+
+            let from_triangles = &vertex_triangles[from as usize];
+
+            // Collapses that flip a triangle would fold the surface over itself.
+            let is_flipped = from_triangles
+                .iter()
+                .map(|&index| &triangles[index])
+                .filter(|triangle| !triangle.contains(&to))
+                .any(|triangle| {
+                    let mut moved = *triangle;
+
+                    for vertex in moved.iter_mut().filter(|vertex| **vertex == from) {
+                        *vertex = to;
+                    }
+
+                    face_normal(&moved).dot(&face_normal(triangle)) <= 0.0
+                });
+
+            if is_flipped {
+                continue;
+            }
+
+            remap[from as usize] = to;
+
+            let from_quadric = quadrics[from as usize];
+            quadrics[to as usize] += from_quadric;
+
+            triangle_count -= from_triangles
+                .iter()
+                .filter(|&&index| triangles[index].contains(&to))
+                .count();
+
+            for &index in from_triangles.iter() {
+                for &vertex in triangles[index].iter() {
+                    is_touched[vertex as usize] = true;
+                }
+            }
+        }
+
+        if triangle_count == triangles.len() {
+            break;
+        }
+
+        triangles = triangles
+            .iter()
+            .map(|t| {
+                [
+                    remap[t[0] as usize],
+                    remap[t[1] as usize],
+                    remap[t[2] as usize],
+                ]
+            })
+            .filter(|t| !is_degenerate(t))
+            .collect();
+    }
+
+    triangles.iter().flat_map(|t| t.iter().copied()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A vertex on the XY plane, facing +Z.
+    fn vertex(position: [f32; 3], tex_coord: [f32; 2]) -> Vertex {
+        Vertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            tangent: [0.0; 4],
+            tex_coord,
+            joints: [0; 4],
+            weights: [0.0; 4],
+            colour: [1.0; 4],
+        }
+    }
+
+    // Every tangent must be finite, unit length, perpendicular to its normal and have a sign.
+    fn check_tangents(vertices: &[Vertex]) {
+        for vertex in vertices.iter() {
+            let tangent =
+                nalgebra::Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+            let normal = nalgebra::Vector3::from(vertex.normal);
+
+            assert!(vertex.tangent.iter().all(|x| x.is_finite()));
+            assert!((tangent.norm() - 1.0).abs() < 1e-4, "{:?}", vertex.tangent);
+            assert!(tangent.dot(&normal).abs() < 1e-4, "{:?}", vertex.tangent);
+            assert_eq!(vertex.tangent[3].abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn follows_tex_coords() {
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+        ];
+        let mut indices = vec![0, 1, 2];
+
+        assert!(generate_tangents(&mut vertices, &mut indices).is_empty());
+
+        check_tangents(&vertices);
+
+        // V increases along -Y, so the bitangent, the normal crossed with the tangent and
+        // multiplied by its sign, has to point along -Y.
+        for vertex in vertices.iter() {
+            assert!((vertex.tangent[0] - 1.0).abs() < 1e-4);
+            assert_eq!(vertex.tangent[3], -1.0);
+        }
+    }
+
+    #[test]
+    fn handles_zero_area_tex_coords() {
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.5, 0.5]),
+            vertex([1.0, 0.0, 0.0], [0.5, 0.5]),
+            vertex([0.0, 1.0, 0.0], [0.5, 0.5]),
+        ];
+        let mut indices = vec![0, 1, 2];
+
+        generate_tangents(&mut vertices, &mut indices);
+
+        check_tangents(&vertices);
+        assert_eq!(indices.len(), 3);
+    }
+
+    #[test]
+    fn handles_missing_tex_coords() {
+        // Missing texture coordinates are read as zero.
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0; 2]),
+            vertex([1.0, 0.0, 0.0], [0.0; 2]),
+            vertex([1.0, 1.0, 0.0], [0.0; 2]),
+            vertex([0.0, 1.0, 0.0], [0.0; 2]),
+        ];
+        let mut indices = vec![0, 1, 2, 0, 2, 3];
+
+        generate_tangents(&mut vertices, &mut indices);
+
+        check_tangents(&vertices);
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn splits_mirrored_seams() {
+        // Two triangles share the edge along the Y axis, with the left one's texture mirrored.
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+            vertex([-1.0, 0.0, 0.0], [1.0, 1.0]),
+        ];
+        let mut indices = vec![0, 2, 1, 0, 1, 3];
+
+        let split_vertices = generate_tangents(&mut vertices, &mut indices);
+
+        check_tangents(&vertices);
+
+        // Both vertices on the seam are split, keeping their positions.
+        let mut sorted_split_vertices = split_vertices.clone();
+        sorted_split_vertices.sort_unstable();
+
+        assert_eq!(sorted_split_vertices, vec![0, 1]);
+        assert_eq!(vertices.len(), 6);
+
+        for (offset, &original) in split_vertices.iter().enumerate() {
+            assert_eq!(
+                vertices[4 + offset].position,
+                vertices[original as usize].position
+            );
+        }
+
+        // The right triangle's tangents point along +X, and the mirrored triangle's along -X
+        // with the opposite sign.
+        for &index in indices[..3].iter() {
+            let tangent = vertices[index as usize].tangent;
+
+            assert!((tangent[0] - 1.0).abs() < 1e-4, "{:?}", tangent);
+        }
+
+        for &index in indices[3..].iter() {
+            let tangent = vertices[index as usize].tangent;
+
+            assert!((tangent[0] + 1.0).abs() < 1e-4, "{:?}", tangent);
+        }
+
+        assert_eq!(
+            vertices[indices[0] as usize].tangent[3],
+            -vertices[indices[3] as usize].tangent[3]
+        );
+    }
+
+    // Two triangles meeting at a right angle along the X axis, like the edge of a cube, facing
+    // -Z and -Y.
+    fn cube_edge() -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 0.0, 1.0], [0.0, 0.0]),
+        ];
+
+        (vertices, vec![0, 2, 1, 0, 1, 3])
+    }
+
+    fn assert_normal(vertex: &Vertex, expected: [f32; 3]) {
+        let normal = nalgebra::Vector3::from(vertex.normal);
+
+        assert!(
+            (normal - nalgebra::Vector3::from(expected)).norm() < 1e-4,
+            "{:?}",
+            vertex.normal
+        );
+    }
+
+    #[test]
+    fn splits_flat_normals_per_face() {
+        let (mut vertices, mut indices) = cube_edge();
+
+        let mut split_vertices = generate_normals(&mut vertices, &mut indices, NormalMode::Flat);
+        split_vertices.sort_unstable();
+
+        // The two vertices on the shared edge are split, one copy per face.
+        assert_eq!(split_vertices, vec![0, 1]);
+        assert_eq!(vertices.len(), 6);
+
+        for &index in indices[..3].iter() {
+            assert_normal(&vertices[index as usize], [0.0, 0.0, -1.0]);
+        }
+
+        for &index in indices[3..].iter() {
+            assert_normal(&vertices[index as usize], [0.0, -1.0, 0.0]);
+        }
+
+        // Coplanar triangles share a normal, so nothing is split.
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 1.0, 0.0], [0.0, 0.0]),
+        ];
+        let mut indices = vec![0, 1, 2, 1, 3, 2];
+
+        assert!(generate_normals(&mut vertices, &mut indices, NormalMode::Flat).is_empty());
+        assert_eq!(vertices.len(), 4);
+
+        for vertex in vertices.iter() {
+            assert_normal(vertex, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn smooths_edges_within_crease_angle() {
+        let (mut vertices, mut indices) = cube_edge();
+
+        // The faces meet at 90 degrees, within a 100 degree crease angle, so the edge's vertices
+        // are shared and their normals averaged.
+        let split_vertices = generate_normals(
+            &mut vertices,
+            &mut indices,
+            NormalMode::Smooth {
+                crease_angle: 100.0f32.to_radians(),
+            },
+        );
+
+        assert!(split_vertices.is_empty());
+        assert_eq!(indices, vec![0, 2, 1, 0, 1, 3]);
+
+        let half_sqrt_2 = 0.5f32.sqrt();
+
+        assert_normal(&vertices[0], [0.0, -half_sqrt_2, -half_sqrt_2]);
+        assert_normal(&vertices[1], [0.0, -half_sqrt_2, -half_sqrt_2]);
+        assert_normal(&vertices[2], [0.0, 0.0, -1.0]);
+        assert_normal(&vertices[3], [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn keeps_edges_past_crease_angle_sharp() {
+        let (mut vertices, mut indices) = cube_edge();
+
+        // Past an 80 degree crease angle, the edge is split like flat normals.
+        let mut split_vertices = generate_normals(
+            &mut vertices,
+            &mut indices,
+            NormalMode::Smooth {
+                crease_angle: 80.0f32.to_radians(),
+            },
+        );
+        split_vertices.sort_unstable();
+
+        assert_eq!(split_vertices, vec![0, 1]);
+        assert_eq!(vertices.len(), 6);
+
+        for &index in indices[..3].iter() {
+            assert_normal(&vertices[index as usize], [0.0, 0.0, -1.0]);
+        }
+
+        for &index in indices[3..].iter() {
+            assert_normal(&vertices[index as usize], [0.0, -1.0, 0.0]);
+        }
+    }
+
+    // Open edges, each used by a single triangle.
+    fn boundary_edges(indices: &[u32]) -> std::collections::HashSet<(u32, u32)> {
+        let mut edge_counts = std::collections::HashMap::new();
+
+        for triangle in indices.chunks_exact(3) {
+            for corner in 0..3 {
+                let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+
+                *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        edge_counts
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|(edge, _)| edge)
+            .collect()
+    }
+
+    #[test]
+    fn simplifies_to_target_keeping_boundary() {
+        // A flat 9x9 grid of vertices, split into 128 triangles.
+        const SIZE: u32 = 9;
+
+        let vertices: Vec<Vertex> = (0..SIZE * SIZE)
+            .map(|i| vertex([(i % SIZE) as f32, (i / SIZE) as f32, 0.0], [0.0, 0.0]))
+            .collect();
+
+        let mut indices = Vec::new();
+
+        for y in 0..SIZE - 1 {
+            for x in 0..SIZE - 1 {
+                let i = y * SIZE + x;
+
+                indices.extend_from_slice(&[i, i + 1, i + SIZE, i + 1, i + SIZE + 1, i + SIZE]);
+            }
+        }
+
+        let target_index_count = indices.len() / 2;
+        let simplified = simplify(&vertices, &indices, target_index_count);
+
+        // Collapsing an interior vertex of the grid removes two triangles, so the target is met
+        // to within one triangle.
+        assert!(
+            simplified.len() <= target_index_count,
+            "{}",
+            simplified.len()
+        );
+        assert!(
+            simplified.len() + 3 >= target_index_count,
+            "{}",
+            simplified.len()
+        );
+
+        // The outline is unchanged, so every boundary vertex is still used.
+        assert_eq!(boundary_edges(&simplified), boundary_edges(&indices));
+
+        for i in 0..SIZE * SIZE {
+            let (x, y) = (i % SIZE, i / SIZE);
+
+            if x == 0 || y == 0 || x == SIZE - 1 || y == SIZE - 1 {
+                assert!(simplified.contains(&i), "{}", i);
+            }
+        }
+
+        // Every triangle still faces +Z.
+        for triangle in simplified.chunks_exact(3) {
+            let p = |i: u32| nalgebra::Vector3::from(vertices[i as usize].position);
+            let normal =
+                (p(triangle[1]) - p(triangle[0])).cross(&(p(triangle[2]) - p(triangle[0])));
+
+            assert!(normal.z > 0.0, "{:?}", triangle);
+        }
+    }
+}
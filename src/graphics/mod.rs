@@ -1,29 +1,67 @@
+pub mod animation;
+pub mod asset_loader;
+pub mod asset_server;
+pub mod basis;
+pub mod benchmark;
 pub mod camera;
+pub mod frustum;
+pub mod gltf_export;
+pub mod hot_reload;
+pub mod image_import;
 pub mod light;
+pub mod lod;
 pub mod material_base;
+pub mod material_extensions;
 pub mod material_hdr;
 pub mod material_pbr;
 pub mod material_skybox;
+pub mod material_unlit;
 pub mod mesh;
+pub mod mesh_processing;
+pub mod morph;
+pub mod obj;
+pub mod pipeline_cache;
+pub mod ply;
 pub mod pose;
+pub mod readback;
 pub mod render_loop;
 pub mod render_state;
 pub mod render_system;
 pub mod renderable;
+pub mod scene;
+pub mod shaders;
+pub mod skin;
 pub mod skybox;
 pub mod texture;
+pub mod upload_ring;
 
+pub use animation::*;
+pub use asset_loader::*;
+pub use asset_server::*;
+pub use benchmark::*;
 pub use camera::*;
+pub use frustum::*;
+pub use gltf_export::*;
+pub use hot_reload::*;
+pub use image_import::*;
 pub use light::*;
+pub use lod::*;
 pub use material_base::*;
+pub use material_extensions::*;
 pub use material_hdr::*;
 pub use material_pbr::*;
 pub use material_skybox::*;
+pub use material_unlit::*;
 pub use mesh::*;
+pub use morph::*;
+pub use pipeline_cache::*;
 pub use pose::*;
 pub use render_loop::*;
 pub use render_state::*;
 pub use render_system::*;
 pub use renderable::*;
+pub use scene::*;
+pub use skin::*;
 pub use skybox::*;
 pub use texture::*;
+pub use upload_ring::*;
@@ -0,0 +1,106 @@
+use specs::prelude::*;
+
+pub const MAX_MORPH_TARGETS: usize = 64;
+
+// Per-vertex displacements for a single blend shape.
+#[derive(Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tangents: Vec<[f32; 3]>,
+}
+
+pub struct MorphWeights {
+    pub weights: Vec<f32>,
+}
+
+impl Component for MorphWeights {
+    type Storage = VecStorage<Self>;
+}
+
+// The morph weights bound for an object, packed four to a vector and padded with zeros.
+pub type PackedMorphWeights = [[f32; 4]; MAX_MORPH_TARGETS / 4];
+
+pub fn pack_morph_weights(weights: &[f32]) -> PackedMorphWeights {
+    let mut packed_weights = [[0.0; 4]; MAX_MORPH_TARGETS / 4];
+
+    for (i, weight) in weights.iter().take(MAX_MORPH_TARGETS).enumerate() {
+        packed_weights[i / 4][i % 4] = *weight;
+    }
+
+    packed_weights
+}
+
+// Extends morph targets to match vertices appended by splitting, given the original vertex of each.
+pub fn split_morph_targets(targets: &mut [MorphTarget], split_vertices: &[u32]) {
+    for target in targets.iter_mut() {
+        for deltas in &mut [
+            &mut target.positions,
+            &mut target.normals,
+            &mut target.tangents,
+        ] {
+            if deltas.is_empty() {
+                continue;
+            }
+
+            for i in split_vertices.iter() {
+                let delta = deltas.get(*i as usize).copied().unwrap_or([0.0; 3]);
+                deltas.push(delta);
+            }
+        }
+    }
+}
+
+// Packs morph targets into the layout read by the vertex shader: a header holding the target
+// count, followed by position, normal and tangent deltas for every target of every vertex.
+pub fn pack_morph_targets(num_vertices: usize, targets: &[MorphTarget]) -> Vec<[f32; 4]> {
+    let num_targets = targets.len().min(MAX_MORPH_TARGETS);
+
+    let mut data = Vec::with_capacity(1 + num_vertices * num_targets * 3);
+
+    data.push([f32::from_bits(num_targets as u32), 0.0, 0.0, 0.0]);
+
+    let delta = |deltas: &Vec<[f32; 3]>, i: usize| match deltas.get(i) {
+        Some(d) => [d[0], d[1], d[2], 0.0],
+        None => [0.0; 4],
+    };
+
+    for i in 0..num_vertices {
+        for target in targets.iter().take(num_targets) {
+            data.push(delta(&target.positions, i));
+            data.push(delta(&target.normals, i));
+            data.push(delta(&target.tangents, i));
+        }
+    }
+
+    data
+}
+
+pub fn create_morph_target_buffer(device: &wgpu::Device, data: &[[f32; 4]]) -> wgpu::Buffer {
+    let data_bytes = unsafe {
+        let len = std::mem::size_of_val(data);
+        let ptr = data.as_ptr() as *const u8;
+        std::slice::from_raw_parts(ptr, len)
+    };
+
+    device.create_buffer_with_data(data_bytes, wgpu::BufferUsage::STORAGE_READ)
+}
+
+pub fn create_morph_target_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    size: wgpu::BufferAddress,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &[wgpu::Binding {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer {
+                buffer,
+                range: 0..size,
+            },
+        }],
+        label: Some("morph_target_bind_group"),
+    })
+}
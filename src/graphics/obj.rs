@@ -0,0 +1,372 @@
+use super::*;
+use std::collections::HashMap;
+
+// Everything read from an OBJ file before it's uploaded to the GPU. Nothing here touches the
+// device, so files can be imported on a worker thread.
+pub struct ObjImport {
+    // The meshes drawn with each material, or with the default material.
+    pub material_meshes: Vec<(Option<tobj::Material>, Vec<MeshData>)>,
+    pub textures: ObjTextures,
+}
+
+// The textures used by an OBJ file's materials, decoded with the file.
+#[derive(Default)]
+pub struct ObjTextures {
+    // Textures are referenced relative to the OBJ file.
+    pub directory: std::path::PathBuf,
+    // Decoded images by their paths. Images that couldn't be decoded are left out.
+    pub images: HashMap<std::path::PathBuf, ImageData>,
+}
+
+impl ObjTextures {
+    // Texture options such as `-bm 1.0` come before the file name.
+    pub fn path(&self, texture_name: &str) -> Option<std::path::PathBuf> {
+        texture_name
+            .split_whitespace()
+            .last()
+            .map(|file_name| self.directory.join(file_name))
+    }
+}
+
+// Reads the meshes, materials and textures of an OBJ file.
+pub fn import_obj(path: &std::path::Path) -> Result<ObjImport, String> {
+    let (models, materials) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        },
+    )
+    .map_err(|err| format!("Failed to load {:?}: {}", path, err))?;
+
+    let materials = match materials {
+        Ok(materials) => materials,
+        Err(err) => {
+            println!("Failed to load materials for {:?}: {}", path, err);
+            Vec::new()
+        }
+    };
+
+    let mut material_meshes: Vec<(Option<usize>, Vec<MeshData>)> = Vec::new();
+
+    for model in models.iter() {
+        let material_id = model.mesh.material_id.filter(|id| *id < materials.len());
+
+        let mesh_data = obj::read_obj_mesh(&model.mesh);
+
+        match material_meshes
+            .iter_mut()
+            .find(|(id, _)| *id == material_id)
+        {
+            Some((_, meshes)) => meshes.push(mesh_data),
+            None => material_meshes.push((material_id, vec![mesh_data])),
+        }
+    }
+
+    let mut textures = ObjTextures {
+        directory: path
+            .parent()
+            .unwrap_or_else(|| std::path::Path::new(""))
+            .to_owned(),
+        images: HashMap::new(),
+    };
+
+    let texture_paths = material_meshes
+        .iter()
+        .filter_map(|(material_id, _)| material_id.map(|id| &materials[id]))
+        .flat_map(|material| obj::texture_names(material).to_vec())
+        .flatten()
+        .filter_map(|texture_name| textures.path(texture_name))
+        .collect::<Vec<std::path::PathBuf>>();
+
+    for texture_path in texture_paths {
+        if textures.images.contains_key(&texture_path) {
+            continue;
+        }
+
+        match std::fs::read(&texture_path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| image_import::decode_image(&data))
+        {
+            Ok(image) => {
+                textures.images.insert(texture_path, image);
+            }
+            Err(err) => println!("Failed to load texture {:?}: {}", texture_path, err),
+        }
+    }
+
+    Ok(ObjImport {
+        material_meshes: material_meshes
+            .into_iter()
+            .map(|(material_id, meshes)| (material_id.map(|id| materials[id].clone()), meshes))
+            .collect(),
+        textures,
+    })
+}
+
+// The albedo, emissive and normal textures of a material.
+fn texture_names(material: &tobj::Material) -> [Option<&String>; 3] {
+    let non_empty = |name| Some(name).filter(|name: &&String| !name.is_empty());
+
+    [
+        non_empty(&material.diffuse_texture),
+        material.unknown_param.get("map_Ke"),
+        non_empty(&material.normal_texture).or_else(|| material.unknown_param.get("norm")),
+    ]
+}
+
+impl Renderable {
+    // Each material in the OBJ file becomes its own renderable.
+    pub fn new_from_obj(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        queue: &wgpu::Queue,
+        import: &ObjImport,
+        skybox: &Skybox,
+        asset_server: &mut AssetServer,
+    ) -> Vec<Self> {
+        import
+            .material_meshes
+            .iter()
+            .map(|(material, mesh_data)| {
+                let meshes = mesh_data
+                    .iter()
+                    .map(|mesh_data| Mesh::new_from_data(device, mesh_data))
+                    .collect();
+
+                let pbr_params = obj::create_pbr_params(
+                    device,
+                    queue,
+                    material.as_ref(),
+                    &import.textures,
+                    skybox,
+                    asset_server,
+                );
+
+                Renderable::new_pbr(device, pipeline_cache, sc_desc, meshes, pbr_params, None)
+            })
+            .collect()
+    }
+}
+
+pub fn read_obj_mesh(obj_mesh: &tobj::Mesh) -> MeshData {
+    let num_vertices = obj_mesh.positions.len() / 3;
+
+    let mut vertices = Vec::with_capacity(num_vertices);
+
+    for i in 0..num_vertices {
+        let normal = if obj_mesh.normals.len() >= (i + 1) * 3 {
+            [
+                obj_mesh.normals[i * 3],
+                obj_mesh.normals[i * 3 + 1],
+                obj_mesh.normals[i * 3 + 2],
+            ]
+        } else {
+            [0.0; 3]
+        };
+
+        // OBJ texture coordinates start at the bottom of the image.
+        let tex_coord = if obj_mesh.texcoords.len() >= (i + 1) * 2 {
+            [
+                obj_mesh.texcoords[i * 2],
+                1.0 - obj_mesh.texcoords[i * 2 + 1],
+            ]
+        } else {
+            [0.0; 2]
+        };
+
+        vertices.push(Vertex {
+            position: [
+                obj_mesh.positions[i * 3],
+                obj_mesh.positions[i * 3 + 1],
+                obj_mesh.positions[i * 3 + 2],
+            ],
+            normal,
+            tangent: [0.0; 4],
+            tex_coord,
+            joints: [0; 4],
+            weights: [0.0; 4],
+            colour: [1.0; 4],
+        });
+    }
+
+    let mut indices = obj_mesh.indices.clone();
+
+    if obj_mesh.normals.is_empty() {
+        mesh_processing::generate_normals(
+            &mut vertices,
+            &mut indices,
+            mesh_processing::NormalMode::Smooth {
+                crease_angle: 60.0f32.to_radians(),
+            },
+        );
+    }
+
+    mesh_processing::generate_tangents(&mut vertices, &mut indices);
+
+    MeshData {
+        vertices,
+        indices,
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        morph_targets: Vec::new(),
+    }
+}
+
+// Maps MTL parameters onto the metallic-roughness model. The PBR extension parameters (Pr, Pm and
+// Ke) are used when present, otherwise roughness is estimated from the specular exponent.
+pub fn create_pbr_params<'a>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material: Option<&tobj::Material>,
+    obj_textures: &ObjTextures,
+    skybox: &'a Skybox,
+    asset_server: &mut AssetServer,
+) -> PbrBindGroup<'a> {
+    let mut textures = Vec::new();
+
+    let mut texture_property =
+        |texture_name: Option<&String>, image_format: wgpu::TextureFormat, factor: [f32; 4]| {
+            match texture_name
+                .and_then(|texture_name| obj_textures.path(texture_name))
+                .and_then(|path| {
+                    obj::load_texture(
+                        device,
+                        queue,
+                        &path,
+                        obj_textures.images.get(&path),
+                        image_format,
+                        asset_server,
+                    )
+                }) {
+                Some(texture) => {
+                    textures.push(texture);
+                    MaterialProperty {
+                        texture_id: Some(textures.len() - 1),
+                        factor: None,
+                    }
+                }
+                None => MaterialProperty {
+                    texture_id: None,
+                    factor: Some(factor),
+                },
+            }
+        };
+
+    let param = |name: &str| material.and_then(|material| material.unknown_param.get(name));
+
+    let param_floats = |name: &str| -> Option<Vec<f32>> {
+        param(name).map(|value| {
+            value
+                .split_whitespace()
+                .filter_map(|x| x.parse().ok())
+                .collect()
+        })
+    };
+
+    let albedo_factor = match material {
+        Some(material) => [
+            material.diffuse[0],
+            material.diffuse[1],
+            material.diffuse[2],
+            material.dissolve,
+        ],
+        None => [0.8, 0.8, 0.8, 1.0],
+    };
+
+    let metallic = match param_floats("Pm") {
+        Some(values) if !values.is_empty() => values[0],
+        _ => 0.0,
+    };
+
+    let roughness = match (param_floats("Pr"), material) {
+        (Some(values), _) if !values.is_empty() => values[0],
+        (_, Some(material)) => (2.0 / (material.shininess.max(0.0) + 2.0)).sqrt(),
+        (_, None) => 1.0,
+    };
+
+    let emissive_factor = match param_floats("Ke") {
+        Some(values) if values.len() >= 3 => [values[0], values[1], values[2], 1.0],
+        _ => [0.0, 0.0, 0.0, 1.0],
+    };
+
+    let [albedo_texture, emissive_texture, normal_texture] = match material {
+        Some(material) => obj::texture_names(material),
+        None => [None; 3],
+    };
+
+    PbrBindGroup {
+        ao_property: texture_property(None, wgpu::TextureFormat::Rgba8Unorm, [1.0, 1.0, 1.0, 1.0]),
+        albedo_property: texture_property(
+            albedo_texture,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            albedo_factor,
+        ),
+        emissive_property: texture_property(
+            emissive_texture,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            emissive_factor,
+        ),
+        // Separate roughness and metallic maps aren't packed into a single texture, so only the
+        // factors are used.
+        metal_roughness_property: texture_property(
+            None,
+            wgpu::TextureFormat::Rgba8Unorm,
+            [
+                0.0,
+                metallic.clamp(0.0, 1.0),
+                roughness.clamp(0.0, 1.0),
+                0.0,
+            ],
+        ),
+        normal_property: texture_property(
+            normal_texture,
+            wgpu::TextureFormat::Rgba8Unorm,
+            [0.0; 4],
+        ),
+        extension_properties: PbrExtensionProperties::default(),
+        irradiance_map: &skybox.irradiance_map,
+        prefiltered_environment_map: &skybox.prefiltered_environment_map,
+        brdf_lut: &skybox.brdf_lut,
+        textures,
+        is_skinned: false,
+        has_morph_targets: false,
+        primitive_topologies: Vec::new(),
+    }
+}
+
+// Uploads a texture decoded with an OBJ file, or shares one already uploaded from the same path.
+pub fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &std::path::Path,
+    image: Option<&ImageData>,
+    image_format: wgpu::TextureFormat,
+    asset_server: &mut AssetServer,
+) -> Option<Handle<Texture>> {
+    // Textures are shared by every material using the same file.
+    let key = AssetServer::hash_key(&(AssetServer::path_key(path), image_format));
+
+    if let Some(texture) = asset_server.cached_texture(key) {
+        return Some(texture);
+    }
+
+    let image = image?;
+
+    Some(asset_server.texture(key, || {
+        let (image_format, mip_levels) = image.texture_levels(image_format);
+        let mip_levels: Vec<&[u8]> = mip_levels.iter().map(Vec::as_slice).collect();
+
+        Texture::new_texture_from_mips(
+            device,
+            queue,
+            image.width,
+            image.height,
+            &mip_levels,
+            image_format,
+            wgpu::AddressMode::Repeat,
+        )
+    }))
+}
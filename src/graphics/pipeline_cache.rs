@@ -0,0 +1,77 @@
+use super::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
+// The binding, visibility and type of each entry in a bind group layout. wgpu's entries can't be
+// compared, so layouts are keyed by their fields.
+pub type BindGroupLayoutKey = Vec<(u32, wgpu::ShaderStage, wgpu::BindingType)>;
+
+// A bind group layout, with the entries it was created from.
+pub struct CachedBindGroupLayout {
+    pub key: BindGroupLayoutKey,
+    pub layout: wgpu::BindGroupLayout,
+}
+
+// Everything a pipeline is built from. The compiled shaders stand in for their sources and
+// defines, and the colour and depth states include the target formats.
+#[derive(PartialEq, Eq, Hash)]
+pub struct RenderPipelineKey {
+    pub bind_group_layouts: Vec<BindGroupLayoutKey>,
+    pub vertex_spirv: Vec<u32>,
+    pub fragment_spirv: Option<Vec<u32>>,
+    pub vertex_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+    pub instance_attributes: Vec<wgpu::VertexAttributeDescriptor>,
+    pub primitive_topology: wgpu::PrimitiveTopology,
+    pub colour_states: Vec<wgpu::ColorStateDescriptor>,
+    pub depth_state: Option<wgpu::DepthStencilStateDescriptor>,
+}
+
+// The bind group layouts and pipelines created on a device, owned alongside it in the
+// `RenderState`. wgpu only binds a bind group to pipelines whose layouts were created with the
+// same bind group layout object, so layouts are shared by their entries rather than created by
+// each material. Only weak references are kept, so pipelines are freed along with the last
+// material using them.
+#[derive(Default)]
+pub struct PipelineCache {
+    bind_group_layouts: Mutex<HashMap<BindGroupLayoutKey, Weak<CachedBindGroupLayout>>>,
+    render_pipelines: Mutex<HashMap<RenderPipelineKey, Weak<wgpu::RenderPipeline>>>,
+}
+
+impl PipelineCache {
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Handle<CachedBindGroupLayout> {
+        let key: BindGroupLayoutKey = entries
+            .iter()
+            .map(|entry| (entry.binding, entry.visibility, entry.ty))
+            .collect();
+
+        let mut bind_group_layouts = self.bind_group_layouts.lock().unwrap();
+
+        asset_server::get_or_insert(&mut bind_group_layouts, key.clone(), || {
+            Arc::new(CachedBindGroupLayout {
+                key,
+                layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    bindings: entries,
+                    label: None,
+                }),
+            })
+        })
+    }
+
+    // Returns the pipeline cached under the key, or creates it.
+    pub fn render_pipeline<F>(
+        &self,
+        key: RenderPipelineKey,
+        create: F,
+    ) -> Handle<wgpu::RenderPipeline>
+    where
+        F: FnOnce() -> wgpu::RenderPipeline,
+    {
+        let mut render_pipelines = self.render_pipelines.lock().unwrap();
+
+        asset_server::get_or_insert(&mut render_pipelines, key, || Arc::new(create()))
+    }
+}
@@ -0,0 +1,561 @@
+use super::*;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<PlyType, String> {
+        Ok(match name {
+            "char" | "int8" => PlyType::Int8,
+            "uchar" | "uint8" => PlyType::UInt8,
+            "short" | "int16" => PlyType::Int16,
+            "ushort" | "uint16" => PlyType::UInt16,
+            "int" | "int32" => PlyType::Int32,
+            "uint" | "uint32" => PlyType::UInt32,
+            "float" | "float32" => PlyType::Float32,
+            "double" | "float64" => PlyType::Float64,
+            _ => return Err(format!("Unsupported PLY property type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::Int8 | PlyType::UInt8 => 1,
+            PlyType::Int16 | PlyType::UInt16 => 2,
+            PlyType::Int32 | PlyType::UInt32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    // The type of the list length, for list properties.
+    count_type: Option<PlyType>,
+    value_type: PlyType,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+    // The values of every property of every element, lists flattened.
+    rows: Vec<Vec<Vec<f64>>>,
+}
+
+impl PlyElement {
+    fn property_index(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+struct PlyReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    format: PlyFormat,
+}
+
+impl<'a> PlyReader<'a> {
+    fn next_token(&mut self) -> Result<&'a str, String> {
+        while self.offset < self.data.len() && self.data[self.offset].is_ascii_whitespace() {
+            self.offset += 1;
+        }
+
+        let start = self.offset;
+
+        while self.offset < self.data.len() && !self.data[self.offset].is_ascii_whitespace() {
+            self.offset += 1;
+        }
+
+        std::str::from_utf8(&self.data[start..self.offset])
+            .map_err(|_| "Invalid PLY data".to_owned())
+    }
+
+    fn read_value(&mut self, value_type: PlyType) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            let token = self.next_token()?;
+
+            if token.is_empty() {
+                return Err("Unexpected end of PLY data".to_owned());
+            }
+
+            return token
+                .parse()
+                .map_err(|_| format!("Invalid PLY value {}", token));
+        }
+
+        let size = value_type.size();
+
+        if self.offset + size > self.data.len() {
+            return Err("Unexpected end of PLY data".to_owned());
+        }
+
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(&self.data[self.offset..self.offset + size]);
+        self.offset += size;
+
+        if self.format == PlyFormat::BinaryBigEndian {
+            bytes[..size].reverse();
+        }
+
+        Ok(match value_type {
+            PlyType::Int8 => bytes[0] as i8 as f64,
+            PlyType::UInt8 => bytes[0] as f64,
+            PlyType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyType::UInt16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyType::Int32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            PlyType::UInt32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            PlyType::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            PlyType::Float64 => f64::from_le_bytes(bytes),
+        })
+    }
+}
+
+fn read_elements(data: &[u8]) -> Result<Vec<PlyElement>, String> {
+    let header_end = data
+        .windows(b"end_header".len())
+        .position(|window| window == b"end_header")
+        .ok_or_else(|| "Missing PLY header".to_owned())?;
+
+    let header =
+        std::str::from_utf8(&data[..header_end]).map_err(|_| "Invalid PLY header".to_owned())?;
+
+    let mut lines = header.lines();
+
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("Not a PLY file".to_owned());
+    }
+
+    let mut format = PlyFormat::Ascii;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            ["format", "ascii", ..] => format = PlyFormat::Ascii,
+            ["format", "binary_little_endian", ..] => format = PlyFormat::BinaryLittleEndian,
+            ["format", "binary_big_endian", ..] => format = PlyFormat::BinaryBigEndian,
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("Invalid PLY element count {}", count))?,
+                properties: Vec::new(),
+                rows: Vec::new(),
+            }),
+            ["property", "list", count_type, value_type, name] => elements
+                .last_mut()
+                .ok_or_else(|| "PLY property without element".to_owned())?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    count_type: Some(PlyType::parse(count_type)?),
+                    value_type: PlyType::parse(value_type)?,
+                }),
+            ["property", value_type, name] => elements
+                .last_mut()
+                .ok_or_else(|| "PLY property without element".to_owned())?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    count_type: None,
+                    value_type: PlyType::parse(value_type)?,
+                }),
+            _ => {}
+        }
+    }
+
+    // Body data starts after the end of the header line.
+    let mut offset = header_end + b"end_header".len();
+
+    while offset < data.len() && data[offset] != b'\n' {
+        offset += 1;
+    }
+
+    let mut reader = PlyReader {
+        data,
+        offset: offset + 1,
+        format,
+    };
+
+    for element in elements.iter_mut() {
+        for _ in 0..element.count {
+            let row = element
+                .properties
+                .iter()
+                .map(|property| match property.count_type {
+                    Some(count_type) => {
+                        let count = reader.read_value(count_type)? as usize;
+
+                        (0..count)
+                            .map(|_| reader.read_value(property.value_type))
+                            .collect()
+                    }
+                    None => Ok(vec![reader.read_value(property.value_type)?]),
+                })
+                .collect::<Result<_, String>>()?;
+
+            element.rows.push(row);
+        }
+    }
+
+    Ok(elements)
+}
+
+// Reads PLY vertices, with their colours, and faces. Files without faces, such as scanned point
+// clouds, are drawn as points.
+pub fn read_ply(data: &[u8]) -> Result<MeshData, String> {
+    let elements = read_elements(data)?;
+
+    let vertex_element = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .ok_or_else(|| "PLY file has no vertices".to_owned())?;
+
+    let property = |names: &[&str]| vertex_element.property_index(names);
+
+    let position_ids = [property(&["x"]), property(&["y"]), property(&["z"])];
+    let normal_ids = [property(&["nx"]), property(&["ny"]), property(&["nz"])];
+    let tex_coord_ids = [
+        property(&["u", "s", "texture_u", "texture_s"]),
+        property(&["v", "t", "texture_v", "texture_t"]),
+    ];
+
+    let colour_ids = [
+        property(&["red", "r", "diffuse_red"]),
+        property(&["green", "g", "diffuse_green"]),
+        property(&["blue", "b", "diffuse_blue"]),
+        property(&["alpha", "a", "diffuse_alpha"]),
+    ];
+
+    let has_normals = normal_ids.iter().all(Option::is_some);
+    let has_tex_coords = tex_coord_ids.iter().all(Option::is_some);
+
+    // List properties in place of a value are read as zero.
+    let value = |row: &Vec<Vec<f64>>, id: Option<usize>| match id {
+        Some(id) => row[id].first().copied().unwrap_or(0.0) as f32,
+        None => 0.0,
+    };
+
+    // Integer colours range up to their type's maximum, and floating point colours up to 1.
+    // Colours are stored in sRGB, except for alpha.
+    let colour = |row: &Vec<Vec<f64>>, channel: usize| match colour_ids[channel] {
+        Some(id) => {
+            let scale = match vertex_element.properties[id].value_type {
+                PlyType::UInt8 => 255.0,
+                PlyType::UInt16 => 65535.0,
+                _ => 1.0,
+            };
+            let value = (value(row, Some(id)) / scale).clamp(0.0, 1.0);
+
+            if channel < 3 {
+                value.powf(2.2)
+            } else {
+                value
+            }
+        }
+        None => 1.0,
+    };
+
+    let mut vertices: Vec<Vertex> = vertex_element
+        .rows
+        .iter()
+        .map(|row| Vertex {
+            position: [
+                value(row, position_ids[0]),
+                value(row, position_ids[1]),
+                value(row, position_ids[2]),
+            ],
+            normal: [
+                value(row, normal_ids[0]),
+                value(row, normal_ids[1]),
+                value(row, normal_ids[2]),
+            ],
+            tangent: [0.0; 4],
+            // PLY texture coordinates start at the bottom of the image.
+            tex_coord: if has_tex_coords {
+                [
+                    value(row, tex_coord_ids[0]),
+                    1.0 - value(row, tex_coord_ids[1]),
+                ]
+            } else {
+                [0.0; 2]
+            },
+            joints: [0; 4],
+            weights: [0.0; 4],
+            colour: [
+                colour(row, 0),
+                colour(row, 1),
+                colour(row, 2),
+                colour(row, 3),
+            ],
+        })
+        .collect();
+
+    let face_element = elements.iter().find(|element| element.name == "face");
+
+    let face_indices_id = face_element
+        .and_then(|element| element.property_index(&["vertex_indices", "vertex_index"]));
+
+    let (mut indices, primitive_topology) = match (face_element, face_indices_id) {
+        (Some(face_element), Some(id)) if face_element.count > 0 => {
+            let mut indices = Vec::new();
+
+            // Polygons are triangulated as fans.
+            for row in face_element.rows.iter() {
+                let polygon: Vec<u32> = row[id].iter().map(|index| *index as u32).collect();
+
+                // Drop polygons referencing missing vertices.
+                if polygon
+                    .iter()
+                    .all(|index| (*index as usize) < vertices.len())
+                {
+                    indices.extend(mesh_processing::triangle_fan_to_list(&polygon));
+                }
+            }
+
+            (indices, wgpu::PrimitiveTopology::TriangleList)
+        }
+        _ => (
+            (0..vertices.len() as u32).collect(),
+            wgpu::PrimitiveTopology::PointList,
+        ),
+    };
+
+    if primitive_topology == wgpu::PrimitiveTopology::TriangleList {
+        // Scans are usually smooth surfaces.
+        if !has_normals {
+            mesh_processing::generate_normals(
+                &mut vertices,
+                &mut indices,
+                mesh_processing::NormalMode::Smooth {
+                    crease_angle: std::f32::consts::PI,
+                },
+            );
+        }
+
+        mesh_processing::generate_tangents(&mut vertices, &mut indices);
+    }
+
+    Ok(MeshData {
+        vertices,
+        indices,
+        primitive_topology,
+        morph_targets: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+
+    // A quad with a red, green, blue and white corner, in the given binary byte order.
+    fn binary_quad(format: &str, to_bytes: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        let mut data = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+
+        let colours = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+        for (position, colour) in POSITIONS.iter().zip(colours.iter()) {
+            for value in position.iter() {
+                data.extend(to_bytes(&value.to_le_bytes()));
+            }
+
+            data.extend_from_slice(colour);
+        }
+
+        data.push(4);
+
+        for index in 0..4i32 {
+            data.extend(to_bytes(&index.to_le_bytes()));
+        }
+
+        data
+    }
+
+    // The positions of each triangle's corners, as vertices may be split by tangent generation.
+    fn triangles(mesh_data: &MeshData) -> Vec<[[f32; 3]; 3]> {
+        mesh_data
+            .indices
+            .chunks(3)
+            .map(|triangle| {
+                [
+                    mesh_data.vertices[triangle[0] as usize].position,
+                    mesh_data.vertices[triangle[1] as usize].position,
+                    mesh_data.vertices[triangle[2] as usize].position,
+                ]
+            })
+            .collect()
+    }
+
+    fn check_quad(mesh_data: &MeshData) {
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::TriangleList
+        );
+        assert_eq!(
+            triangles(mesh_data),
+            vec![
+                [POSITIONS[0], POSITIONS[1], POSITIONS[2]],
+                [POSITIONS[0], POSITIONS[2], POSITIONS[3]],
+            ]
+        );
+
+        let colour_at = |position: [f32; 3]| {
+            mesh_data
+                .vertices
+                .iter()
+                .find(|vertex| vertex.position == position)
+                .unwrap()
+                .colour
+        };
+
+        assert_eq!(colour_at(POSITIONS[0]), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colour_at(POSITIONS[1]), [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(colour_at(POSITIONS[2]), [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(colour_at(POSITIONS[3]), [1.0; 4]);
+    }
+
+    #[test]
+    fn reads_ascii() {
+        let data = format!(
+            "ply\nformat ascii 1.0\n{}{}",
+            HEADER, "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n"
+        );
+
+        check_quad(&read_ply(data.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_little_endian() {
+        let data = binary_quad("binary_little_endian", |bytes| bytes.to_vec());
+
+        check_quad(&read_ply(&data).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_big_endian() {
+        let data = binary_quad("binary_big_endian", |bytes| {
+            bytes.iter().rev().copied().collect()
+        });
+
+        check_quad(&read_ply(&data).unwrap());
+    }
+
+    #[test]
+    fn reads_float_colours_as_srgb() {
+        let data = "ply
+format ascii 1.0
+element vertex 1
+property float x
+property float y
+property float z
+property float red
+property float green
+property float blue
+property float alpha
+end_header
+0 0 0 0.5 1 0 0.5
+";
+
+        let mesh_data = read_ply(data.as_bytes()).unwrap();
+        let colour = mesh_data.vertices[0].colour;
+
+        assert!((colour[0] - 0.5f32.powf(2.2)).abs() < 1e-6);
+        assert_eq!(&colour[1..], &[1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn reads_point_clouds() {
+        let data = "ply
+format ascii 1.0
+element vertex 2
+property float x
+property float y
+property float z
+end_header
+1 2 3
+4 5 6
+";
+
+        let mesh_data = read_ply(data.as_bytes()).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::PointList
+        );
+        assert_eq!(mesh_data.indices, vec![0, 1]);
+        assert_eq!(mesh_data.vertices[1].position, [4.0, 5.0, 6.0]);
+        assert_eq!(mesh_data.vertices[1].colour, [1.0; 4]);
+    }
+
+    #[test]
+    fn drops_faces_with_missing_vertices() {
+        let data = format!(
+            "ply\nformat ascii 1.0\n{}{}",
+            HEADER, "0 0 0 0 0 0\n1 0 0 0 0 0\n1 1 0 0 0 0\n0 1 0 0 0 0\n3 0 1 4\n"
+        );
+
+        assert!(read_ply(data.as_bytes()).unwrap().indices.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let errors = [
+            "format ascii 1.0\nelement vertex 0\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n",
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty half x\nend_header\n0\n",
+            "ply\nformat ascii 1.0\nproperty float x\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex one\nend_header\n",
+            "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n0\n",
+            "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nend_header\nx\n",
+            "ply\nformat ascii 1.0\nelement face 0\nend_header\n",
+        ];
+
+        for data in errors.iter() {
+            assert!(read_ply(data.as_bytes()).is_err(), "{}", data);
+        }
+
+        // Binary files cut off part way through a value.
+        let data = binary_quad("binary_little_endian", |bytes| bytes.to_vec());
+
+        assert!(read_ply(&data[..data.len() - 2]).is_err());
+    }
+}
@@ -1,9 +1,47 @@
 use specs::prelude::*;
 
+// The model matrix can scale each axis differently, as glTF node transforms can.
 pub struct Pose {
-    pub model_matrix: nalgebra::Similarity3<f32>,
+    pub model_matrix: nalgebra::Affine3<f32>,
 }
 
 impl Component for Pose {
     type Storage = VecStorage<Self>;
 }
+
+impl Pose {
+    pub fn new(model_matrix: nalgebra::Similarity3<f32>) -> Self {
+        Self {
+            model_matrix: nalgebra::convert(model_matrix),
+        }
+    }
+
+    // The largest scale along any axis, so a bounding sphere's radius scaled by it still bounds
+    // the transformed model.
+    pub fn max_scale(&self) -> f32 {
+        let matrix = self.model_matrix.matrix();
+
+        (0..3)
+            .map(|column| {
+                matrix
+                    .fixed_slice::<nalgebra::U3, nalgebra::U1>(0, column)
+                    .norm()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    // The position and orientation without scale, which place lights and cameras.
+    pub fn isometry(&self) -> nalgebra::Isometry3<f32> {
+        let matrix = self.model_matrix.matrix();
+
+        let translation =
+            nalgebra::Translation3::new(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+        let basis = nalgebra::Matrix3::from_fn(|row, column| matrix[(row, column)]);
+        let rotation = nalgebra::Rotation3::from_matrix(&basis);
+
+        nalgebra::Isometry3::from_parts(
+            translation,
+            nalgebra::UnitQuaternion::from_rotation_matrix(&rotation),
+        )
+    }
+}
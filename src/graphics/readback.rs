@@ -0,0 +1,43 @@
+// Copies GPU data into a staging buffer of the given size with the commands recorded by
+// `record_copy`, and waits for the copy to finish. This stalls the device, so it's only used when
+// exporting, which lets meshes and textures drop their data once it's uploaded.
+pub fn read_back<F>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: wgpu::BufferAddress,
+    record_copy: F,
+) -> Result<Vec<u8>, String>
+where
+    F: FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+{
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback_buffer"),
+        size,
+        usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("readback_encoder"),
+    });
+
+    record_copy(&mut encoder, &staging_buffer);
+
+    queue.submit(&[encoder.finish()]);
+
+    let mapping = staging_buffer.map_read(0, size);
+
+    device.poll(wgpu::Maintain::Wait);
+
+    let mapping = futures::executor::block_on(mapping)
+        .map_err(|_| "Failed to read data back from the GPU".to_owned())?;
+
+    Ok(mapping.as_slice().to_vec())
+}
+
+// Reinterprets bytes read back from a buffer as the values uploaded to it.
+pub fn from_bytes<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(std::mem::size_of::<T>())
+        .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const T) })
+        .collect()
+}
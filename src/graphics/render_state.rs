@@ -6,6 +6,8 @@ pub struct RenderState {
     surface: wgpu::Surface,
     pub swap_chain_desc: wgpu::SwapChainDescriptor,
     pub swap_chain: wgpu::SwapChain,
+    // Bind group layouts and pipelines belong to the device they were created on.
+    pub pipeline_cache: super::PipelineCache,
 }
 
 impl RenderState {
@@ -50,6 +52,7 @@ impl RenderState {
             surface,
             swap_chain_desc,
             swap_chain,
+            pipeline_cache: Default::default(),
         }
     }
 
@@ -1,20 +1,104 @@
 use super::*;
 use specs::prelude::*;
+use std::collections::HashMap;
 
 pub struct RenderSystem;
 
+// Counts from the last frame drawn.
+#[derive(Default, Debug)]
+pub struct RenderStats {
+    pub objects: usize,
+    // Objects outside the camera's view, which weren't drawn.
+    pub culled_objects: usize,
+    pub draw_calls: usize,
+    pub instances: usize,
+    // Objects drawn at each level of detail.
+    pub lod_objects: Vec<usize>,
+    // Draw calls in the depth pre-pass, as well as those counted in `draw_calls`.
+    pub depth_prepass_draw_calls: usize,
+}
+
+// How frames are drawn, read every frame.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    // Draws the depth of opaque objects before shading them, so only the nearest surface of each
+    // pixel is shaded.
+    pub depth_prepass: bool,
+    // Sorts draws by distance and by pipeline and material, rather than the order they're joined.
+    pub sort_draws: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            depth_prepass: false,
+            sort_draws: true,
+        }
+    }
+}
+
+// One draw of a renderable, with the distance from the camera and model matrix of each instance.
+struct Draw<'a> {
+    renderable: &'a Renderable,
+    lod: usize,
+    render_pipelines: RenderPipelines,
+    offsets: ObjectOffsets,
+    instances: Vec<(f32, nalgebra::Matrix4<f32>)>,
+}
+
+impl<'a> Draw<'a> {
+    // The distance to the nearest instance.
+    fn distance(&self) -> f32 {
+        self.instances
+            .iter()
+            .map(|(distance, _)| *distance)
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    // Draws sharing a key share their pipelines and material bind group.
+    fn state_key(&self) -> (usize, usize) {
+        let pipeline = match self.render_pipelines.colour.first() {
+            Some((_, render_pipeline)) => {
+                &**render_pipeline as *const wgpu::RenderPipeline as usize
+            }
+            None => 0,
+        };
+
+        (pipeline, self.renderable.material_key())
+    }
+
+    fn draw_calls(&self) -> usize {
+        self.renderable.lods()[self.lod].meshes.len()
+    }
+}
+
+fn compare_distance(a: f32, b: f32) -> std::cmp::Ordering {
+    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+}
+
 pub struct RenderSystemData {
     depth_texture: Texture,
+    // The camera, lights and object uniforms, shared by every material.
+    frame_bind_group: FrameBindGroup,
+    upload_ring: UploadRing,
+    // The level of detail each object was drawn at last frame.
+    lod_levels: HashMap<Entity, usize>,
+    // Set while the scene has more lights than are drawn, so that's only reported once.
+    has_too_many_lights: bool,
 }
 
 impl<'a> System<'a> for RenderSystem {
     type SystemData = (
+        Entities<'a>,
         WriteExpect<'a, RenderState>,
         ReadExpect<'a, Camera>,
-        ReadExpect<'a, RenderSystemData>,
+        WriteExpect<'a, RenderSystemData>,
         ReadStorage<'a, Light>,
         ReadStorage<'a, Pose>,
         ReadStorage<'a, Renderable>,
+        ReadStorage<'a, MorphWeights>,
+        Read<'a, RenderSettings>,
+        Write<'a, RenderStats>,
     );
 
     fn setup(&mut self, world: &mut World) {
@@ -32,14 +116,42 @@ impl<'a> System<'a> for RenderSystem {
                 wgpu::TextureFormat::Depth32Float,
             );
 
-            render_system_data = RenderSystemData { depth_texture };
+            let frame_bind_group =
+                FrameBindGroup::new(&render_state.device, &render_state.pipeline_cache);
+
+            render_system_data = RenderSystemData {
+                depth_texture,
+                frame_bind_group,
+                upload_ring: UploadRing::new(),
+                lod_levels: HashMap::new(),
+                has_too_many_lights: false,
+            };
         }
 
         world.insert(render_system_data);
     }
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut render_state, camera, render_system_data, light, pose, renderable) = data;
+        let (
+            entities,
+            mut render_state,
+            camera,
+            mut render_system_data,
+            light,
+            pose,
+            renderable,
+            morph_weights,
+            render_settings,
+            mut render_stats,
+        ) = data;
+
+        let RenderSystemData {
+            depth_texture,
+            frame_bind_group,
+            upload_ring,
+            lod_levels,
+            has_too_many_lights,
+        } = &mut *render_system_data;
 
         // Start new command buffer.
         let frame = render_state
@@ -54,79 +166,261 @@ impl<'a> System<'a> for RenderSystem {
                     label: Some("Render Encoder"),
                 });
 
-        // Clear frame.
-        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                attachment: &frame.view,
-                resolve_target: None,
-                load_op: wgpu::LoadOp::Clear,
-                store_op: wgpu::StoreOp::Store,
-                clear_color: wgpu::Color::BLACK,
-            }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                attachment: &render_system_data.depth_texture.view,
-                depth_load_op: wgpu::LoadOp::Clear,
-                depth_store_op: wgpu::StoreOp::Store,
-                clear_depth: 1.0,
-                stencil_load_op: wgpu::LoadOp::Clear,
-                stencil_store_op: wgpu::StoreOp::Store,
-                clear_stencil: 0,
-            }),
-        });
-
         // Upload lighting data.
-        let mut light_positions = Vec::new();
+        let mut lights = Vec::new();
 
-        for (pose, _) in (&pose, &light).join() {
-            light_positions.push(pose.model_matrix.isometry.translation.vector);
+        for (pose, light) in (&pose, &light).join() {
+            lights.push(LightData::new(light, pose));
+        }
+
+        if lights.len() > MAX_LIGHTS && !*has_too_many_lights {
+            println!(
+                "Scene has {} lights, only the first {} will be used",
+                lights.len(),
+                MAX_LIGHTS
+            );
         }
 
-        let lighting_data = LightingBindGroup {
-            position: light_positions[0],
-            _padding: 0,
+        *has_too_many_lights = lights.len() > MAX_LIGHTS;
+
+        let lighting_data = LightingBindGroup::new(&lights);
+
+        // Upload camera data.
+        let camera_data = CameraBindGroup {
+            view_matrix: camera.view_matrix.to_homogeneous(),
+            proj_matrix: camera.proj_matrix.to_homogeneous(),
         };
 
-        for (pose, renderable) in (&pose, &renderable).join() {
-            // Upload transform data.
-            let transform_data = TransformBindGroup {
-                model_matrix: pose.model_matrix.to_homogeneous(),
-                view_matrix: camera.view_matrix.to_homogeneous(),
-                proj_matrix: camera.proj_matrix.to_homogeneous(),
-                camera_world_position: camera.view_matrix.inverse().translation.vector,
+        // Gather every object's uniforms and instances, so they're uploaded together. Objects
+        // sharing meshes and a material are batched into one instanced draw.
+        let mut object_uniforms = ObjectUniforms::new();
+        let mut draws: Vec<Draw> = Vec::new();
+        let mut instanced_draws: HashMap<(usize, usize, usize), usize> = HashMap::new();
+
+        // Objects out of view forget their level, so they pick one afresh when they return.
+        let previous_lod_levels = std::mem::take(lod_levels);
+
+        let frustum = Frustum::new(&(camera_data.proj_matrix * camera_data.view_matrix));
+
+        *render_stats = RenderStats::default();
+
+        for (entity, pose, renderable, morph_weights) in
+            (&entities, &pose, &renderable, morph_weights.maybe()).join()
+        {
+            let model_matrix = pose.model_matrix.to_homogeneous();
+
+            render_stats.objects += 1;
+
+            // Objects are culled by their bounding sphere, then by their box if the sphere is
+            // partly in view.
+            if let Some(bounding_box) = renderable.culling_bounds() {
+                let (centre, radius) = bounding_box.bounding_sphere();
+
+                let is_visible = frustum.intersects_sphere(
+                    &pose.model_matrix.transform_point(&centre),
+                    radius * pose.max_scale(),
+                ) && frustum
+                    .intersects_box(&bounding_box.transform(&model_matrix));
+
+                if !is_visible {
+                    render_stats.culled_objects += 1;
+                    continue;
+                }
+            }
+
+            let lod =
+                renderable.select_lod(&camera, pose, previous_lod_levels.get(&entity).copied());
+
+            lod_levels.insert(entity, lod);
+
+            if render_stats.lod_objects.len() <= lod {
+                render_stats.lod_objects.resize(lod + 1, 0);
+            }
+
+            render_stats.lod_objects[lod] += 1;
+
+            // Objects drawn regardless of the view, like the skybox, surround the camera, so
+            // they're sorted behind everything else.
+            let distance = match renderable.bounding_box() {
+                Some(bounding_box) if renderable.frustum_culled => {
+                    let centre = pose.model_matrix.transform_point(&bounding_box.centre());
+
+                    (camera.view_matrix * centre).coords.norm()
+                }
+                _ => f32::INFINITY,
             };
 
-            // Render the object.
-            let render_pass_desc = wgpu::RenderPassDescriptor {
+            let instancing_key = renderable.instancing_key(lod);
+
+            if let Some(&draw_index) = instancing_key
+                .as_ref()
+                .and_then(|key| instanced_draws.get(key))
+            {
+                draws[draw_index].instances.push((distance, model_matrix));
+                continue;
+            }
+
+            if let Some(key) = instancing_key {
+                instanced_draws.insert(key, draws.len());
+            }
+
+            draws.push(Draw {
+                renderable,
+                lod,
+                // Cloned so the pipelines outlive the material's lock while the pass is recorded.
+                render_pipelines: renderable.material.pipelines().read().clone(),
+                offsets: renderable.write_uniforms(&mut object_uniforms, morph_weights),
+                instances: vec![(distance, model_matrix)],
+            });
+        }
+
+        // Opaque objects drawn front to back hide the surfaces behind them from the depth test
+        // before they're shaded. After a depth pre-pass only the nearest surfaces pass anyway, so
+        // draws are grouped by pipeline and material instead, to change state less often.
+        if render_settings.sort_draws {
+            for draw in draws.iter_mut() {
+                draw.instances
+                    .sort_by(|(a, _), (b, _)| compare_distance(*a, *b));
+            }
+
+            if render_settings.depth_prepass {
+                draws.sort_by(|a, b| {
+                    a.state_key()
+                        .cmp(&b.state_key())
+                        .then_with(|| compare_distance(a.distance(), b.distance()))
+                });
+            } else {
+                draws.sort_by(|a, b| {
+                    compare_distance(a.distance(), b.distance())
+                        .then_with(|| a.state_key().cmp(&b.state_key()))
+                });
+            }
+        }
+
+        // The pre-pass draws the objects with depth-only pipelines, front to back.
+        let mut depth_prepass_draws: Vec<usize> = if render_settings.depth_prepass {
+            (0..draws.len())
+                .filter(|&index| !draws[index].render_pipelines.depth.is_empty())
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        if render_settings.sort_draws {
+            depth_prepass_draws
+                .sort_by(|&a, &b| compare_distance(draws[a].distance(), draws[b].distance()));
+        }
+
+        let mut instances = Vec::new();
+        let mut instance_ranges = Vec::new();
+
+        render_stats.draw_calls = draws.iter().map(Draw::draw_calls).sum();
+        render_stats.depth_prepass_draw_calls = depth_prepass_draws
+            .iter()
+            .map(|&index| draws[index].draw_calls())
+            .sum();
+
+        for draw in draws.iter() {
+            let first_instance = instances.len() as u32;
+
+            instances.extend(
+                draw.instances
+                    .iter()
+                    .map(|&(_, model_matrix)| InstanceData { model_matrix }),
+            );
+
+            instance_ranges.push(first_instance..instances.len() as u32);
+        }
+
+        render_stats.instances = instances.len();
+
+        frame_bind_group.update(
+            &render_state.device,
+            &render_state.pipeline_cache,
+            &mut encoder,
+            upload_ring,
+            &camera_data,
+            &lighting_data,
+            &object_uniforms,
+            &instances,
+        );
+
+        if !depth_prepass_draws.is_empty() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &depth_texture.view,
+                    depth_load_op: wgpu::LoadOp::Clear,
+                    depth_store_op: wgpu::StoreOp::Store,
+                    clear_depth: 1.0,
+                    stencil_load_op: wgpu::LoadOp::Clear,
+                    stencil_store_op: wgpu::StoreOp::Store,
+                    clear_stencil: 0,
+                }),
+            });
+
+            render_pass.set_vertex_buffer(1, &frame_bind_group.instance_buffer, 0, 0);
+
+            for &index in depth_prepass_draws.iter() {
+                let draw = &draws[index];
+
+                draw.renderable.render(
+                    &mut render_pass,
+                    &draw.render_pipelines.depth,
+                    frame_bind_group,
+                    &draw.offsets,
+                    draw.lod,
+                    instance_ranges[index].clone(),
+                );
+            }
+        }
+
+        // Shade every object in one render pass, keeping the pre-pass's depth if there was one.
+        {
+            let depth_load_op = if depth_prepass_draws.is_empty() {
+                wgpu::LoadOp::Clear
+            } else {
+                wgpu::LoadOp::Load
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                     attachment: &frame.view,
                     resolve_target: None,
-                    load_op: wgpu::LoadOp::Load,
+                    load_op: wgpu::LoadOp::Clear,
                     store_op: wgpu::StoreOp::Store,
                     clear_color: wgpu::Color::BLACK,
                 }],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: &render_system_data.depth_texture.view,
-                    depth_load_op: wgpu::LoadOp::Load,
+                    attachment: &depth_texture.view,
+                    depth_load_op,
                     depth_store_op: wgpu::StoreOp::Store,
                     clear_depth: 1.0,
                     stencil_load_op: wgpu::LoadOp::Clear,
                     stencil_store_op: wgpu::StoreOp::Store,
                     clear_stencil: 0,
                 }),
-            };
+            });
 
-            renderable.render(
-                &render_state,
-                &render_pass_desc,
-                &mut encoder,
-                &transform_data,
-                &lighting_data,
-            );
+            render_pass.set_vertex_buffer(1, &frame_bind_group.instance_buffer, 0, 0);
+
+            for (draw, instance_range) in draws.iter().zip(instance_ranges) {
+                draw.renderable.render(
+                    &mut render_pass,
+                    &draw.render_pipelines.colour,
+                    frame_bind_group,
+                    &draw.offsets,
+                    draw.lod,
+                    instance_range,
+                );
+            }
         }
 
         // Submit command buffer to the render queue.
         let command_buffer = encoder.finish();
 
         render_state.queue.submit(&[command_buffer]);
+
+        upload_ring.finish_frame();
     }
 }
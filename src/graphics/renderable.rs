@@ -1,9 +1,17 @@
 use super::*;
 use specs::prelude::*;
+use std::ops::Range;
 
+// Meshes and materials are shared between renderables loaded from the same file.
 pub struct Renderable {
-    meshes: Vec<Mesh>,
-    pub material: Box<dyn MaterialBase + Send + Sync>,
+    // The levels of detail, from most to least detailed.
+    lods: Handle<Vec<Lod>>,
+    pub material: MaterialHandle,
+    pub skin: Option<Skin>,
+    // Whether the renderable is skipped when outside the camera's view. The skybox surrounds the
+    // camera, so it's always drawn.
+    pub frustum_culled: bool,
+    bounding_box: Option<BoundingBox>,
 }
 
 impl Component for Renderable {
@@ -11,64 +19,315 @@ impl Component for Renderable {
 }
 
 impl Renderable {
+    // Adds the object's joints and morph weights to the frame's uniforms.
+    pub fn write_uniforms(
+        &self,
+        object_uniforms: &mut ObjectUniforms,
+        morph_weights: Option<&MorphWeights>,
+    ) -> ObjectOffsets {
+        let joints = self
+            .skin
+            .as_ref()
+            .map(|skin| object_uniforms.push(&skin.joint_matrices()));
+
+        let morph_weights = if self.has_morph_targets() {
+            let morph_weight_data = match morph_weights {
+                Some(morph_weights) => morph::pack_morph_weights(&morph_weights.weights),
+                None => morph::pack_morph_weights(&[]),
+            };
+
+            Some(object_uniforms.push(&morph_weight_data))
+        } else {
+            None
+        };
+
+        ObjectOffsets {
+            joints,
+            morph_weights,
+        }
+    }
+
+    // Renderables sharing meshes and a material can be drawn as instances of one draw, unless
+    // they're skinned or morphed, as their joints and morph weights are bound per object.
+    pub fn instancing_key(&self, lod: usize) -> Option<(usize, usize, usize)> {
+        if self.skin.is_some() || self.has_morph_targets() {
+            return None;
+        }
+
+        let lods = &*self.lods as *const Vec<Lod> as usize;
+
+        Some((lods, lod, self.material_key()))
+    }
+
+    // Identifies the material, which may be shared with other renderables.
+    pub fn material_key(&self) -> usize {
+        &*self.material as *const (dyn MaterialBase + Send + Sync) as *const u8 as usize
+    }
+
+    fn has_morph_targets(&self) -> bool {
+        self.meshes()
+            .iter()
+            .any(|mesh| mesh.morph_target_buffer.is_some())
+    }
+
+    // Picks the level of detail to draw from the renderable's size on screen, keeping the previous
+    // frame's level near a threshold.
+    pub fn select_lod(&self, camera: &Camera, pose: &Pose, previous_level: Option<usize>) -> usize {
+        match &self.bounding_box {
+            Some(bounding_box) if self.lods.len() > 1 => {
+                let (centre, radius) = bounding_box.bounding_sphere();
+
+                let screen_size = lod::projected_screen_size(
+                    camera,
+                    &pose.model_matrix.transform_point(&centre),
+                    radius * pose.max_scale(),
+                );
+
+                lod::select_lod(&self.lods, screen_size, previous_level)
+            }
+            _ => 0,
+        }
+    }
+
+    // Draws the instances of the level's meshes with the material's colour or depth pipelines,
+    // taken from the material before the render pass so a shader reload can swap them while the
+    // frame is recorded.
     pub fn render<'a>(
         &'a self,
-        render_state: &RenderState,
-        render_pass_desc: &wgpu::RenderPassDescriptor,
-        encoder: &mut wgpu::CommandEncoder,
-        transform_data: &TransformBindGroup,
-        lighting_data: &LightingBindGroup,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        render_pipelines: &'a [(wgpu::PrimitiveTopology, Handle<wgpu::RenderPipeline>)],
+        frame_bind_group: &'a FrameBindGroup,
+        offsets: &ObjectOffsets,
+        lod: usize,
+        instances: Range<u32>,
     ) {
-        let mut render_pass = self.material.begin_render_pass(
-            &render_state.device,
-            encoder,
-            render_pass_desc,
-            transform_data,
-            lighting_data,
-        );
+        self.material
+            .set_bind_groups(render_pass, frame_bind_group, offsets);
+
+        // Materials that can draw several primitive topologies switch pipelines per mesh.
+        for mesh in self.lods[lod].meshes.iter() {
+            let render_pipeline = render_pipelines
+                .iter()
+                .find(|(topology, _)| *topology == mesh.primitive_topology)
+                .or_else(|| render_pipelines.first());
 
-        for mesh in self.meshes.iter() {
-            mesh.draw(&mut render_pass);
+            if let Some((_, render_pipeline)) = render_pipeline {
+                render_pass.set_pipeline(render_pipeline);
+            }
+
+            mesh.draw(render_pass, instances.clone());
         }
     }
 
+    // The most detailed meshes.
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.lods[0].meshes
+    }
+
+    pub fn lods(&self) -> &[Lod] {
+        &self.lods
+    }
+
+    pub fn lod_handle(&self) -> &Handle<Vec<Lod>> {
+        &self.lods
+    }
+
     pub fn new(meshes: Vec<Mesh>, material: Box<dyn MaterialBase + Send + Sync>) -> Self {
-        Self { meshes, material }
+        Renderable::new_with_lods(vec![Lod::new(meshes)], material)
+    }
+
+    pub fn new_with_lods(lods: Vec<Lod>, material: Box<dyn MaterialBase + Send + Sync>) -> Self {
+        Renderable::new_shared(Handle::new(lods), material.into())
+    }
+
+    // The levels are culled and sized by the bounds of the most detailed one.
+    pub fn new_shared(lods: Handle<Vec<Lod>>, material: MaterialHandle) -> Self {
+        let bounding_box = lods[0]
+            .meshes
+            .iter()
+            .filter_map(|mesh| mesh.bounding_box)
+            .fold(None, |bounding_box: Option<BoundingBox>, mesh_box| {
+                Some(match bounding_box {
+                    Some(bounding_box) => bounding_box.union(&mesh_box),
+                    None => mesh_box,
+                })
+            });
+
+        Self {
+            lods,
+            material,
+            skin: None,
+            frustum_culled: true,
+            bounding_box,
+        }
+    }
+
+    // The bounds of the most detailed meshes' vertices, in model space.
+    pub fn bounding_box(&self) -> Option<&BoundingBox> {
+        self.bounding_box.as_ref()
+    }
+
+    // The bounds to cull the renderable by, in model space. Skinned and morphed renderables can
+    // move outside the bounds of their vertices, so they're never culled.
+    pub fn culling_bounds(&self) -> Option<&BoundingBox> {
+        if !self.frustum_culled || self.skin.is_some() || self.has_morph_targets() {
+            return None;
+        }
+
+        self.bounding_box.as_ref()
     }
 
     pub fn new_from_single_mesh(mesh: Mesh, material: Box<dyn MaterialBase + Send + Sync>) -> Self {
-        let mut meshes = Vec::new();
+        Self::new(vec![mesh], material)
+    }
 
-        meshes.push(mesh);
+    pub fn import_gltf(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        queue: &wgpu::Queue,
+        import: &GltfImport,
+        skybox: &Skybox,
+        asset_server: &mut AssetServer,
+    ) -> Result<Self, String> {
+        // Models that are already loaded share their meshes, material and textures.
+        if let Some(mut renderable) = asset_server.renderable(import.key) {
+            renderable.skin = Renderable::read_skin(import)?;
 
-        Self::new(meshes, material)
+            return Ok(renderable);
+        }
+
+        let renderable = Renderable::create_from_gltf(
+            device,
+            pipeline_cache,
+            sc_desc,
+            queue,
+            import,
+            skybox,
+            asset_server,
+        )?;
+
+        asset_server.add_renderable(import.key, &renderable);
+
+        Ok(renderable)
+    }
+
+    // Only the first skin in the file is supported. The vertex shader indexes the joint matrices
+    // by each vertex's joints, so skins with more joints than it holds, or vertices naming joints
+    // the skin doesn't have, are rejected.
+    fn read_skin(import: &GltfImport) -> Result<Option<Skin>, String> {
+        let gltf_skin = match import.document.skins().next() {
+            Some(gltf_skin) => gltf_skin,
+            None => return Ok(None),
+        };
+
+        let skin = Skin::new_from_gltf(&import.document, &gltf_skin, &import.buffers)?;
+
+        if skin.joints.len() > MAX_JOINTS {
+            return Err(format!(
+                "Skin {} has {} joints, more than the {} supported",
+                gltf_skin.index(),
+                skin.joints.len(),
+                MAX_JOINTS
+            ));
+        }
+
+        let joint_count = skin.joints.len() as u32;
+
+        let has_missing_joints = import
+            .lods
+            .iter()
+            .flat_map(|lod_data| lod_data.meshes.iter())
+            .flat_map(|mesh_data| mesh_data.vertices.iter())
+            .any(|vertex| vertex.joints.iter().any(|&joint| joint >= joint_count));
+
+        if has_missing_joints {
+            return Err(format!(
+                "Vertices use joints outside the {} of skin {}",
+                joint_count,
+                gltf_skin.index()
+            ));
+        }
+
+        Ok(Some(skin))
     }
 
-    fn import_gltf(
+    fn create_from_gltf(
         device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
         sc_desc: &wgpu::SwapChainDescriptor,
         queue: &wgpu::Queue,
-        gltf: &gltf::Document,
-        buffers: &Vec<gltf::buffer::Data>,
-        images: &Vec<gltf::image::Data>,
+        import: &GltfImport,
         skybox: &Skybox,
-    ) -> Self {
-        let mut meshes = Vec::new();
+        asset_server: &mut AssetServer,
+    ) -> Result<Self, String> {
+        let gltf = &import.document;
+        let images = &import.images;
+        let extensions = &import.extensions;
+
+        let lods = import
+            .lods
+            .iter()
+            .map(|lod_data| Lod {
+                meshes: lod_data
+                    .meshes
+                    .iter()
+                    .map(|mesh_data| Mesh::new_from_data(device, mesh_data))
+                    .collect(),
+                min_screen_size: lod_data.min_screen_size,
+            })
+            .collect();
+
         let mut textures = Vec::new();
 
-        for mesh in gltf.meshes() {
-            meshes.push(Renderable::create_mesh(&device, &mesh, &buffers));
-        }
+        let skin = Renderable::read_skin(import)?;
+
         let mat = gltf.materials().next().unwrap();
 
-        let pbr_params = PbrBindGroup {
+        if mat.unlit() {
+            let pbr_metallic_roughness = mat.pbr_metallic_roughness();
+
+            let unlit_params = UnlitBindGroup {
+                base_colour_factor: pbr_metallic_roughness.base_color_factor(),
+                base_colour_texture: pbr_metallic_roughness.base_color_texture().map(
+                    |gltf_texture| {
+                        Renderable::create_texture(
+                            device,
+                            queue,
+                            image_import::texture_image(
+                                &gltf_texture.texture(),
+                                extensions,
+                                images,
+                            ),
+                            wgpu::TextureFormat::Rgba8UnormSrgb,
+                            asset_server,
+                        )
+                    },
+                ),
+                is_skinned: false,
+                has_morph_targets: false,
+                primitive_topologies: Vec::new(),
+            };
+
+            return Ok(Renderable::new_unlit_with_lods(
+                device,
+                pipeline_cache,
+                sc_desc,
+                lods,
+                unlit_params,
+                skin,
+            ));
+        }
+
+        let mut pbr_params = PbrBindGroup {
             ao_property: match mat.occlusion_texture() {
                 Some(gltf_texture) => {
                     textures.push(Renderable::create_texture(
-                        &device,
-                        &queue,
-                        &images[gltf_texture.texture().index()],
+                        device,
+                        queue,
+                        image_import::texture_image(&gltf_texture.texture(), extensions, images),
                         wgpu::TextureFormat::Rgba8Unorm,
+                        asset_server,
                     ));
                     MaterialProperty {
                         texture_id: Some(textures.len() - 1),
@@ -83,10 +342,11 @@ impl Renderable {
             albedo_property: match mat.pbr_metallic_roughness().base_color_texture() {
                 Some(gltf_texture) => {
                     textures.push(Renderable::create_texture(
-                        &device,
-                        &queue,
-                        &images[gltf_texture.texture().index()],
+                        device,
+                        queue,
+                        image_import::texture_image(&gltf_texture.texture(), extensions, images),
                         wgpu::TextureFormat::Rgba8UnormSrgb,
+                        asset_server,
                     ));
                     MaterialProperty {
                         texture_id: Some(textures.len() - 1),
@@ -101,10 +361,11 @@ impl Renderable {
             emissive_property: match mat.emissive_texture() {
                 Some(gltf_texture) => {
                     textures.push(Renderable::create_texture(
-                        &device,
-                        &queue,
-                        &images[gltf_texture.texture().index()],
+                        device,
+                        queue,
+                        image_import::texture_image(&gltf_texture.texture(), extensions, images),
                         wgpu::TextureFormat::Rgba8UnormSrgb,
+                        asset_server,
                     ));
                     MaterialProperty {
                         texture_id: Some(textures.len() - 1),
@@ -127,10 +388,11 @@ impl Renderable {
             {
                 Some(gltf_texture) => {
                     textures.push(Renderable::create_texture(
-                        &device,
-                        &queue,
-                        &images[gltf_texture.texture().index()],
+                        device,
+                        queue,
+                        image_import::texture_image(&gltf_texture.texture(), extensions, images),
                         wgpu::TextureFormat::Rgba8Unorm,
+                        asset_server,
                     ));
                     MaterialProperty {
                         texture_id: Some(textures.len() - 1),
@@ -150,10 +412,11 @@ impl Renderable {
             normal_property: match mat.normal_texture() {
                 Some(gltf_texture) => {
                     textures.push(Renderable::create_texture(
-                        &device,
-                        &queue,
-                        &images[gltf_texture.texture().index()],
+                        device,
+                        queue,
+                        image_import::texture_image(&gltf_texture.texture(), extensions, images),
                         wgpu::TextureFormat::Rgba8Unorm,
+                        asset_server,
                     ));
                     MaterialProperty {
                         texture_id: Some(textures.len() - 1),
@@ -165,179 +428,837 @@ impl Renderable {
                     factor: Some([0.0; 4]),
                 },
             },
+            extension_properties: PbrExtensionProperties::default(),
             irradiance_map: &skybox.irradiance_map,
             prefiltered_environment_map: &skybox.prefiltered_environment_map,
             brdf_lut: &skybox.brdf_lut,
-            textures,
+            textures: Vec::new(),
+            is_skinned: false,
+            has_morph_targets: false,
+            primitive_topologies: Vec::new(),
         };
 
-        let material = Box::new(PbrMaterial::new(&device, &sc_desc, &pbr_params));
+        pbr_params.extension_properties = Renderable::create_extension_properties(
+            device,
+            queue,
+            import,
+            mat.index(),
+            &mut textures,
+            asset_server,
+        );
+        pbr_params.textures = textures;
 
-        Renderable::new(meshes, material)
+        Ok(Renderable::new_pbr_with_lods(
+            device,
+            pipeline_cache,
+            sc_desc,
+            lods,
+            pbr_params,
+            skin,
+        ))
     }
 
-    pub fn new_from_path(
+    fn create_extension_properties(
         device: &wgpu::Device,
-        sc_desc: &wgpu::SwapChainDescriptor,
         queue: &wgpu::Queue,
-        path: &std::path::Path,
-        skybox: &Skybox,
+        import: &GltfImport,
+        material_index: Option<usize>,
+        textures: &mut Vec<Handle<Texture>>,
+        asset_server: &mut AssetServer,
+    ) -> PbrExtensionProperties {
+        let gltf = &import.document;
+        let images = &import.images;
+        let extensions = &import.extensions;
+
+        let material_extensions = &extensions.material(material_index);
+
+        let mut property = |texture: Option<ExtensionTexture>,
+                            image_format: wgpu::TextureFormat,
+                            factor: [f32; 4]| {
+            let gltf_texture = texture.and_then(|texture| gltf.textures().nth(texture.index));
+
+            match gltf_texture {
+                Some(gltf_texture) => {
+                    let image = image_import::texture_image(&gltf_texture, extensions, images);
+
+                    textures.push(Renderable::create_texture(
+                        device,
+                        queue,
+                        image,
+                        image_format,
+                        asset_server,
+                    ));
+                    Some(MaterialProperty {
+                        texture_id: Some(textures.len() - 1),
+                        factor: None,
+                    })
+                }
+                None => Some(MaterialProperty {
+                    texture_id: None,
+                    factor: Some(factor),
+                }),
+            }
+        };
+
+        let mut properties = PbrExtensionProperties {
+            ior: material_extensions.ior,
+            emissive_strength: material_extensions.emissive_strength,
+            ..PbrExtensionProperties::default()
+        };
+
+        if let Some(clearcoat) = &material_extensions.clearcoat {
+            properties.clearcoat_property = property(
+                clearcoat.texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [clearcoat.factor, 0.0, 0.0, 0.0],
+            );
+            properties.clearcoat_roughness_property = property(
+                clearcoat.roughness_texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [0.0, clearcoat.roughness_factor, 0.0, 0.0],
+            );
+            properties.clearcoat_normal_property = property(
+                clearcoat.normal_texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [0.0; 4],
+            );
+        }
+
+        if let Some(sheen) = &material_extensions.sheen {
+            let colour = sheen.colour_factor;
+
+            properties.sheen_colour_property = property(
+                sheen.colour_texture,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                [colour[0], colour[1], colour[2], 0.0],
+            );
+            properties.sheen_roughness_property = property(
+                sheen.roughness_texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [0.0, 0.0, 0.0, sheen.roughness_factor],
+            );
+        }
+
+        if let Some(transmission) = &material_extensions.transmission {
+            properties.transmission_property = property(
+                transmission.texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [transmission.factor, 0.0, 0.0, 0.0],
+            );
+        }
+
+        if let Some(specular) = &material_extensions.specular {
+            let colour = specular.colour_factor;
+
+            properties.specular_property = property(
+                specular.texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                [0.0, 0.0, 0.0, specular.factor],
+            );
+            properties.specular_colour_property = property(
+                specular.colour_texture,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                [colour[0], colour[1], colour[2], 0.0],
+            );
+        }
+
+        if let Some(anisotropy) = &material_extensions.anisotropy {
+            // The texture's direction and strength are rotated and scaled by the factor.
+            let factor = [
+                anisotropy.rotation.cos(),
+                anisotropy.rotation.sin(),
+                anisotropy.strength,
+                0.0,
+            ];
+
+            properties.anisotropy_property =
+                property(anisotropy.texture, wgpu::TextureFormat::Rgba8Unorm, factor).map(
+                    |property| MaterialProperty {
+                        factor: Some(factor),
+                        ..property
+                    },
+                );
+        }
+
+        properties
+    }
+
+    // Creates a PBR material for the meshes, enabling the vertex features they use.
+    pub fn new_pbr(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        meshes: Vec<Mesh>,
+        pbr_params: PbrBindGroup,
+        skin: Option<Skin>,
+    ) -> Self {
+        Renderable::new_pbr_with_lods(
+            device,
+            pipeline_cache,
+            sc_desc,
+            vec![Lod::new(meshes)],
+            pbr_params,
+            skin,
+        )
+    }
+
+    pub fn new_pbr_with_lods(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        mut lods: Vec<Lod>,
+        mut pbr_params: PbrBindGroup,
+        skin: Option<Skin>,
     ) -> Self {
-        let (gltf, buffers, images) = gltf::import(path).unwrap();
+        pbr_params.primitive_topologies = Renderable::primitive_topologies(&lods);
+        pbr_params.has_morph_targets = Renderable::lods_have_morph_targets(&lods);
+        pbr_params.is_skinned = skin.is_some();
 
-        Renderable::import_gltf(device, sc_desc, queue, &gltf, &buffers, &images, skybox)
+        let material = Box::new(PbrMaterial::new(
+            device,
+            pipeline_cache,
+            sc_desc,
+            &pbr_params,
+        ));
+
+        for mesh in lods.iter_mut().flat_map(|lod| lod.meshes.iter_mut()) {
+            mesh.create_morph_target_bind_group(
+                device,
+                &material.morph_target_bind_group_layout.layout,
+            );
+        }
+
+        let mut renderable = Renderable::new_with_lods(lods, material);
+        renderable.skin = skin;
+
+        renderable
     }
 
-    pub fn new_from_glb<'a>(
+    // Creates an unlit material for the meshes, enabling the vertex features they use.
+    pub fn new_unlit(
         device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
         sc_desc: &wgpu::SwapChainDescriptor,
-        queue: &wgpu::Queue,
-        glb_data: &[u8],
-        skybox: &Skybox,
+        meshes: Vec<Mesh>,
+        unlit_params: UnlitBindGroup,
+        skin: Option<Skin>,
+    ) -> Self {
+        Renderable::new_unlit_with_lods(
+            device,
+            pipeline_cache,
+            sc_desc,
+            vec![Lod::new(meshes)],
+            unlit_params,
+            skin,
+        )
+    }
+
+    pub fn new_unlit_with_lods(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        mut lods: Vec<Lod>,
+        mut unlit_params: UnlitBindGroup,
+        skin: Option<Skin>,
     ) -> Self {
-        let (gltf, buffers, images) = gltf::import_slice(glb_data.as_ref()).unwrap();
+        unlit_params.primitive_topologies = Renderable::primitive_topologies(&lods);
+        unlit_params.has_morph_targets = Renderable::lods_have_morph_targets(&lods);
+        unlit_params.is_skinned = skin.is_some();
+
+        let material = Box::new(UnlitMaterial::new(
+            device,
+            pipeline_cache,
+            sc_desc,
+            &unlit_params,
+        ));
 
-        Renderable::import_gltf(device, sc_desc, queue, &gltf, &buffers, &images, skybox)
+        for mesh in lods.iter_mut().flat_map(|lod| lod.meshes.iter_mut()) {
+            mesh.create_morph_target_bind_group(
+                device,
+                &material.morph_target_bind_group_layout.layout,
+            );
+        }
+
+        let mut renderable = Renderable::new_with_lods(lods, material);
+        renderable.skin = skin;
+
+        renderable
     }
 
+    fn primitive_topologies(lods: &[Lod]) -> Vec<wgpu::PrimitiveTopology> {
+        let mut primitive_topologies = Vec::new();
+
+        for mesh in lods.iter().flat_map(|lod| lod.meshes.iter()) {
+            if !primitive_topologies.contains(&mesh.primitive_topology) {
+                primitive_topologies.push(mesh.primitive_topology);
+            }
+        }
+
+        primitive_topologies
+    }
+
+    fn lods_have_morph_targets(lods: &[Lod]) -> bool {
+        lods.iter()
+            .flat_map(|lod| lod.meshes.iter())
+            .any(|mesh| mesh.morph_target_buffer.is_some())
+    }
+
+    // Images that couldn't be decoded are replaced with a placeholder. Textures are shared by
+    // every material using the same pixels.
     fn create_texture(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        image: &gltf::image::Data,
+        image: Option<&ImageData>,
         image_format: wgpu::TextureFormat,
-    ) -> Texture {
-        match image.format {
-            gltf::image::Format::R8G8B8 => {
-                // Convert RGB to RGBA.
-                let mut rgba_data = vec![0; (image.width * image.height * 4) as usize];
-
-                for i in 0..(image.width * image.height) as usize {
-                    rgba_data[i * 4 + 0] = image.pixels[i * 3 + 0];
-                    rgba_data[i * 4 + 1] = image.pixels[i * 3 + 1];
-                    rgba_data[i * 4 + 2] = image.pixels[i * 3 + 2];
-                    rgba_data[i * 4 + 3] = 255;
-                }
+        asset_server: &mut AssetServer,
+    ) -> Handle<Texture> {
+        let placeholder = ImageData::placeholder();
+        let image = image.unwrap_or(&placeholder);
 
-                Texture::new_texture_from_data(
-                    &device,
-                    &queue,
-                    image.width,
-                    image.height,
-                    rgba_data.as_ref(),
-                    image_format,
-                    wgpu::AddressMode::Repeat,
-                )
-            }
-            gltf::image::Format::R8G8B8A8 => Texture::new_texture_from_data(
-                &device,
-                &queue,
+        let key =
+            AssetServer::hash_key(&(image.width, image.height, &image.mip_levels, image_format));
+
+        asset_server.texture(key, || {
+            let (image_format, mip_levels) = image.texture_levels(image_format);
+            let mip_levels: Vec<&[u8]> = mip_levels.iter().map(Vec::as_slice).collect();
+
+            Texture::new_texture_from_mips(
+                device,
+                queue,
                 image.width,
                 image.height,
-                image.pixels.as_ref(),
+                &mip_levels,
                 image_format,
                 wgpu::AddressMode::Repeat,
-            ),
-            _ => panic!("Unimplemented tex type"),
-        }
+            )
+        })
     }
 
-    fn create_mesh(
-        device: &wgpu::Device,
-        gltf_mesh: &gltf::Mesh,
-        buffers: &Vec<gltf::buffer::Data>,
-    ) -> Mesh {
+    // Fails for primitives whose accessors or indices don't fit the data they read.
+    pub fn read_primitive(
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<MeshData, String> {
+        check_primitive(primitive, buffers)?;
+
+        let primitive_topology = mesh_processing::list_topology(primitive.mode());
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+        let pos_iter = reader
+            .read_positions()
+            .ok_or_else(|| "Primitive has no positions".to_owned())?;
+
+        let has_normals = reader.read_normals().is_some();
+
+        // Missing normals are generated after the indices are read.
+        let norm_iter: Box<dyn Iterator<Item = [f32; 3]>> = match reader.read_normals() {
+            Some(norm_iter) => Box::new(norm_iter),
+            None => Box::new(std::iter::repeat([0.0; 3])),
+        };
+
+        let tex_coord_iter: Box<dyn Iterator<Item = [f32; 2]>> = match reader.read_tex_coords(0) {
+            Some(tex_coords_iter) => Box::new(tex_coords_iter.into_f32()),
+            None => Box::new(std::iter::repeat([0.0; 2])),
+        };
+
+        // The glTF spec requires tangents to be ignored when normals are missing.
+        let has_tangents = has_normals && reader.read_tangents().is_some();
+
+        let tangent_iter: Box<dyn Iterator<Item = [f32; 4]>> = match reader.read_tangents() {
+            Some(tangent_iter) => Box::new(tangent_iter),
+            None => Box::new(std::iter::repeat([0.0; 4])),
+        };
+
+        let joints_iter: Box<dyn Iterator<Item = [u16; 4]>> = match reader.read_joints(0) {
+            Some(joints_iter) => Box::new(joints_iter.into_u16()),
+            None => Box::new(std::iter::repeat([0; 4])),
+        };
+
+        let weights_iter: Box<dyn Iterator<Item = [f32; 4]>> = match reader.read_weights(0) {
+            Some(weights_iter) => Box::new(weights_iter.into_f32()),
+            None => Box::new(std::iter::repeat([0.0; 4])),
+        };
+
+        let colour_iter: Box<dyn Iterator<Item = [f32; 4]>> = match reader.read_colors(0) {
+            Some(colour_iter) => Box::new(colour_iter.into_rgba_f32()),
+            None => Box::new(std::iter::repeat([1.0; 4])),
+        };
+
         let mut vertices = Vec::new();
-        let mut indices = Vec::new();
 
-        for primitive in gltf_mesh.primitives() {
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        for (
+            vert_pos,
+            vert_norm,
+            vert_tex_coord,
+            vert_tangent,
+            vert_joints,
+            vert_weights,
+            vert_colour,
+        ) in izip!(
+            pos_iter,
+            norm_iter,
+            tex_coord_iter,
+            tangent_iter,
+            joints_iter,
+            weights_iter,
+            colour_iter
+        ) {
+            vertices.push(Vertex {
+                position: vert_pos,
+                normal: vert_norm,
+                tangent: vert_tangent,
+                tex_coord: vert_tex_coord,
+                joints: [
+                    vert_joints[0] as u32,
+                    vert_joints[1] as u32,
+                    vert_joints[2] as u32,
+                    vert_joints[3] as u32,
+                ],
+                weights: vert_weights,
+                colour: vert_colour,
+            });
+        }
 
-            let pos_iter = reader.read_positions().unwrap();
-            let norm_iter = reader.read_normals().unwrap();
+        // Read morph targets.
+        let mut morph_targets: Vec<MorphTarget> = reader
+            .read_morph_targets()
+            .map(|(positions, normals, tangents)| MorphTarget {
+                positions: positions.map(|iter| iter.collect()).unwrap_or_default(),
+                normals: normals.map(|iter| iter.collect()).unwrap_or_default(),
+                tangents: tangents.map(|iter| iter.collect()).unwrap_or_default(),
+            })
+            .collect();
 
-            let tex_coord_iter: Box<dyn Iterator<Item = [f32; 2]>> = match reader.read_tex_coords(0)
-            {
-                Some(tex_coords_iter) => Box::new(tex_coords_iter.into_f32()),
-                None => Box::new(std::iter::repeat([0.0; 2])),
-            };
+        // Read indices, generating them for non-indexed primitives.
+        let primitive_indices = match reader.read_indices() {
+            Some(iter) => iter.into_u32().collect::<Vec<u32>>(),
+            None => (0..vertices.len() as u32).collect(),
+        };
 
-            let tangent_iter: Box<dyn Iterator<Item = [f32; 4]>> = match reader.read_tangents() {
-                Some(tangent_iter) => Box::new(tangent_iter),
-                None => Box::new(std::iter::repeat([0.0; 4])),
-            };
+        if let Some(index) = primitive_indices
+            .iter()
+            .find(|&&index| index as usize >= vertices.len())
+        {
+            return Err(format!(
+                "Index {} is out of range of {} vertices",
+                index,
+                vertices.len()
+            ));
+        }
 
-            for (vert_pos, vert_norm, vert_tex_coord, vert_tangent) in
-                izip!(pos_iter, norm_iter, tex_coord_iter, tangent_iter)
-            {
-                vertices.push(Vertex {
-                    position: vert_pos,
-                    normal: vert_norm,
-                    tangent: vert_tangent,
-                    tex_coord: vert_tex_coord,
-                });
+        let mut indices = mesh_processing::convert_to_list(primitive.mode(), &primitive_indices);
+
+        // Only triangles have a surface to generate normals and tangents over. Lines and points are
+        // drawn unlit, so they don't need them.
+        if primitive_topology == wgpu::PrimitiveTopology::TriangleList {
+            if !has_normals {
+                let split_vertices = mesh_processing::generate_normals(
+                    &mut vertices,
+                    &mut indices,
+                    mesh_processing::NormalMode::Flat,
+                );
+
+                split_morph_targets(&mut morph_targets, &split_vertices);
             }
 
-            // Read indices.
-            if let Some(iter) = reader.read_indices() {
-                for vertex_index in iter.into_u32() {
-                    indices.push(vertex_index);
-                }
+            // Tangents from the file are kept.
+            if !has_tangents {
+                let split_vertices =
+                    mesh_processing::generate_tangents(&mut vertices, &mut indices);
+
+                split_morph_targets(&mut morph_targets, &split_vertices);
             }
+        }
 
-            // Calculate tangents.
-            let mut tangents: Vec<nalgebra::Vector3<f32>> =
-                vec![nalgebra::Vector3::zeros(); vertices.len()];
-            let mut bitangents: Vec<nalgebra::Vector3<f32>> =
-                vec![nalgebra::Vector3::zeros(); vertices.len()];
+        Ok(MeshData {
+            vertices,
+            indices,
+            primitive_topology,
+            morph_targets,
+        })
+    }
+}
 
-            for tri_ids in indices.chunks(3) {
-                let i0 = tri_ids[0] as usize;
-                let i1 = tri_ids[1] as usize;
-                let i2 = tri_ids[2] as usize;
+// The glTF crate slices accessors out of their buffers without checking their bounds or types, so
+// every accessor a primitive, animation or skin reads is checked first.
+fn check_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<(), String> {
+    use gltf::accessor::{DataType::*, Dimensions::*};
+    use gltf::Semantic;
 
-                let p0: nalgebra::Vector3<f32> = vertices[i0].position.into();
-                let p1: nalgebra::Vector3<f32> = vertices[i1].position.into();
-                let p2: nalgebra::Vector3<f32> = vertices[i2].position.into();
+    for (semantic, accessor) in primitive.attributes() {
+        let (data_types, dimensions): (&[_], &[_]) = match semantic {
+            Semantic::Positions | Semantic::Normals => (&[F32], &[Vec3]),
+            Semantic::Tangents => (&[F32], &[Vec4]),
+            Semantic::TexCoords(_) => (&[F32, U8, U16], &[Vec2]),
+            Semantic::Colors(_) => (&[F32, U8, U16], &[Vec3, Vec4]),
+            Semantic::Joints(_) => (&[U8, U16], &[Vec4]),
+            Semantic::Weights(_) => (&[F32, U8, U16], &[Vec4]),
+        };
 
-                let w0: nalgebra::Vector2<f32> = vertices[i0].tex_coord.into();
-                let w1: nalgebra::Vector2<f32> = vertices[i1].tex_coord.into();
-                let w2: nalgebra::Vector2<f32> = vertices[i2].tex_coord.into();
+        check_accessor(&accessor, data_types, dimensions, buffers)?;
+    }
 
-                let e1 = p1 - p0;
-                let e2 = p2 - p0;
+    if let Some(accessor) = primitive.indices() {
+        check_accessor(&accessor, &[U8, U16, U32], &[Scalar], buffers)?;
+    }
 
-                let x1 = w1.x - w0.x;
-                let x2 = w2.x - w0.x;
+    for morph_target in primitive.morph_targets() {
+        let accessors = morph_target
+            .positions()
+            .into_iter()
+            .chain(morph_target.normals())
+            .chain(morph_target.tangents());
 
-                let y1 = w1.y - w0.y;
-                let y2 = w2.y - w0.y;
+        for accessor in accessors {
+            check_accessor(&accessor, &[F32], &[Vec3], buffers)?;
+        }
+    }
 
-                let r = 1.0 / (x1 * y2 - x2 * y1);
+    Ok(())
+}
 
-                let t = (e1 * y2 - e2 * y1) * r;
-                let b = (e2 * x1 - e1 * x2) * r;
+pub fn check_accessor(
+    accessor: &gltf::Accessor,
+    data_types: &[gltf::accessor::DataType],
+    dimensions: &[gltf::accessor::Dimensions],
+    buffers: &[gltf::buffer::Data],
+) -> Result<(), String> {
+    let index = accessor.index();
 
-                tangents[i0] += t;
-                tangents[i1] += t;
-                tangents[i2] += t;
+    if !data_types.contains(&accessor.data_type()) || !dimensions.contains(&accessor.dimensions()) {
+        return Err(format!("Accessor {} has the wrong type", index));
+    }
 
-                bitangents[i0] += b;
-                bitangents[i1] += b;
-                bitangents[i2] += b;
-            }
+    if accessor.count() == 0 {
+        return Err(format!("Accessor {} is empty", index));
+    }
+
+    let is_in_range = |view: gltf::buffer::View, offset: usize, count: usize, size: usize| {
+        let stride = view.stride().unwrap_or(size);
+        let buffer_length = buffers
+            .get(view.buffer().index())
+            .map_or(0, |data| data.len());
+
+        view.offset() + view.length() <= buffer_length
+            && offset + stride * (count - 1) + size <= view.length()
+    };
+
+    if let Some(view) = accessor.view() {
+        if !is_in_range(view, accessor.offset(), accessor.count(), accessor.size()) {
+            return Err(format!("Accessor {} is out of range of its buffer", index));
+        }
+    }
+
+    if let Some(sparse) = accessor.sparse() {
+        let count = sparse.count() as usize;
+        let indices = sparse.indices();
+        let values = sparse.values();
+
+        if count == 0
+            || !is_in_range(
+                indices.view(),
+                indices.offset() as usize,
+                count,
+                indices.index_type().size(),
+            )
+            || !is_in_range(
+                values.view(),
+                values.offset() as usize,
+                count,
+                accessor.size(),
+            )
+        {
+            return Err(format!(
+                "Sparse accessor {} is out of range of its buffer",
+                index
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-            for (i, vertex) in vertices.iter_mut().enumerate() {
-                let t = tangents[i];
-                let b = bitangents[i];
-                let n: nalgebra::Vector3<f32> = vertex.normal.into();
+    const VERTEX_COUNT: usize = 5;
 
-                let tangent = (t - n.dot(&t) * n).normalize();
-                let handedness = if n.dot(&t.cross(&b)) > 0.0 { 1.0 } else { -1.0 };
+    // glTF component types.
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
 
-                vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+    // A glTF file with one primitive of the given mode, over five vertices with normals and
+    // tangents so none are generated. The position accessor can claim more vertices than its
+    // buffer view holds.
+    fn primitive_gltf(
+        mode: u32,
+        indices: Option<&[u32]>,
+        position_count: usize,
+    ) -> (gltf::Document, Vec<gltf::buffer::Data>) {
+        let mut data = Vec::new();
+
+        for vertex in 0..VERTEX_COUNT {
+            for value in [vertex as f32, (vertex % 2) as f32, 0.0].iter() {
+                data.extend_from_slice(&value.to_le_bytes());
             }
         }
 
-        Mesh::new(device, vertices.as_slice(), Some(indices.as_slice()))
+        for _ in 0..VERTEX_COUNT {
+            for value in [0.0f32, 0.0, 1.0].iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        for _ in 0..VERTEX_COUNT {
+            for value in [1.0f32, 0.0, 0.0, 1.0].iter() {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        let index_offset = data.len();
+
+        for index in indices.unwrap_or(&[]) {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let mut primitive = json!({
+            "attributes": { "POSITION": 0, "NORMAL": 1, "TANGENT": 2 },
+            "mode": mode,
+        });
+
+        // Position accessors must have bounds.
+        let mut accessors = vec![
+            json!({
+                "bufferView": 0,
+                "componentType": FLOAT,
+                "count": position_count,
+                "type": "VEC3",
+                "min": [0.0, 0.0, 0.0],
+                "max": [VERTEX_COUNT - 1, 1, 0.0],
+            }),
+            json!({ "bufferView": 1, "componentType": FLOAT, "count": VERTEX_COUNT, "type": "VEC3" }),
+            json!({ "bufferView": 2, "componentType": FLOAT, "count": VERTEX_COUNT, "type": "VEC4" }),
+        ];
+
+        let buffer_view = |offset: usize, length: usize| json!({ "buffer": 0, "byteOffset": offset, "byteLength": length });
+
+        let mut buffer_views = vec![
+            buffer_view(0, VERTEX_COUNT * 12),
+            buffer_view(VERTEX_COUNT * 12, VERTEX_COUNT * 12),
+            buffer_view(VERTEX_COUNT * 24, VERTEX_COUNT * 16),
+        ];
+
+        if let Some(indices) = indices {
+            primitive["indices"] = json!(3);
+
+            accessors.push(json!({
+                "bufferView": 3,
+                "componentType": UNSIGNED_INT,
+                "count": indices.len(),
+                "type": "SCALAR",
+            }));
+
+            buffer_views.push(buffer_view(index_offset, indices.len() * 4));
+        }
+
+        let gltf_json = json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": data.len() }],
+            "bufferViews": buffer_views,
+            "accessors": accessors,
+            "meshes": [{ "primitives": [primitive] }],
+        });
+
+        let gltf = gltf::Gltf::from_slice(gltf_json.to_string().as_bytes()).unwrap();
+
+        (gltf.document, vec![gltf::buffer::Data(data)])
+    }
+
+    fn read(mode: u32, indices: Option<&[u32]>) -> Result<MeshData, String> {
+        read_with_positions(mode, indices, VERTEX_COUNT)
+    }
+
+    fn read_with_positions(
+        mode: u32,
+        indices: Option<&[u32]>,
+        position_count: usize,
+    ) -> Result<MeshData, String> {
+        let (document, buffers) = primitive_gltf(mode, indices, position_count);
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+
+        Renderable::read_primitive(&primitive, &buffers)
+    }
+
+    #[test]
+    fn reads_points() {
+        let mesh_data = read(0, None).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::PointList
+        );
+        assert_eq!(mesh_data.indices, vec![0, 1, 2, 3, 4]);
+        assert_eq!(mesh_data.vertices.len(), VERTEX_COUNT);
+    }
+
+    #[test]
+    fn reads_lines() {
+        // The unpaired last index is dropped.
+        let mesh_data = read(1, None).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::LineList
+        );
+        assert_eq!(mesh_data.indices, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn converts_line_strips_and_loops() {
+        let mesh_data = read(3, None).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::LineList
+        );
+        assert_eq!(mesh_data.indices, vec![0, 1, 1, 2, 2, 3, 3, 4]);
+
+        let mesh_data = read(2, Some(&[4, 3, 2])).unwrap();
+
+        assert_eq!(mesh_data.indices, vec![4, 3, 3, 2, 2, 4]);
+    }
+
+    #[test]
+    fn converts_triangle_strips() {
+        let mesh_data = read(5, None).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::TriangleList
+        );
+        assert_eq!(mesh_data.indices, vec![0, 1, 2, 1, 3, 2, 2, 3, 4]);
+        assert_eq!(mesh_data.vertices.len(), VERTEX_COUNT);
+    }
+
+    #[test]
+    fn converts_triangle_fans() {
+        let mesh_data = read(6, Some(&[4, 0, 1, 2])).unwrap();
+
+        assert_eq!(
+            mesh_data.primitive_topology,
+            wgpu::PrimitiveTopology::TriangleList
+        );
+        assert_eq!(mesh_data.indices, vec![4, 0, 1, 4, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_accessors() {
+        // More positions than the buffer view holds.
+        assert!(read_with_positions(4, None, VERTEX_COUNT + 1).is_err());
+
+        // An index past the last vertex.
+        assert!(read(4, Some(&[0, 1, VERTEX_COUNT as u32])).is_err());
+
+        // A buffer view past the end of its buffer.
+        let (document, mut buffers) = primitive_gltf(4, Some(&[0, 1, 2]), VERTEX_COUNT);
+        let length = buffers[0].0.len();
+        buffers[0].0.truncate(length - 4);
+
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+
+        assert!(Renderable::read_primitive(&primitive, &buffers).is_err());
+    }
+
+    #[test]
+    fn reads_primitives_sharing_buffers() {
+        let (_, buffers) = primitive_gltf(4, Some(&[0, 1, 2, 2, 3, 4, 0, 1, 2]), VERTEX_COUNT);
+        let index_offset = VERTEX_COUNT * 40;
+
+        // The second primitive reads the middle three vertices through accessors overlapping the
+        // first's, and indexes them from zero.
+        let accessor =
+            |view: usize, offset: usize, count: usize, component_type: u32, kind: &str| {
+                json!({
+                    "bufferView": view,
+                    "byteOffset": offset,
+                    "componentType": component_type,
+                    "count": count,
+                    "type": kind,
+                })
+            };
+
+        let mut accessors = vec![
+            accessor(0, 0, VERTEX_COUNT, FLOAT, "VEC3"),
+            accessor(1, 0, VERTEX_COUNT, FLOAT, "VEC3"),
+            accessor(2, 0, VERTEX_COUNT, FLOAT, "VEC4"),
+            accessor(3, 0, 6, UNSIGNED_INT, "SCALAR"),
+            accessor(0, 12, 3, FLOAT, "VEC3"),
+            accessor(1, 12, 3, FLOAT, "VEC3"),
+            accessor(2, 16, 3, FLOAT, "VEC4"),
+            accessor(3, 24, 3, UNSIGNED_INT, "SCALAR"),
+        ];
+
+        accessors[0]["min"] = json!([0.0, 0.0, 0.0]);
+        accessors[0]["max"] = json!([VERTEX_COUNT - 1, 1, 0.0]);
+        accessors[4]["min"] = json!([1.0, 0.0, 0.0]);
+        accessors[4]["max"] = json!([3.0, 1.0, 0.0]);
+
+        let gltf_json = json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": buffers[0].len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": VERTEX_COUNT * 12 },
+                { "buffer": 0, "byteOffset": VERTEX_COUNT * 12, "byteLength": VERTEX_COUNT * 12 },
+                { "buffer": 0, "byteOffset": VERTEX_COUNT * 24, "byteLength": VERTEX_COUNT * 16 },
+                { "buffer": 0, "byteOffset": index_offset, "byteLength": 36 },
+            ],
+            "accessors": accessors,
+            "meshes": [{
+                "primitives": [
+                    { "attributes": { "POSITION": 0, "NORMAL": 1, "TANGENT": 2 }, "indices": 3 },
+                    { "attributes": { "POSITION": 4, "NORMAL": 5, "TANGENT": 6 }, "indices": 7 },
+                ],
+            }],
+        });
+
+        let document = gltf::Gltf::from_slice(gltf_json.to_string().as_bytes())
+            .unwrap()
+            .document;
+
+        let mesh_data = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .map(|primitive| Renderable::read_primitive(&primitive, &buffers).unwrap())
+            .collect::<Vec<MeshData>>();
+
+        assert_eq!(mesh_data.len(), 2);
+
+        assert_eq!(mesh_data[0].indices, vec![0, 1, 2, 2, 3, 4]);
+        assert_eq!(mesh_data[0].vertices.len(), VERTEX_COUNT);
+
+        assert_eq!(mesh_data[1].indices, vec![0, 1, 2]);
+        assert_eq!(mesh_data[1].vertices.len(), 3);
+        assert_eq!(mesh_data[1].vertices[0].position, [1.0, 1.0, 0.0]);
+        assert_eq!(mesh_data[1].vertices[2].position, [3.0, 1.0, 0.0]);
     }
 }
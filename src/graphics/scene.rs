@@ -0,0 +1,231 @@
+use super::*;
+use specs::prelude::*;
+
+pub struct Scene {
+    pub renderable: Renderable,
+    pub animation_player: Option<AnimationPlayer>,
+    pub morph_weights: Option<MorphWeights>,
+    // Lights with poses relative to the scene.
+    pub lights: Vec<(Light, Pose)>,
+    pub cameras: Vec<(SceneCamera, Pose)>,
+}
+
+// The light and camera entities added with a scene, which are replaced if it's reloaded.
+pub struct SceneNodes {
+    pub entities: Vec<Entity>,
+}
+
+impl Component for SceneNodes {
+    type Storage = VecStorage<Self>;
+}
+
+impl Scene {
+    pub fn import_gltf(
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        sc_desc: &wgpu::SwapChainDescriptor,
+        queue: &wgpu::Queue,
+        import: &GltfImport,
+        skybox: &Skybox,
+        asset_server: &mut AssetServer,
+    ) -> Result<Self, String> {
+        let renderable = Renderable::import_gltf(
+            device,
+            pipeline_cache,
+            sc_desc,
+            queue,
+            import,
+            skybox,
+            asset_server,
+        )?;
+
+        let gltf = &import.document;
+
+        let animation_player = AnimationPlayer::new_from_gltf(gltf, &import.buffers)?;
+
+        // Start from the default weights of the first mesh with morph targets.
+        let morph_weights = gltf
+            .meshes()
+            .find(|mesh| {
+                mesh.primitives()
+                    .any(|primitive| primitive.morph_targets().next().is_some())
+            })
+            .map(|mesh| MorphWeights {
+                weights: match mesh.weights() {
+                    Some(weights) => weights.to_vec(),
+                    None => Vec::new(),
+                },
+            });
+
+        let node_transforms = scene::node_world_transforms(gltf);
+
+        let lights = gltf
+            .nodes()
+            .filter_map(|node| {
+                node.light().map(|gltf_light| {
+                    (
+                        Light::new_from_gltf(&gltf_light),
+                        Pose {
+                            model_matrix: node_transforms[node.index()],
+                        },
+                    )
+                })
+            })
+            .collect();
+
+        let aspect_ratio = sc_desc.width as f32 / sc_desc.height as f32;
+
+        let cameras = gltf
+            .nodes()
+            .filter_map(|node| {
+                node.camera().map(|gltf_camera| {
+                    (
+                        SceneCamera {
+                            name: gltf_camera.name().or(node.name()).map(str::to_owned),
+                            projection: Projection::new_from_gltf(&gltf_camera, aspect_ratio),
+                        },
+                        Pose {
+                            model_matrix: node_transforms[node.index()],
+                        },
+                    )
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            renderable,
+            animation_player,
+            morph_weights,
+            lights,
+            cameras,
+        })
+    }
+
+    // Adds the scene to an existing entity, such as one created while the scene loaded, replacing
+    // any scene added to it before. Lights and cameras are placed relative to the entity's pose.
+    pub fn insert_entities(self, world: &mut World, entity: Entity) {
+        if !world.is_alive(entity) {
+            return;
+        }
+
+        let scene_matrix = match world.read_storage::<Pose>().get(entity) {
+            Some(pose) => pose.model_matrix,
+            None => nalgebra::Affine3::identity(),
+        };
+
+        world
+            .write_storage::<Renderable>()
+            .insert(entity, self.renderable)
+            .unwrap();
+
+        match self.animation_player {
+            Some(animation_player) => {
+                world
+                    .write_storage::<AnimationPlayer>()
+                    .insert(entity, animation_player)
+                    .unwrap();
+            }
+            None => {
+                world.write_storage::<AnimationPlayer>().remove(entity);
+            }
+        }
+
+        match self.morph_weights {
+            Some(morph_weights) => {
+                world
+                    .write_storage::<MorphWeights>()
+                    .insert(entity, morph_weights)
+                    .unwrap();
+            }
+            None => {
+                world.write_storage::<MorphWeights>().remove(entity);
+            }
+        }
+
+        let previous_nodes = world.write_storage::<SceneNodes>().remove(entity);
+
+        if let Some(previous_nodes) = previous_nodes {
+            world.delete_entities(&previous_nodes.entities).unwrap();
+        }
+
+        let mut node_entities = Vec::new();
+
+        for (light, light_pose) in self.lights {
+            node_entities.push(
+                world
+                    .create_entity()
+                    .with(light)
+                    .with(Pose {
+                        model_matrix: scene_matrix * light_pose.model_matrix,
+                    })
+                    .build(),
+            );
+        }
+
+        for (scene_camera, camera_pose) in self.cameras {
+            node_entities.push(
+                world
+                    .create_entity()
+                    .with(scene_camera)
+                    .with(Pose {
+                        model_matrix: scene_matrix * camera_pose.model_matrix,
+                    })
+                    .build(),
+            );
+        }
+
+        world
+            .write_storage::<SceneNodes>()
+            .insert(
+                entity,
+                SceneNodes {
+                    entities: node_entities,
+                },
+            )
+            .unwrap();
+    }
+}
+
+// World transforms of every node in the document, indexed by node index.
+pub fn node_world_transforms(gltf: &gltf::Document) -> Vec<nalgebra::Affine3<f32>> {
+    let mut parents = vec![None; gltf.nodes().len()];
+
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+
+    let local_transforms: Vec<nalgebra::Affine3<f32>> = gltf
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+
+            NodeTransform {
+                translation: translation.into(),
+                rotation: nalgebra::UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(
+                    rotation[3],
+                    rotation[0],
+                    rotation[1],
+                    rotation[2],
+                )),
+                scale: scale.into(),
+            }
+            .to_affine()
+        })
+        .collect();
+
+    (0..local_transforms.len())
+        .map(|i| {
+            let mut transform = local_transforms[i];
+            let mut parent = parents[i];
+
+            while let Some(parent_index) = parent {
+                transform = local_transforms[parent_index] * transform;
+                parent = parents[parent_index];
+            }
+
+            transform
+        })
+        .collect()
+}
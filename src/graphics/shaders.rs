@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Shader sources built into the binary, by file name. The .glsl files hold code shared between
+// shaders through `#include`.
+const BUILT_IN_SHADERS: &[(&str, &str)] = &[
+    ("brdf.glsl", include_str!("shaders/brdf.glsl")),
+    ("common.glsl", include_str!("shaders/common.glsl")),
+    ("frame.glsl", include_str!("shaders/frame.glsl")),
+    ("hdr.vert", include_str!("shaders/hdr.vert")),
+    (
+        "hdr_convolve_brdf.frag",
+        include_str!("shaders/hdr_convolve_brdf.frag"),
+    ),
+    (
+        "hdr_convolve_diffuse.frag",
+        include_str!("shaders/hdr_convolve_diffuse.frag"),
+    ),
+    (
+        "hdr_convolve_specular.frag",
+        include_str!("shaders/hdr_convolve_specular.frag"),
+    ),
+    ("hdr_cvt.frag", include_str!("shaders/hdr_cvt.frag")),
+    ("pbr.frag", include_str!("shaders/pbr.frag")),
+    ("pbr.vert", include_str!("shaders/pbr.vert")),
+    ("sampling.glsl", include_str!("shaders/sampling.glsl")),
+    ("skybox.frag", include_str!("shaders/skybox.frag")),
+    ("skybox.vert", include_str!("shaders/skybox.vert")),
+    ("tonemapping.glsl", include_str!("shaders/tonemapping.glsl")),
+    ("unlit.frag", include_str!("shaders/unlit.frag")),
+];
+
+// Includes nested deeper than this are assumed to be recursive.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+// Sources reloaded from disk, which replace the built-in ones for new materials.
+static RELOADED_SHADERS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+// SPIR-V compiled this run, by permutation key.
+static COMPILED_SHADERS: Mutex<Option<HashMap<u64, Vec<u32>>>> = Mutex::new(None);
+
+// The preprocessor macros a shader permutation is compiled with.
+#[derive(Clone, Default, Hash)]
+pub struct ShaderDefines(Vec<(String, Option<String>)>);
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str) {
+        self.0.push((name.to_owned(), None));
+    }
+
+    pub fn add_value<T: ToString>(&mut self, name: &str, value: T) {
+        self.0.push((name.to_owned(), Some(value.to_string())));
+    }
+}
+
+impl std::fmt::Display for ShaderDefines {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let defines = self
+            .0
+            .iter()
+            .map(|(name, value)| match value {
+                Some(value) => format!("{}={}", name, value),
+                None => name.clone(),
+            })
+            .collect::<Vec<String>>();
+
+        write!(f, "{}", defines.join(" "))
+    }
+}
+
+pub fn is_shader(name: &str) -> bool {
+    BUILT_IN_SHADERS
+        .iter()
+        .any(|(shader_name, _)| *shader_name == name)
+}
+
+pub fn shader_source(name: &str) -> String {
+    if let Some(source) = RELOADED_SHADERS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|shaders| shaders.get(name))
+    {
+        return source.clone();
+    }
+
+    BUILT_IN_SHADERS
+        .iter()
+        .find(|(shader_name, _)| *shader_name == name)
+        .map(|(_, source)| source.to_string())
+        .unwrap_or_else(|| panic!("No shader named {:?}", name))
+}
+
+pub fn set_shader_source(name: &str, source: String) {
+    RELOADED_SHADERS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_owned(), source);
+}
+
+// Whether the shader is the file, or includes it directly or through another include.
+pub fn depends_on(shader_name: &str, file_name: &str) -> bool {
+    let mut sources = Vec::new();
+    collect_sources(shader_name, &shader_source, &mut sources);
+
+    sources.iter().any(|(name, _)| name == file_name)
+}
+
+// Compiles a permutation of a shader to SPIR-V, resolving its includes from the library. Each
+// permutation is cached in memory and on disk, keyed by its sources and defines. `replaced` tries
+// a new source for one file in place of the current one, as when reloading it.
+pub fn compile_shader(
+    name: &str,
+    shader_kind: shaderc::ShaderKind,
+    defines: &ShaderDefines,
+    replaced: Option<(&str, &str)>,
+) -> Result<Vec<u32>, String> {
+    let source_of = |file_name: &str| match replaced {
+        Some((replaced_name, source)) if replaced_name == file_name => source.to_owned(),
+        _ => shader_source(file_name),
+    };
+
+    let source = source_of(name);
+
+    // The key covers every included source, so editing an include recompiles its dependents.
+    let mut sources = Vec::new();
+    collect_sources(name, &source_of, &mut sources);
+
+    let key = super::AssetServer::hash_key(&(name, shader_kind as u32, defines, &sources));
+
+    if let Some(spirv) = COMPILED_SHADERS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|shaders| shaders.get(&key))
+    {
+        return Ok(spirv.clone());
+    }
+
+    let spirv = match read_cached_spirv(key) {
+        Some(spirv) => spirv,
+        None => {
+            let spirv = compile_spirv(name, &source, shader_kind, defines, &source_of)?;
+
+            write_cached_spirv(key, &spirv);
+
+            spirv
+        }
+    };
+
+    COMPILED_SHADERS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(key, spirv.clone());
+
+    Ok(spirv)
+}
+
+fn compile_spirv<F>(
+    name: &str,
+    source: &str,
+    shader_kind: shaderc::ShaderKind,
+    defines: &ShaderDefines,
+    source_of: &F,
+) -> Result<Vec<u32>, String>
+where
+    F: Fn(&str) -> String,
+{
+    let mut compiler = shaderc::Compiler::new().unwrap();
+    let mut options = shaderc::CompileOptions::new().unwrap();
+
+    // Defines are passed to the preprocessor rather than prepended, so the lines in errors match
+    // the source files.
+    for (define_name, value) in &defines.0 {
+        options.add_macro_definition(define_name, value.as_deref());
+    }
+
+    options.set_include_callback(|requested, _, requesting, depth| {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!("{} is included recursively", requested));
+        }
+
+        if !is_shader(requested) {
+            return Err(format!(
+                "{} includes unknown file {}",
+                requesting, requested
+            ));
+        }
+
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: requested.to_owned(),
+            content: source_of(requested),
+        })
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(source, shader_kind, name, "main", Some(&options))
+        .map_err(|err| {
+            // Compilation errors are listed as "file:line: error: message".
+            let log = match err {
+                shaderc::Error::CompilationError(_, log) => log,
+                err => err.to_string(),
+            };
+
+            format!("Failed to compile {} [{}]:\n{}", name, defines, log)
+        })?;
+
+    Ok(artifact.as_binary().to_vec())
+}
+
+// The files named by a source's `#include "file"` lines.
+fn include_names(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix("#include")?
+                .trim()
+                .strip_prefix('"')?
+                .strip_suffix('"')
+        })
+        .collect()
+}
+
+fn collect_sources<F>(name: &str, source_of: &F, sources: &mut Vec<(String, String)>)
+where
+    F: Fn(&str) -> String,
+{
+    if sources.iter().any(|(source_name, _)| source_name == name) {
+        return;
+    }
+
+    let source = source_of(name);
+
+    let include_names = include_names(&source)
+        .into_iter()
+        .filter(|include_name| is_shader(include_name))
+        .map(|include_name| include_name.to_owned())
+        .collect::<Vec<String>>();
+
+    sources.push((name.to_owned(), source));
+
+    for include_name in include_names {
+        collect_sources(&include_name, source_of, sources);
+    }
+}
+
+fn cache_path(key: u64) -> std::path::PathBuf {
+    std::env::temp_dir()
+        .join("rust-pbr-shader-cache")
+        .join(format!("{:016x}.spv", key))
+}
+
+fn read_cached_spirv(key: u64) -> Option<Vec<u32>> {
+    let file = std::fs::File::open(cache_path(key)).ok()?;
+
+    wgpu::read_spirv(file).ok()
+}
+
+// The disk cache only saves compile time, so failing to write it isn't an error.
+fn write_cached_spirv(key: u64, spirv: &[u32]) {
+    let path = cache_path(key);
+
+    let bytes = spirv
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect::<Vec<u8>>();
+
+    let result =
+        std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| std::fs::write(&path, bytes));
+
+    if let Err(err) = result {
+        println!("Failed to cache shader at {:?}: {}", path, err);
+    }
+}
@@ -0,0 +1,30 @@
+#ifndef BRDF_GLSL
+#define BRDF_GLSL
+
+#include "common.glsl"
+
+float distribution_ggx(vec3 normal, vec3 half_dir, float roughness)
+{
+    float a         = roughness * roughness;
+    float a_2       = a * a;
+    float n_dot_h   = max(dot(normal, half_dir), 0.0);
+    float n_dot_h_2 = n_dot_h * n_dot_h;
+
+    float nom    = a_2;
+    float denom  = (n_dot_h_2 * (a_2 - 1.0) + 1.0);
+    denom        = PI * denom * denom;
+
+    return nom / denom;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 fresnel_0)
+{
+    return fresnel_0 + (1.0 - fresnel_0) * pow(1.0 - cos_theta, 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 fresnel_0, float roughness)
+{
+    return fresnel_0 + ( max(vec3(1.0 - roughness), fresnel_0) - fresnel_0 ) * pow(1.0 - cos_theta, 5.0);
+}
+
+#endif
@@ -0,0 +1,6 @@
+#ifndef COMMON_GLSL
+#define COMMON_GLSL
+
+const float PI = 3.14159265359;
+
+#endif
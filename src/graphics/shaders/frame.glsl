@@ -0,0 +1,16 @@
+#ifndef FRAME_GLSL
+#define FRAME_GLSL
+
+// The camera, shared by every material through the frame bind group.
+layout(set=0, binding=0)
+uniform Camera {
+    mat4 view;
+    mat4 proj;
+} u_camera;
+
+// The view matrix is a rigid transform, so the camera sits at its inverse translation.
+vec3 camera_world_pos() {
+    return -transpose(mat3(u_camera.view)) * u_camera.view[3].xyz;
+}
+
+#endif
@@ -1,6 +1,6 @@
 #version 450
 
-const float PI = 3.14159265359;
+#include "common.glsl"
 
 layout(location = 0)
 in VS_OUT {
@@ -1,66 +1,154 @@
+#version 450
 
-#define PI 3.1415926538
+#include "brdf.glsl"
+#include "tonemapping.glsl"
 
 layout(location = 0) out vec4 f_colour;
 
-layout(set=0, binding=0)
-uniform Transforms {
-    mat4 model;
-    mat4 view;
-    mat4 proj;
-    vec3 world_pos;
-} u_camera;
+#include "frame.glsl"
 
-layout(set=1, binding=0)
-uniform Light {
-    vec3 world_pos;
-} u_light;
+#define LIGHT_DIRECTIONAL 0u
+#define LIGHT_POINT 1u
+#define LIGHT_SPOT 2u
 
-#if !defined(AO_TEXTURE_BINDING) || !defined(ALBEDO_TEXTURE_BINDING) || !defined(EMISSIVE_TEXTURE_BINDING) || !defined(METAL_ROUGHNESS_TEXTURE_BINDING)
-layout(set=2, binding=0)
+struct Light {
+    vec3 world_pos;
+    uint light_type;
+    vec3 direction;
+    float range;
+    vec3 colour;
+    float intensity;
+    float inner_cone_cos;
+    float outer_cone_cos;
+};
+
+layout(set=0, binding=1)
+uniform Lights {
+    Light lights[MAX_LIGHTS];
+    uint num_lights;
+} u_lights;
+
+// Every factor is a vec4, in the order they're pushed by `PbrMaterial`.
+layout(set=1, binding=0)
 uniform MaterialProperties {
 #ifndef AO_TEXTURE_BINDING
-    float ao;
+    vec4 ao;
 #endif
 #ifndef ALBEDO_TEXTURE_BINDING
-    vec3 albedo;
+    vec4 albedo;
 #endif
 #ifndef EMISSIVE_TEXTURE_BINDING
-    vec3 emissive;
+    vec4 emissive;
 #endif
 #ifndef METAL_ROUGHNESS_TEXTURE_BINDING
-    float metallic;
-    float roughness;
+    vec4 metal_roughness;
 #endif
-} u_material;
+#ifndef NORMAL_TEXTURE_BINDING
+    vec4 normal;
+#endif
+#ifdef MATERIAL_CLEARCOAT
+#ifndef CLEARCOAT_TEXTURE_BINDING
+    vec4 clearcoat;
+#endif
+#ifndef CLEARCOAT_ROUGHNESS_TEXTURE_BINDING
+    vec4 clearcoat_roughness;
+#endif
+#ifndef CLEARCOAT_NORMAL_TEXTURE_BINDING
+    vec4 clearcoat_normal;
+#endif
+#endif
+#ifdef MATERIAL_SHEEN
+#ifndef SHEEN_COLOUR_TEXTURE_BINDING
+    vec4 sheen_colour;
+#endif
+#ifndef SHEEN_ROUGHNESS_TEXTURE_BINDING
+    vec4 sheen_roughness;
+#endif
+#endif
+#ifdef MATERIAL_TRANSMISSION
+#ifndef TRANSMISSION_TEXTURE_BINDING
+    vec4 transmission;
+#endif
+#endif
+#ifdef MATERIAL_SPECULAR
+#ifndef SPECULAR_TEXTURE_BINDING
+    vec4 specular;
 #endif
+#ifndef SPECULAR_COLOUR_TEXTURE_BINDING
+    vec4 specular_colour;
+#endif
+#endif
+#ifdef MATERIAL_ANISOTROPY
+    // Rotation cosine and sine, and strength.
+    vec4 anisotropy;
+#endif
+    // IOR and emissive strength.
+    vec4 scalars;
+} u_material;
 
-layout(set = 3, binding = 0) uniform textureCube t_irradiance;
-layout(set = 3, binding = 1) uniform sampler s_irradiance;
-layout(set = 3, binding = 2) uniform textureCube t_prefiltered_env_map;
-layout(set = 3, binding = 3) uniform sampler s_prefiltered_env_map;
-layout(set = 3, binding = 4) uniform texture2D t_brdf_lut;
-layout(set = 3, binding = 5) uniform sampler s_brdf_lut;
+layout(set = 1, binding = 1) uniform textureCube t_irradiance;
+layout(set = 1, binding = 2) uniform sampler s_irradiance;
+layout(set = 1, binding = 3) uniform textureCube t_prefiltered_env_map;
+layout(set = 1, binding = 4) uniform sampler s_prefiltered_env_map;
+layout(set = 1, binding = 5) uniform texture2D t_brdf_lut;
+layout(set = 1, binding = 6) uniform sampler s_brdf_lut;
 
 #ifdef AO_TEXTURE_BINDING
-layout(set = 3, binding = AO_TEXTURE_BINDING) uniform texture2D t_ao;
-layout(set = 3, binding = AO_TEXTURE_BINDING + 1) uniform sampler s_ao;
+layout(set = 1, binding = AO_TEXTURE_BINDING) uniform texture2D t_ao;
+layout(set = 1, binding = AO_TEXTURE_BINDING + 1) uniform sampler s_ao;
 #endif
 #ifdef ALBEDO_TEXTURE_BINDING
-layout(set = 3, binding = ALBEDO_TEXTURE_BINDING) uniform texture2D t_albedo;
-layout(set = 3, binding = ALBEDO_TEXTURE_BINDING + 1) uniform sampler s_albedo;
+layout(set = 1, binding = ALBEDO_TEXTURE_BINDING) uniform texture2D t_albedo;
+layout(set = 1, binding = ALBEDO_TEXTURE_BINDING + 1) uniform sampler s_albedo;
 #endif
 #ifdef EMISSIVE_TEXTURE_BINDING
-layout(set = 3, binding = EMISSIVE_TEXTURE_BINDING) uniform texture2D t_emissive;
-layout(set = 3, binding = EMISSIVE_TEXTURE_BINDING + 1) uniform sampler s_emissive;
+layout(set = 1, binding = EMISSIVE_TEXTURE_BINDING) uniform texture2D t_emissive;
+layout(set = 1, binding = EMISSIVE_TEXTURE_BINDING + 1) uniform sampler s_emissive;
 #endif
 #ifdef METAL_ROUGHNESS_TEXTURE_BINDING
-layout(set = 3, binding = METAL_ROUGHNESS_TEXTURE_BINDING) uniform texture2D t_metal_roughness;
-layout(set = 3, binding = METAL_ROUGHNESS_TEXTURE_BINDING + 1) uniform sampler s_metal_roughness;
+layout(set = 1, binding = METAL_ROUGHNESS_TEXTURE_BINDING) uniform texture2D t_metal_roughness;
+layout(set = 1, binding = METAL_ROUGHNESS_TEXTURE_BINDING + 1) uniform sampler s_metal_roughness;
 #endif
 #ifdef NORMAL_TEXTURE_BINDING
-layout(set = 3, binding = NORMAL_TEXTURE_BINDING) uniform texture2D t_normal;
-layout(set = 3, binding = NORMAL_TEXTURE_BINDING + 1) uniform sampler s_normal;
+layout(set = 1, binding = NORMAL_TEXTURE_BINDING) uniform texture2D t_normal;
+layout(set = 1, binding = NORMAL_TEXTURE_BINDING + 1) uniform sampler s_normal;
+#endif
+#ifdef CLEARCOAT_TEXTURE_BINDING
+layout(set = 1, binding = CLEARCOAT_TEXTURE_BINDING) uniform texture2D t_clearcoat;
+layout(set = 1, binding = CLEARCOAT_TEXTURE_BINDING + 1) uniform sampler s_clearcoat;
+#endif
+#ifdef CLEARCOAT_ROUGHNESS_TEXTURE_BINDING
+layout(set = 1, binding = CLEARCOAT_ROUGHNESS_TEXTURE_BINDING) uniform texture2D t_clearcoat_roughness;
+layout(set = 1, binding = CLEARCOAT_ROUGHNESS_TEXTURE_BINDING + 1) uniform sampler s_clearcoat_roughness;
+#endif
+#ifdef CLEARCOAT_NORMAL_TEXTURE_BINDING
+layout(set = 1, binding = CLEARCOAT_NORMAL_TEXTURE_BINDING) uniform texture2D t_clearcoat_normal;
+layout(set = 1, binding = CLEARCOAT_NORMAL_TEXTURE_BINDING + 1) uniform sampler s_clearcoat_normal;
+#endif
+#ifdef SHEEN_COLOUR_TEXTURE_BINDING
+layout(set = 1, binding = SHEEN_COLOUR_TEXTURE_BINDING) uniform texture2D t_sheen_colour;
+layout(set = 1, binding = SHEEN_COLOUR_TEXTURE_BINDING + 1) uniform sampler s_sheen_colour;
+#endif
+#ifdef SHEEN_ROUGHNESS_TEXTURE_BINDING
+layout(set = 1, binding = SHEEN_ROUGHNESS_TEXTURE_BINDING) uniform texture2D t_sheen_roughness;
+layout(set = 1, binding = SHEEN_ROUGHNESS_TEXTURE_BINDING + 1) uniform sampler s_sheen_roughness;
+#endif
+#ifdef TRANSMISSION_TEXTURE_BINDING
+layout(set = 1, binding = TRANSMISSION_TEXTURE_BINDING) uniform texture2D t_transmission;
+layout(set = 1, binding = TRANSMISSION_TEXTURE_BINDING + 1) uniform sampler s_transmission;
+#endif
+#ifdef SPECULAR_TEXTURE_BINDING
+layout(set = 1, binding = SPECULAR_TEXTURE_BINDING) uniform texture2D t_specular;
+layout(set = 1, binding = SPECULAR_TEXTURE_BINDING + 1) uniform sampler s_specular;
+#endif
+#ifdef SPECULAR_COLOUR_TEXTURE_BINDING
+layout(set = 1, binding = SPECULAR_COLOUR_TEXTURE_BINDING) uniform texture2D t_specular_colour;
+layout(set = 1, binding = SPECULAR_COLOUR_TEXTURE_BINDING + 1) uniform sampler s_specular_colour;
+#endif
+
+#ifdef ANISOTROPY_TEXTURE_BINDING
+layout(set = 1, binding = ANISOTROPY_TEXTURE_BINDING) uniform texture2D t_anisotropy;
+layout(set = 1, binding = ANISOTROPY_TEXTURE_BINDING + 1) uniform sampler s_anisotropy;
 #endif
 
 layout(location = 0)
@@ -69,22 +157,9 @@ in VS_IN {
     vec2 tex_coord;
     vec3 world_pos;
     mat3 tbn;
+    vec4 colour;
 } vs_in;
 
-float distribution_ggx(vec3 normal, vec3 half_dir, float roughness)
-{
-    float a         = roughness * roughness;
-    float a_2       = a * a;
-    float n_dot_h   = max(dot(normal, half_dir), 0.0);
-    float n_dot_h_2 = n_dot_h * n_dot_h;
-	
-    float nom    = a_2;
-    float denom  = (n_dot_h_2 * (a_2 - 1.0) + 1.0);
-    denom        = PI * denom * denom;
-	
-    return nom / denom;
-}
-
 float geometry_schlick_ggx(float n_dot_v, float roughness)
 {
     float r = (roughness + 1.0);
@@ -106,15 +181,48 @@ float geometry_smith(vec3 normal, vec3 view_dir, vec3 light_dir, float roughness
     return ggx1 * ggx2;
 }
 
-vec3 fresnel_schlick(float cos_theta, vec3 fresnel_0)
+#ifdef MATERIAL_SHEEN
+// Charlie sheen distribution and Neubelt visibility, as in the KHR_materials_sheen spec.
+float distribution_charlie(float n_dot_h, float sheen_roughness)
 {
-    return fresnel_0 + (1.0 - fresnel_0) * pow(1.0 - cos_theta, 5.0);
+    float alpha = max(sheen_roughness * sheen_roughness, 0.000001);
+    float inv_alpha = 1.0 / alpha;
+    float sin_2 = 1.0 - n_dot_h * n_dot_h;
+
+    return (2.0 + inv_alpha) * pow(sin_2, inv_alpha * 0.5) / (2.0 * PI);
 }
 
-vec3 fresnel_schlick_roughness(float cos_theta, vec3 fresnel_0, float roughness)
+float visibility_neubelt(float n_dot_l, float n_dot_v)
 {
-    return fresnel_0 + ( max(vec3(1.0 - roughness), fresnel_0) - fresnel_0 ) * pow(1.0 - cos_theta, 5.0);
+    return clamp(1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v) + 0.001), 0.0, 1.0);
 }
+#endif
+
+#ifdef MATERIAL_ANISOTROPY
+// Anisotropic GGX distribution and height-correlated visibility, as in the
+// KHR_materials_anisotropy spec. The roughness is stretched along the anisotropy direction.
+float anisotropic_ggx(vec3 normal, vec3 view_dir, vec3 light_dir, vec3 half_dir, vec3 tangent, vec3 bitangent, float roughness, float anisotropy)
+{
+    float alpha = roughness * roughness;
+    float alpha_t = max(mix(alpha, 1.0, anisotropy * anisotropy), 0.001);
+    float alpha_b = max(alpha, 0.001);
+
+    float n_dot_h = max(dot(normal, half_dir), 0.0);
+    float n_dot_l = max(dot(normal, light_dir), 0.0);
+    float n_dot_v = max(dot(normal, view_dir), 0.0);
+
+    float a_2 = alpha_t * alpha_b;
+    vec3 f = vec3(alpha_b * dot(tangent, half_dir), alpha_t * dot(bitangent, half_dir), a_2 * n_dot_h);
+    float w_2 = a_2 / dot(f, f);
+    float D = a_2 * w_2 * w_2 / PI;
+
+    float ggx_v = n_dot_l * length(vec3(alpha_t * dot(tangent, view_dir), alpha_b * dot(bitangent, view_dir), n_dot_v));
+    float ggx_l = n_dot_v * length(vec3(alpha_t * dot(tangent, light_dir), alpha_b * dot(bitangent, light_dir), n_dot_l));
+    float V = clamp(0.5 / (ggx_v + ggx_l + 0.0001), 0.0, 1.0);
+
+    return D * V;
+}
+#endif
 
 void main() {
 
@@ -123,8 +231,9 @@ void main() {
 #ifdef ALBEDO_TEXTURE_BINDING
     pow(texture(sampler2D(t_albedo, s_albedo), vs_in.tex_coord).rgb, vec3(2.2));
 #else
-    pow(u_material.albedo, vec3(2.2));
+    pow(u_material.albedo.rgb, vec3(2.2));
 #endif
+    albedo *= vs_in.colour.rgb;
 
     vec3 normal     =
 #ifdef NORMAL_TEXTURE_BINDING
@@ -142,7 +251,7 @@ void main() {
 #ifdef METAL_ROUGHNESS_TEXTURE_BINDING
     texture(sampler2D(t_metal_roughness, s_metal_roughness), vs_in.tex_coord).b;
 #else
-    u_material.metallic;
+    u_material.metal_roughness.y;
 #endif
 
 
@@ -150,7 +259,7 @@ void main() {
 #ifdef METAL_ROUGHNESS_TEXTURE_BINDING
     texture(sampler2D(t_metal_roughness, s_metal_roughness), vs_in.tex_coord).g;
 #else
-    u_material.roughness;
+    u_material.metal_roughness.z;
 #endif
 
 
@@ -158,37 +267,172 @@ void main() {
 #ifdef AO_TEXTURE_BINDING
     texture(sampler2D(t_ao, s_ao), vs_in.tex_coord).g;
 #else
-    u_material.ao;
+    u_material.ao.x;
 #endif
 
     vec3 emissive   = 
 #ifdef EMISSIVE_TEXTURE_BINDING
     texture(sampler2D(t_emissive, s_emissive), vs_in.tex_coord).rgb;
 #else
-    u_material.emissive;
+    u_material.emissive.rgb;
+#endif
+
+    emissive *= u_material.scalars.y;
+
+    // Load extension parameters. Factors are stored in the channel their texture is read from.
+    float ior = u_material.scalars.x;
+
+#ifdef MATERIAL_CLEARCOAT
+    float clearcoat =
+#ifdef CLEARCOAT_TEXTURE_BINDING
+    texture(sampler2D(t_clearcoat, s_clearcoat), vs_in.tex_coord).r;
+#else
+    u_material.clearcoat.x;
 #endif
 
+    float clearcoat_roughness =
+#ifdef CLEARCOAT_ROUGHNESS_TEXTURE_BINDING
+    texture(sampler2D(t_clearcoat_roughness, s_clearcoat_roughness), vs_in.tex_coord).g;
+#else
+    u_material.clearcoat_roughness.y;
+#endif
+
+    vec3 clearcoat_normal =
+#ifdef CLEARCOAT_NORMAL_TEXTURE_BINDING
+    normalize(vs_in.tbn * (texture(sampler2D(t_clearcoat_normal, s_clearcoat_normal), vs_in.tex_coord).rgb * 2.0 - 1.0));
+#else
+    // Interpolated normals aren't unit length.
+    normalize(vs_in.normal);
+#endif
+#endif
+
+#ifdef MATERIAL_SHEEN
+    vec3 sheen_colour =
+#ifdef SHEEN_COLOUR_TEXTURE_BINDING
+    texture(sampler2D(t_sheen_colour, s_sheen_colour), vs_in.tex_coord).rgb;
+#else
+    u_material.sheen_colour.rgb;
+#endif
+
+    float sheen_roughness =
+#ifdef SHEEN_ROUGHNESS_TEXTURE_BINDING
+    texture(sampler2D(t_sheen_roughness, s_sheen_roughness), vs_in.tex_coord).a;
+#else
+    u_material.sheen_roughness.w;
+#endif
+
+    // Approximates the directional albedo of the sheen layer, which scales down the base layer.
+    float sheen_albedo_scaling = 1.0 - max(sheen_colour.r, max(sheen_colour.g, sheen_colour.b)) * 0.157;
+#endif
+
+#ifdef MATERIAL_TRANSMISSION
+    float transmission =
+#ifdef TRANSMISSION_TEXTURE_BINDING
+    texture(sampler2D(t_transmission, s_transmission), vs_in.tex_coord).r;
+#else
+    u_material.transmission.x;
+#endif
+#endif
+
+#ifdef MATERIAL_SPECULAR
+    float specular_factor =
+#ifdef SPECULAR_TEXTURE_BINDING
+    texture(sampler2D(t_specular, s_specular), vs_in.tex_coord).a;
+#else
+    u_material.specular.w;
+#endif
+
+    vec3 specular_colour =
+#ifdef SPECULAR_COLOUR_TEXTURE_BINDING
+    texture(sampler2D(t_specular_colour, s_specular_colour), vs_in.tex_coord).rgb;
+#else
+    u_material.specular_colour.rgb;
+#endif
+#endif
+
+#ifdef UNLIT
+    vec3 colour = albedo * ao + emissive;
+#else
     // PBR shading.
 
-    vec3 view_dir = normalize(u_camera.world_pos - vs_in.world_pos);
-    vec3 fresnel_0 = mix(vec3(0.04), albedo, metallic);
+    vec3 view_dir = normalize(camera_world_pos() - vs_in.world_pos);
+
+#ifdef MATERIAL_ANISOTROPY
+    // The texture holds the tangent-space direction and a strength multiplier.
+    vec3 anisotropy_texel =
+#ifdef ANISOTROPY_TEXTURE_BINDING
+    texture(sampler2D(t_anisotropy, s_anisotropy), vs_in.tex_coord).rgb;
+#else
+    vec3(1.0, 0.5, 1.0);
+#endif
+
+    vec2 anisotropy_direction = mat2(u_material.anisotropy.x, u_material.anisotropy.y, -u_material.anisotropy.y, u_material.anisotropy.x) * (anisotropy_texel.rg * 2.0 - 1.0);
+    float anisotropy = clamp(u_material.anisotropy.z * anisotropy_texel.b, 0.0, 1.0);
+
+    // Keep the anisotropy frame perpendicular to the mapped normal.
+    vec3 anisotropy_tangent = vs_in.tbn * vec3(anisotropy_direction, 0.0);
+    anisotropy_tangent = normalize(anisotropy_tangent - normal * dot(normal, anisotropy_tangent));
+    vec3 anisotropy_bitangent = cross(normal, anisotropy_tangent);
+#endif
+
+    // Dielectric reflectance from the index of refraction, 0.04 for the default of 1.5.
+    vec3 dielectric_fresnel_0 = vec3(pow((ior - 1.0) / (ior + 1.0), 2.0));
+
+#ifdef MATERIAL_SPECULAR
+    dielectric_fresnel_0 = min(dielectric_fresnel_0 * specular_colour, vec3(1.0)) * specular_factor;
+#endif
+
+    vec3 fresnel_0 = mix(dielectric_fresnel_0, albedo, metallic);
 
     vec3 reflect_dir = reflect(-view_dir, normal);   
 
+#ifdef MATERIAL_ANISOTROPY
+    // Approximate the anisotropic lobe in the environment lookup by bending the reflection
+    // normal towards the anisotropy direction.
+    vec3 anisotropic_tangent = cross(anisotropy_bitangent, view_dir);
+    vec3 anisotropic_normal = cross(anisotropic_tangent, anisotropy_bitangent);
+    float bend_factor = 1.0 - anisotropy * (1.0 - roughness);
+    vec3 bent_normal = normalize(mix(anisotropic_normal, normal, pow(bend_factor, 4.0)));
+
+    reflect_dir = reflect(-view_dir, bent_normal);
+#endif
+
     // Over all lights:
     vec3 L_0 = vec3(0.0);
 
-    for (int i = 0; i < 1; ++i)
+    for (uint i = 0u; i < u_lights.num_lights; ++i)
     {
-        // Calculate light properties.
-        vec3 light_colour = vec3(23.47, 21.31, 20.79);
+        Light light = u_lights.lights[i];
+
+        // Calculate light properties, attenuated as in KHR_lights_punctual.
+        vec3 light_dir;
+        float light_attenuation = 1.0;
+
+        if (light.light_type == LIGHT_DIRECTIONAL) {
+            light_dir = -light.direction;
+        } else {
+            vec3 light_offset = light.world_pos - vs_in.world_pos;
+            float light_distance = length(light_offset);
+
+            light_dir = light_offset / light_distance;
+            light_attenuation = 1.0 / (light_distance * light_distance);
+
+            if (light.range > 0.0) {
+                light_attenuation *= clamp(1.0 - pow(light_distance / light.range, 4.0), 0.0, 1.0);
+            }
+
+            if (light.light_type == LIGHT_SPOT) {
+                float angle_scale = 1.0 / max(0.001, light.inner_cone_cos - light.outer_cone_cos);
+                float angle_offset = -light.outer_cone_cos * angle_scale;
+                float cone_attenuation = clamp(dot(light.direction, -light_dir) * angle_scale + angle_offset, 0.0, 1.0);
+
+                light_attenuation *= cone_attenuation * cone_attenuation;
+            }
+        }
 
-        vec3 light_dir = normalize(u_light.world_pos - vs_in.world_pos);
         vec3 half_dir = normalize(view_dir + light_dir);
 
-        float light_distance = length(u_light.world_pos - vs_in.world_pos);
-        float light_attenuation = 1.0 / (light_distance * light_distance);
-        vec3 light_radiance = light_colour * light_attenuation;
+        vec3 light_radiance = light.colour * light.intensity * light_attenuation;
 
         // Calculate Cook-Torrance specular BRDF: DFG / 4(ωo⋅n)(ωi⋅n)
         vec3 F = fresnel_schlick( max( dot(half_dir, view_dir), 0.0 ), fresnel_0 );
@@ -199,6 +443,10 @@ void main() {
 
         vec3 specular = (D * F * G) / denom;
 
+#ifdef MATERIAL_ANISOTROPY
+        specular = F * anisotropic_ggx(normal, view_dir, light_dir, half_dir, anisotropy_tangent, anisotropy_bitangent, roughness, anisotropy);
+#endif
+
         // Calculate ratio of reflected-refracted light.
         vec3 kS = F;
         vec3 kD = vec3(1.0) - kS;
@@ -208,7 +456,34 @@ void main() {
         // Calculate output radiance.
         float n_dot_l = max(dot(normal, light_dir), 0.0);
 
-        L_0 += (kD * albedo / PI + specular) * light_radiance * n_dot_l;
+#ifdef MATERIAL_TRANSMISSION
+        // Transmitted light replaces diffuse light.
+        kD *= 1.0 - transmission;
+#endif
+
+        vec3 light_colour = (kD * albedo / PI + specular) * n_dot_l;
+
+#ifdef MATERIAL_SHEEN
+        float sheen_n_dot_v = max(dot(normal, view_dir), 0.0);
+
+        light_colour = light_colour * sheen_albedo_scaling + sheen_colour * distribution_charlie(max(dot(normal, half_dir), 0.0), sheen_roughness) * visibility_neubelt(n_dot_l, sheen_n_dot_v) * n_dot_l;
+#endif
+
+#ifdef MATERIAL_CLEARCOAT
+        // The clearcoat is a dielectric layer over the base, with its own normal and roughness.
+        float clearcoat_n_dot_l = max(dot(clearcoat_normal, light_dir), 0.0);
+        float clearcoat_n_dot_v = max(dot(clearcoat_normal, view_dir), 0.0);
+
+        vec3 clearcoat_F = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), vec3(0.04));
+        float clearcoat_D = distribution_ggx(clearcoat_normal, half_dir, clearcoat_roughness);
+        float clearcoat_G = geometry_smith(clearcoat_normal, view_dir, light_dir, clearcoat_roughness);
+
+        vec3 clearcoat_specular = (clearcoat_D * clearcoat_F * clearcoat_G) / (4.0 * clearcoat_n_dot_v * clearcoat_n_dot_l + 0.001);
+
+        light_colour = light_colour * (1.0 - clearcoat * clearcoat_F) + clearcoat * clearcoat_specular * clearcoat_n_dot_l;
+#endif
+
+        L_0 += light_colour * light_radiance;
     }
 
     // Calculate final fragment colour.
@@ -227,13 +502,39 @@ void main() {
     vec2 environment_brdf = texture(sampler2D(t_brdf_lut, s_brdf_lut), vec2(max( dot(normal, view_dir), 0.0 ), roughness)).rg;
     vec3 specular = prefiltered_colour * (fresnel * environment_brdf.x + environment_brdf.y);
 
-    vec3 ambient = (kD * diffuse + specular) * ao + emissive;
+#ifdef MATERIAL_TRANSMISSION
+    // Approximate transmission by sampling the prefiltered environment behind the surface. Other
+    // objects aren't seen through it, and the volume's thickness and absorption are ignored.
+    vec3 refract_dir = refract(-view_dir, normal, 1.0 / ior);
+    vec3 transmitted = textureLod(samplerCube(t_prefiltered_env_map, s_prefiltered_env_map), refract_dir, roughness * MAX_REFLECTION_LOD).rgb * albedo;
+
+    diffuse = mix(diffuse, transmitted, transmission);
+#endif
+
+    vec3 ambient = kD * diffuse + specular;
+
+#ifdef MATERIAL_SHEEN
+    // Approximate the sheen's environment lighting with the irradiance, scaled by its albedo.
+    ambient = ambient * sheen_albedo_scaling + sheen_colour * irradiance * (1.0 - sheen_albedo_scaling);
+#endif
+
+#ifdef MATERIAL_CLEARCOAT
+    float clearcoat_n_dot_v = max(dot(clearcoat_normal, view_dir), 0.0);
+    vec3 clearcoat_reflect_dir = reflect(-view_dir, clearcoat_normal);
+
+    vec3 clearcoat_fresnel = fresnel_schlick_roughness(clearcoat_n_dot_v, vec3(0.04), clearcoat_roughness);
+    vec3 clearcoat_prefiltered = textureLod(samplerCube(t_prefiltered_env_map, s_prefiltered_env_map), clearcoat_reflect_dir, clearcoat_roughness * MAX_REFLECTION_LOD).rgb;
+    vec2 clearcoat_brdf = texture(sampler2D(t_brdf_lut, s_brdf_lut), vec2(clearcoat_n_dot_v, clearcoat_roughness)).rg;
+
+    ambient = ambient * (1.0 - clearcoat * clearcoat_fresnel) + clearcoat * clearcoat_prefiltered * (clearcoat_fresnel * clearcoat_brdf.x + clearcoat_brdf.y);
+#endif
+
+    ambient = ambient * ao + emissive;
     
     vec3 colour = ambient + L_0;
+#endif
 
-    // Gamma correct.
-    colour = colour / (colour + vec3(1.0));
-    colour = pow(colour, vec3(1.0 / 2.2));
+    colour = tonemap(colour);
 
     f_colour = vec4(colour, 1.0); 
 
@@ -4,14 +4,40 @@ layout(location = 0) in vec3 i_position;
 layout(location = 1) in vec3 i_normal;
 layout(location = 2) in vec4 i_tangent;
 layout(location = 3) in vec2 i_tex_coord;
+layout(location = 10) in vec4 i_colour;
 
-layout(set=0, binding=0)
-uniform Transforms {
-    mat4 model;
-    mat4 view;
-    mat4 proj;
-    vec3 world_pos;
-} u_camera;
+#ifdef SKINNED
+layout(location = 4) in uvec4 i_joints;
+layout(location = 5) in vec4 i_weights;
+#endif
+
+// Read per instance from the frame's instance buffer.
+layout(location = 6) in mat4 i_model;
+
+#include "frame.glsl"
+
+// The joints and morph weights are bound by dynamic offsets into the frame's object buffer.
+
+#ifdef SKINNED
+layout(set=2, binding=0)
+uniform Joints {
+    mat4 matrices[MAX_JOINTS];
+} u_joints;
+#endif
+
+#ifdef MORPH_TARGETS
+layout(set=2, binding=1)
+uniform MorphWeights {
+    vec4 weights[MAX_MORPH_TARGETS / 4];
+} u_morph_weights;
+
+// Position, normal and tangent deltas for every target of every vertex.
+layout(set=3, binding=0)
+readonly buffer MorphTargets {
+    uvec4 info;
+    vec4 deltas[];
+} u_morph_targets;
+#endif
 
 layout(location = 0)
 out VS_OUT {
@@ -19,22 +45,73 @@ out VS_OUT {
     vec2 tex_coord;
     vec3 world_pos;
     mat3 tbn;
+    vec4 colour;
 } vs_out;
 
 void main() {
 
-    vec4 position = vec4(i_position, 1.0);
-    vec4 normal = vec4(i_normal, 1.0);
+    vec3 morphed_position = i_position;
+    vec3 morphed_normal = i_normal;
+    vec3 morphed_tangent = i_tangent.xyz;
+
+#ifdef MORPH_TARGETS
+    uint num_targets = u_morph_targets.info.x;
+    uint num_active_targets = min(num_targets, uint(MAX_MORPH_TARGETS));
+
+    for (uint i = 0u; i < num_active_targets; ++i) {
+        float weight = u_morph_weights.weights[i / 4u][i % 4u];
+
+        if (weight == 0.0) {
+            continue;
+        }
 
-    gl_Position = u_camera.proj * u_camera.view * u_camera.model * position;
+        uint base = (uint(gl_VertexIndex) * num_targets + i) * 3u;
 
-    vs_out.normal = (u_camera.model * normal).xyz;
+        morphed_position += weight * u_morph_targets.deltas[base].xyz;
+        morphed_normal += weight * u_morph_targets.deltas[base + 1u].xyz;
+        morphed_tangent += weight * u_morph_targets.deltas[base + 2u].xyz;
+    }
+#endif
+
+    vec4 position = vec4(morphed_position, 1.0);
+
+#ifdef SKINNED
+    // Vertices without joint weights are left in their bind pose.
+    mat4 skin_matrix = mat4(1.0);
+
+    if (dot(i_weights, vec4(1.0)) > 0.0) {
+        skin_matrix =
+            i_weights.x * u_joints.matrices[i_joints.x] +
+            i_weights.y * u_joints.matrices[i_joints.y] +
+            i_weights.z * u_joints.matrices[i_joints.z] +
+            i_weights.w * u_joints.matrices[i_joints.w];
+    }
+
+    mat4 model = i_model * skin_matrix;
+#else
+    mat4 model = i_model;
+#endif
+
+    gl_Position = u_camera.proj * u_camera.view * model * position;
+
+    // Normals are transformed by the inverse transpose, which keeps them perpendicular to the
+    // surface when it's scaled unevenly. Tangents lie in the surface, so they follow the model
+    // matrix and are made perpendicular to the normal again.
+    mat3 normal_matrix = transpose(inverse(mat3(model)));
+
+    vec3 N = normalize(normal_matrix * morphed_normal);
+    vec3 T = normalize(mat3(model) * morphed_tangent);
+    T = normalize(T - dot(T, N) * N);
+    vec3 B = cross(T, N) * i_tangent.w;
+
+    vs_out.normal = N;
     vs_out.tex_coord = i_tex_coord;
-    vs_out.world_pos = (u_camera.model * position).xyz;
+    vs_out.colour = i_colour;
+    vs_out.world_pos = (model * position).xyz;
 
-    vec3 T = normalize( (u_camera.model * vec4(i_tangent.xyz, 0.0)).xyz );
-    vec3 N = normalize( vs_out.normal );
-    vec3 B = normalize( (u_camera.model * vec4( (cross(i_tangent.xyz, i_normal) * i_tangent.w), 0.0 )).xyz );
-    
     vs_out.tbn = mat3(T, B, N);
-}
\ No newline at end of file
+
+#ifdef POINTS
+    gl_PointSize = 1.0;
+#endif
+}
@@ -1,5 +1,7 @@
 #version 450
 
+#include "tonemapping.glsl"
+
 layout(location = 0) out vec4 f_colour;
 
 layout(set = 1, binding = 0) uniform textureCube t_environmentMap;
@@ -14,8 +16,7 @@ void main()
 {
     vec3 envColor = textureLod(samplerCube(t_environmentMap, s_environmentMap), vs_out.local_pos, 1.2).rgb;
     
-    envColor = envColor / (envColor + vec3(1.0));
-    envColor = pow(envColor, vec3(1.0/2.2)); 
+    envColor = tonemap(envColor);
   
     f_colour = vec4(envColor, 1.0);
 }
\ No newline at end of file
@@ -5,13 +5,7 @@ layout(location = 1) in vec3 i_normal;
 layout(location = 2) in vec4 i_tangent;
 layout(location = 3) in vec2 i_tex_coord;
 
-layout(set=0, binding=0)
-uniform Transforms {
-    mat4 model;
-    mat4 view;
-    mat4 proj;
-    vec3 world_pos;
-} u_camera;
+#include "frame.glsl"
 
 layout(location = 0)
 out VS_OUT {
@@ -0,0 +1,12 @@
+#ifndef TONEMAPPING_GLSL
+#define TONEMAPPING_GLSL
+
+// Reinhard tone mapping followed by gamma correction.
+vec3 tonemap(vec3 colour)
+{
+    colour = colour / (colour + vec3(1.0));
+
+    return pow(colour, vec3(1.0 / 2.2));
+}
+
+#endif
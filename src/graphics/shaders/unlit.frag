@@ -0,0 +1,33 @@
+#version 450
+
+layout(location = 0) out vec4 f_colour;
+
+layout(set=1, binding=0)
+uniform MaterialProperties {
+    vec4 base_colour;
+} u_material;
+
+#ifdef BASE_COLOUR_TEXTURE_BINDING
+layout(set = 1, binding = BASE_COLOUR_TEXTURE_BINDING) uniform texture2D t_base_colour;
+layout(set = 1, binding = BASE_COLOUR_TEXTURE_BINDING + 1) uniform sampler s_base_colour;
+#endif
+
+layout(location = 0)
+in VS_IN {
+    vec3 normal;
+    vec2 tex_coord;
+    vec3 world_pos;
+    mat3 tbn;
+    vec4 colour;
+} vs_in;
+
+void main() {
+    vec4 base_colour = u_material.base_colour * vs_in.colour;
+
+#ifdef BASE_COLOUR_TEXTURE_BINDING
+    base_colour *= texture(sampler2D(t_base_colour, s_base_colour), vs_in.tex_coord);
+#endif
+
+    // Unlit colours are written as they are, without tone mapping, so baked lighting is kept.
+    f_colour = vec4(base_colour.rgb, 1.0);
+}
@@ -0,0 +1,160 @@
+use nalgebra::*;
+
+pub const MAX_JOINTS: usize = 128;
+
+#[derive(Clone)]
+pub struct SkinNode {
+    pub parent: Option<usize>,
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl SkinNode {
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+pub struct Skin {
+    // Every node in the glTF document, indexed by node index, so joints can be animated.
+    pub nodes: Vec<SkinNode>,
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+    pub mesh_node: Option<usize>,
+}
+
+// The joint matrices bound for a skinned object, padded with identity matrices.
+pub type JointMatrices = [Matrix4<f32>; MAX_JOINTS];
+
+impl Skin {
+    pub fn new_from_gltf(
+        gltf: &gltf::Document,
+        gltf_skin: &gltf::Skin,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Self, String> {
+        // Build the node hierarchy.
+        let mut nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+
+                SkinNode {
+                    parent: None,
+                    translation: translation.into(),
+                    rotation: UnitQuaternion::from_quaternion(Quaternion::new(
+                        rotation[3],
+                        rotation[0],
+                        rotation[1],
+                        rotation[2],
+                    )),
+                    scale: scale.into(),
+                }
+            })
+            .collect::<Vec<SkinNode>>();
+
+        for node in gltf.nodes() {
+            for child in node.children() {
+                nodes[child.index()].parent = Some(node.index());
+            }
+        }
+
+        let joints = gltf_skin
+            .joints()
+            .map(|joint| joint.index())
+            .collect::<Vec<usize>>();
+
+        if let Some(accessor) = gltf_skin.inverse_bind_matrices() {
+            use gltf::accessor::{DataType, Dimensions};
+
+            super::check_accessor(&accessor, &[DataType::F32], &[Dimensions::Mat4], buffers)?;
+        }
+
+        // Inverse bind matrices default to identity if they are not supplied.
+        let reader = gltf_skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(iter) => iter.map(Matrix4::from).collect::<Vec<Matrix4<f32>>>(),
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+
+        if inverse_bind_matrices.len() != joints.len() {
+            return Err(format!(
+                "Skin {} has {} inverse bind matrices for {} joints",
+                gltf_skin.index(),
+                inverse_bind_matrices.len(),
+                joints.len()
+            ));
+        }
+
+        // Find the node the skinned mesh is attached to.
+        let mesh_node = gltf
+            .nodes()
+            .find(|node| match node.skin() {
+                Some(skin) => skin.index() == gltf_skin.index(),
+                None => false,
+            })
+            .map(|node| node.index());
+
+        Ok(Self {
+            nodes,
+            joints,
+            inverse_bind_matrices,
+            mesh_node,
+        })
+    }
+
+    pub fn global_transforms(&self) -> Vec<Matrix4<f32>> {
+        let mut global_transforms: Vec<Option<Matrix4<f32>>> = vec![None; self.nodes.len()];
+
+        for i in 0..self.nodes.len() {
+            // Walk up to the first ancestor with a known transform.
+            let mut chain = vec![i];
+
+            while let Some(parent) = self.nodes[*chain.last().unwrap()].parent {
+                if global_transforms[parent].is_some() {
+                    break;
+                }
+                chain.push(parent);
+            }
+
+            // Resolve the chain from the top down.
+            for &node in chain.iter().rev() {
+                if global_transforms[node].is_some() {
+                    continue;
+                }
+
+                let local = self.nodes[node].local_matrix();
+
+                global_transforms[node] = Some(match self.nodes[node].parent {
+                    Some(parent) => global_transforms[parent].unwrap() * local,
+                    None => local,
+                });
+            }
+        }
+
+        global_transforms.into_iter().map(|x| x.unwrap()).collect()
+    }
+
+    pub fn joint_matrices(&self) -> JointMatrices {
+        let global_transforms = self.global_transforms();
+
+        let inverse_mesh_transform = match self.mesh_node {
+            Some(node) => global_transforms[node]
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity),
+            None => Matrix4::identity(),
+        };
+
+        let mut joint_matrices = [Matrix4::identity(); MAX_JOINTS];
+
+        for (i, &joint) in self.joints.iter().enumerate() {
+            joint_matrices[i] =
+                inverse_mesh_transform * global_transforms[joint] * self.inverse_bind_matrices[i];
+        }
+
+        joint_matrices
+    }
+}
@@ -8,14 +8,58 @@ pub struct Skybox {
     pub brdf_lut: Texture,
 }
 
+// Decoded equirectangular HDR pixels, as RGBA floats.
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl HdrImage {
+    pub fn decode(hdr_data: &[u8]) -> Result<Self, String> {
+        let decoder = image::hdr::HdrDecoder::new(hdr_data).map_err(|err| err.to_string())?;
+
+        let width = decoder.metadata().width;
+        let height = decoder.metadata().height;
+        let hdr_pixels = decoder.read_image_hdr().map_err(|err| err.to_string())?;
+
+        // Add alpha data.
+        let mut pixels = Vec::with_capacity(hdr_pixels.len() * 4);
+
+        for pixel in hdr_pixels {
+            pixels.push(pixel[0]);
+            pixels.push(pixel[1]);
+            pixels.push(pixel[2]);
+            pixels.push(1.0);
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // A uniform grey environment, which lights models when no skybox could be loaded.
+    pub fn neutral() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![0.5, 0.5, 0.5, 1.0],
+        }
+    }
+}
+
 impl Skybox {
-    pub fn new(
+    // Bakes the image based lighting maps for a decoded HDR image.
+    pub fn new_from_image(
         device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
         sc_desc: &wgpu::SwapChainDescriptor,
         queue: &wgpu::Queue,
-        hdr_data: &[u8],
+        hdr_image: &HdrImage,
     ) -> (Skybox, Renderable) {
-        let hdr_texture = Skybox::create_hdr_texture(device, queue, hdr_data);
+        let hdr_texture = Skybox::create_hdr_texture(device, queue, hdr_image);
 
         // Create unit cube for projections.
         let cube_vertices: [[f32; 3]; 8] = [
@@ -48,6 +92,9 @@ impl Skybox {
                 normal: [0.0, 0.0, 0.0],
                 tangent: [0.0, 0.0, 0.0, 0.0],
                 tex_coord: [0.0, 0.0],
+                joints: [0, 0, 0, 0],
+                weights: [0.0, 0.0, 0.0, 0.0],
+                colour: [1.0; 4],
             })
             .collect::<Vec<Vertex>>();
 
@@ -130,7 +177,12 @@ impl Skybox {
             environment_texture,
         };
 
-        let material = Box::new(SkyboxMaterial::new(device, sc_desc, &skybox_params));
+        let material = Box::new(SkyboxMaterial::new(
+            device,
+            pipeline_cache,
+            sc_desc,
+            &skybox_params,
+        ));
         let skybox = Skybox {
             environment_texture: skybox_params.environment_texture,
             irradiance_map,
@@ -138,43 +190,29 @@ impl Skybox {
             brdf_lut: precomputed_brdf,
         };
 
-        (
-            skybox,
-            Renderable::new_from_single_mesh(unit_cube_mesh, material),
-        )
-    }
-
-    fn create_hdr_texture(device: &wgpu::Device, queue: &wgpu::Queue, hdr_data: &[u8]) -> Texture {
-        // Decode HDR data.
-        let reader = image::hdr::HdrDecoder::new(hdr_data);
-        let decoder = reader.unwrap();
+        let mut renderable = Renderable::new_from_single_mesh(unit_cube_mesh, material);
+        renderable.frustum_culled = false;
 
-        let width = decoder.metadata().width;
-        let height = decoder.metadata().height;
-        let pixels = decoder.read_image_hdr().unwrap();
-
-        // Add alpha data.
-        let mut pixel_data = Vec::new();
-
-        for pixel in pixels {
-            pixel_data.push(pixel[0]);
-            pixel_data.push(pixel[1]);
-            pixel_data.push(pixel[2]);
-            pixel_data.push(1.0);
-        }
+        (skybox, renderable)
+    }
 
+    fn create_hdr_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        hdr_image: &HdrImage,
+    ) -> Texture {
         // Create HDR equirectangular texture.
         let pixel_data_bytes = unsafe {
-            let len = pixel_data.len() * std::mem::size_of::<f32>();
-            let ptr = pixel_data.as_ptr() as *const u8;
+            let len = hdr_image.pixels.len() * std::mem::size_of::<f32>();
+            let ptr = hdr_image.pixels.as_ptr() as *const u8;
             std::slice::from_raw_parts(ptr, len)
         };
 
         Texture::new_texture_from_data(
             device,
             queue,
-            width,
-            height,
+            hdr_image.width,
+            hdr_image.height,
             pixel_data_bytes,
             wgpu::TextureFormat::Rgba32Float,
             wgpu::AddressMode::ClampToEdge,
@@ -239,7 +277,7 @@ impl Skybox {
                 render_pass.set_bind_group(0, &hdr_material.transform_bind_group, &[]);
                 render_pass.set_bind_group(1, &hdr_material.cvt_bind_group, &[]);
 
-                unit_cube_mesh.draw(&mut render_pass);
+                unit_cube_mesh.draw(&mut render_pass, 0..1);
             }
 
             cubemap_faces.push(cubemap_face);
@@ -313,7 +351,7 @@ impl Skybox {
                 render_pass.set_bind_group(0, &convolve_material.transform_bind_group, &[]);
                 render_pass.set_bind_group(1, &convolve_material.convolve_bind_group, &[]);
 
-                unit_cube_mesh.draw(&mut render_pass);
+                unit_cube_mesh.draw(&mut render_pass, 0..1);
             }
 
             cubemap_faces.push(cubemap_face);
@@ -418,7 +456,7 @@ impl Skybox {
                         &[],
                     );
 
-                    unit_cube_mesh.draw(&mut render_pass);
+                    unit_cube_mesh.draw(&mut render_pass, 0..1);
                 }
 
                 cubemap_faces.push(cubemap_face);
@@ -461,6 +499,9 @@ impl Skybox {
                 normal: [0.0, 0.0, 0.0],
                 tangent: [0.0, 0.0, 0.0, 0.0],
                 tex_coord: tex_coord,
+                joints: [0, 0, 0, 0],
+                weights: [0.0, 0.0, 0.0, 0.0],
+                colour: [1.0; 4],
             })
             .collect::<Vec<Vertex>>();
 
@@ -506,7 +547,7 @@ impl Skybox {
             render_pass.set_pipeline(&brdf_mat.render_pipeline);
             render_pass.set_bind_group(0, &brdf_mat.transform_bind_group, &[]);
 
-            screen_space_quad_mesh.draw(&mut render_pass);
+            screen_space_quad_mesh.draw(&mut render_pass, 0..1);
         }
 
         let cmd_buffer = encoder.finish();
@@ -1,8 +1,26 @@
+use super::*;
+
 pub struct Texture {
     _texture: wgpu::Texture,
     pub dimension: wgpu::TextureViewDimension,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    // The size and format of textures created from data, which can be read back to export them.
+    pub layout: Option<TextureLayout>,
+}
+
+#[derive(Copy, Clone)]
+pub struct TextureLayout {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+}
+
+// The largest mip level of a texture, read back from the GPU.
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub rgba_data: Vec<u8>,
 }
 
 impl Texture {
@@ -14,6 +32,27 @@ impl Texture {
         rgba_data: &[u8],
         image_format: wgpu::TextureFormat,
         wrap_mode: wgpu::AddressMode,
+    ) -> Self {
+        Texture::new_texture_from_mips(
+            device,
+            queue,
+            width,
+            height,
+            &[rgba_data],
+            image_format,
+            wrap_mode,
+        )
+    }
+
+    // Creates a texture from mip levels, largest first, each half the size of the last.
+    pub fn new_texture_from_mips(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        mip_levels: &[&[u8]],
+        image_format: wgpu::TextureFormat,
+        wrap_mode: wgpu::AddressMode,
     ) -> Self {
         // Create texture.
         let size = wgpu::Extent3d {
@@ -25,35 +64,61 @@ impl Texture {
             label: None,
             size: size,
             array_layer_count: 1,
-            mip_level_count: 1,
+            mip_level_count: mip_levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: image_format,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::COPY_SRC,
         });
 
         // Upload data to texture.
-        let buffer = device.create_buffer_with_data(&rgba_data, wgpu::BufferUsage::COPY_SRC);
-
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("texture_buffer_copy_encoder"),
         });
 
-        encoder.copy_buffer_to_texture(
-            wgpu::BufferCopyView {
-                buffer: &buffer,
-                offset: 0,
-                bytes_per_row: (rgba_data.len() / height as usize) as u32,
-                rows_per_image: height,
-            },
-            wgpu::TextureCopyView {
-                texture: &_texture,
-                mip_level: 0,
-                array_layer: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            size,
-        );
+        let bytes_per_pixel = mip_levels[0].len() / (width * height) as usize;
+
+        for (mip_level, rgba_data) in mip_levels.iter().enumerate() {
+            let mip_width = (width >> mip_level).max(1);
+            let mip_height = (height >> mip_level).max(1);
+
+            // Small mip levels need their rows padded.
+            let bytes_per_row = mip_width as usize * bytes_per_pixel;
+            let padded_bytes_per_row = padded_bytes_per_row(bytes_per_row);
+
+            let mut padded_data = vec![0; padded_bytes_per_row * mip_height as usize];
+
+            for (padded_row, row) in padded_data
+                .chunks_mut(padded_bytes_per_row)
+                .zip(rgba_data.chunks(bytes_per_row))
+            {
+                padded_row[..row.len()].copy_from_slice(row);
+            }
+
+            let buffer = device.create_buffer_with_data(&padded_data, wgpu::BufferUsage::COPY_SRC);
+
+            encoder.copy_buffer_to_texture(
+                wgpu::BufferCopyView {
+                    buffer: &buffer,
+                    offset: 0,
+                    bytes_per_row: padded_bytes_per_row as u32,
+                    rows_per_image: mip_height,
+                },
+                wgpu::TextureCopyView {
+                    texture: &_texture,
+                    mip_level: mip_level as u32,
+                    array_layer: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::Extent3d {
+                    width: mip_width,
+                    height: mip_height,
+                    depth: 1,
+                },
+            );
+        }
 
         queue.submit(&[encoder.finish()]);
 
@@ -76,9 +141,77 @@ impl Texture {
             dimension: wgpu::TextureViewDimension::D2,
             view,
             sampler,
+            layout: Some(TextureLayout {
+                width,
+                height,
+                format: image_format,
+            }),
         }
     }
 
+    // Copies the largest mip level back from the GPU as RGBA, for exporting. Only 8-bit textures
+    // created from data can be read back.
+    pub fn read_back(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<TextureData, String> {
+        let layout = self
+            .layout
+            .ok_or_else(|| "Only textures created from data can be read back".to_owned())?;
+
+        let channels = match layout.format {
+            wgpu::TextureFormat::R8Unorm => 1,
+            wgpu::TextureFormat::Rg8Unorm => 2,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => 4,
+            format => return Err(format!("{:?} textures can't be read back", format)),
+        };
+
+        let bytes_per_row = layout.width as usize * channels;
+        let padded_bytes_per_row = padded_bytes_per_row(bytes_per_row);
+
+        let size = (padded_bytes_per_row * layout.height as usize) as wgpu::BufferAddress;
+
+        let padded_data = readback::read_back(device, queue, size, |encoder, staging_buffer| {
+            encoder.copy_texture_to_buffer(
+                wgpu::TextureCopyView {
+                    texture: &self._texture,
+                    mip_level: 0,
+                    array_layer: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::BufferCopyView {
+                    buffer: staging_buffer,
+                    offset: 0,
+                    bytes_per_row: padded_bytes_per_row as u32,
+                    rows_per_image: layout.height,
+                },
+                wgpu::Extent3d {
+                    width: layout.width,
+                    height: layout.height,
+                    depth: 1,
+                },
+            )
+        })?;
+
+        let data: Vec<u8> = padded_data
+            .chunks(padded_bytes_per_row)
+            .flat_map(|row| row[..bytes_per_row].iter().copied())
+            .collect();
+
+        let rgba_data = if channels == 4 {
+            data
+        } else {
+            image_import::expand_to_rgba(&data, channels, false)
+        };
+
+        Ok(TextureData {
+            width: layout.width,
+            height: layout.height,
+            rgba_data,
+        })
+    }
+
     pub fn new_texture_from_framebuffer(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -145,6 +278,7 @@ impl Texture {
             dimension: wgpu::TextureViewDimension::D2,
             view,
             sampler,
+            layout: None,
         }
     }
 
@@ -231,6 +365,7 @@ impl Texture {
             dimension: wgpu::TextureViewDimension::Cube,
             view,
             sampler,
+            layout: None,
         }
     }
 
@@ -280,6 +415,12 @@ impl Texture {
             dimension: wgpu::TextureViewDimension::D2,
             view,
             sampler,
+            layout: None,
         }
     }
 }
+
+// Buffer rows copied to and from textures must be aligned to 256 bytes.
+fn padded_bytes_per_row(bytes_per_row: usize) -> usize {
+    bytes_per_row.div_ceil(256) * 256
+}
@@ -0,0 +1,147 @@
+use futures::FutureExt;
+use std::future::Future;
+use std::pin::Pin;
+
+// Frames that can be recorded before an upload waits for the GPU to finish copying.
+const RING_SIZE: usize = 3;
+
+type WriteMapping = Pin<
+    Box<dyn Future<Output = Result<wgpu::BufferWriteMapping, wgpu::BufferAsyncErr>> + Send + Sync>,
+>;
+
+struct StagingBuffer {
+    buffer: wgpu::Buffer,
+    size: wgpu::BufferAddress,
+    // Requested once the frame copying from the buffer is submitted.
+    mapping: Option<WriteMapping>,
+}
+
+// Uploads each frame's uniforms through a ring of staging buffers, rather than creating a staging
+// buffer per upload. Each staging buffer is mapped again once the GPU has copied from it, so
+// recording only waits for the GPU when it gets a whole ring ahead.
+pub struct UploadRing {
+    staging_buffers: Vec<Option<StagingBuffer>>,
+    next_index: usize,
+    submitted_index: Option<usize>,
+}
+
+impl UploadRing {
+    pub fn new() -> Self {
+        Self {
+            staging_buffers: (0..RING_SIZE).map(|_| None).collect(),
+            next_index: 0,
+            submitted_index: None,
+        }
+    }
+
+    // Copies the data to the start of each buffer, through the next staging buffer in the ring.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        uploads: &[(&wgpu::Buffer, &[u8])],
+    ) {
+        let size = uploads.iter().map(|(_, data)| data.len()).sum::<usize>() as u64;
+
+        if size == 0 {
+            return;
+        }
+
+        let index = self.next_index;
+
+        let mapping = match &mut self.staging_buffers[index] {
+            Some(staging_buffer) if staging_buffer.size >= size => staging_buffer
+                .mapping
+                .take()
+                .map(|mapping| wait_for_mapping(device, mapping)),
+            _ => None,
+        };
+
+        match mapping {
+            Some(Ok(mut mapping)) => write_uploads(mapping.as_slice(), uploads),
+            _ => {
+                // The ring grows to fit the largest frame.
+                let buffer_size = size.next_power_of_two();
+
+                let mapped_buffer = device.create_buffer_mapped(&wgpu::BufferDescriptor {
+                    label: Some("upload_ring_buffer"),
+                    size: buffer_size,
+                    usage: wgpu::BufferUsage::MAP_WRITE | wgpu::BufferUsage::COPY_SRC,
+                });
+
+                write_uploads(mapped_buffer.data, uploads);
+
+                self.staging_buffers[index] = Some(StagingBuffer {
+                    buffer: mapped_buffer.finish(),
+                    size: buffer_size,
+                    mapping: None,
+                });
+            }
+        }
+
+        let staging_buffer = &self.staging_buffers[index].as_ref().unwrap().buffer;
+        let mut offset = 0;
+
+        for (buffer, data) in uploads {
+            if !data.is_empty() {
+                encoder.copy_buffer_to_buffer(
+                    staging_buffer,
+                    offset,
+                    buffer,
+                    0,
+                    data.len() as wgpu::BufferAddress,
+                );
+            }
+
+            offset += data.len() as wgpu::BufferAddress;
+        }
+
+        self.submitted_index = Some(index);
+    }
+
+    // Maps the staging buffer used this frame again, to be written once the GPU is done with it.
+    // Called after the frame's command buffer is submitted.
+    pub fn finish_frame(&mut self) {
+        if let Some(index) = self.submitted_index.take() {
+            if let Some(staging_buffer) = &mut self.staging_buffers[index] {
+                staging_buffer.mapping = Some(Box::pin(
+                    staging_buffer.buffer.map_write(0, staging_buffer.size),
+                ));
+            }
+
+            self.next_index = (index + 1) % RING_SIZE;
+        }
+    }
+}
+
+impl Default for UploadRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wait_for_mapping(
+    device: &wgpu::Device,
+    mut mapping: WriteMapping,
+) -> Result<wgpu::BufferWriteMapping, wgpu::BufferAsyncErr> {
+    device.poll(wgpu::Maintain::Poll);
+
+    match mapping.as_mut().now_or_never() {
+        Some(result) => result,
+        None => {
+            device.poll(wgpu::Maintain::Wait);
+
+            futures::executor::block_on(mapping)
+        }
+    }
+}
+
+fn write_uploads(staging_data: &mut [u8], uploads: &[(&wgpu::Buffer, &[u8])]) {
+    let mut offset = 0;
+
+    for (_, data) in uploads {
+        staging_data[offset..offset + data.len()].copy_from_slice(data);
+
+        offset += data.len();
+    }
+}
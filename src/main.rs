@@ -8,6 +8,12 @@ use specs::prelude::*;
 struct ExampleRenderLoop {
     world: World,
     dispatcher: Dispatcher<'static, 'static>,
+    scene_camera_index: usize,
+    // Set once the models have loaded.
+    startup_camera_name: Option<String>,
+    startup_animation_name: Option<String>,
+    hot_reloader: graphics::HotReloader,
+    benchmark: Option<graphics::Benchmark>,
 }
 
 // System that rotates entities with the RotatingModel component on every frame update.
@@ -28,10 +34,10 @@ impl<'a> System<'a> for RotateObjectSystem {
         let (mut pose, light) = data;
 
         for (pose, _) in (&mut pose, &light).join() {
-            pose.model_matrix.append_rotation_wrt_point_mut(
-                &nalgebra::UnitQuaternion::new(nalgebra::Vector3::new(0.0, 0.01, 0.0)),
-                &nalgebra::Point3::new(0.0, 0.0, 0.0),
-            )
+            pose.model_matrix = nalgebra::Isometry3::rotation_wrt_point(
+                nalgebra::UnitQuaternion::new(nalgebra::Vector3::new(0.0, 0.01, 0.0)),
+                nalgebra::Point3::new(0.0, 0.0, 0.0),
+            ) * pose.model_matrix;
         }
     }
 }
@@ -58,8 +64,18 @@ impl graphics::RenderLoopEvent for ExampleRenderLoop {
 
         // Create render system.
         let mut dispatcher = DispatcherBuilder::new()
+            .with(graphics::AssetLoadSystem, "asset_load_system", &[])
             .with(RotateObjectSystem, "rot_system", &[])
-            .with(graphics::RenderSystem, "render_system", &["rot_system"])
+            .with(
+                graphics::AnimationSystem::default(),
+                "animation_system",
+                &[],
+            )
+            .with(
+                graphics::RenderSystem,
+                "render_system",
+                &["asset_load_system", "rot_system", "animation_system"],
+            )
             .build();
 
         // Create world.
@@ -68,30 +84,34 @@ impl graphics::RenderLoopEvent for ExampleRenderLoop {
         world.register::<graphics::Renderable>();
         world.register::<graphics::Pose>();
         world.register::<graphics::Light>();
+        world.register::<graphics::SceneCamera>();
+        world.register::<graphics::AnimationPlayer>();
+        world.register::<graphics::MorphWeights>();
+        world.register::<graphics::SceneNodes>();
         world.register::<RotatingModel>();
 
-        // Add models to world.
-        let helmet_data = include_bytes!("../res/DamagedHelmet.glb");
-        let hdr_data = include_bytes!("../res/newport_loft.hdr");
+        // Add models to world. They're decoded in the background, with placeholders drawn until
+        // they've been uploaded, and reloaded whenever they change on disk.
+        let mut hot_reloader = graphics::HotReloader::new();
 
-        let (skybox, skybox_renderable) = graphics::Skybox::new(
-            &render_state.device,
-            &render_state.swap_chain_desc,
-            &render_state.queue,
-            hdr_data,
-        );
+        let skybox_path =
+            std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res/newport_loft.hdr"));
+        let skybox_entity = graphics::load_skybox_async(&mut world, skybox_path);
+        hot_reloader.watch_skybox(skybox_path, skybox_entity);
+
+        // Passing --benchmark draws a benchmark scene in place of the models, and measures each
+        // render setting in turn.
+        let is_benchmark = std::env::args().nth(1).as_deref() == Some("--benchmark");
 
-        world
-            .create_entity()
-            .with(graphics::Renderable::new_from_glb(
-                &render_state.device,
-                &render_state.swap_chain_desc,
-                &render_state.queue,
-                helmet_data,
-                &skybox,
-            ))
-            .with(graphics::Pose {
-                model_matrix: nalgebra::Similarity3::from_parts(
+        if !is_benchmark {
+            let helmet_path = std::path::Path::new(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/res/DamagedHelmet.glb"
+            ));
+            let helmet_entity = graphics::load_model_async(
+                &mut world,
+                helmet_path,
+                graphics::Pose::new(nalgebra::Similarity3::from_parts(
                     nalgebra::Translation3::identity(),
                     nalgebra::UnitQuaternion::from_euler_angles(
                         std::f32::consts::FRAC_PI_2,
@@ -99,37 +119,53 @@ impl graphics::RenderLoopEvent for ExampleRenderLoop {
                         0.0,
                     ),
                     1.0,
-                ),
-            })
-            .build();
+                )),
+            );
+            hot_reloader.watch_model(helmet_path, helmet_entity);
 
-        world
-            .create_entity()
-            .with(skybox_renderable)
-            .with(graphics::Pose {
-                model_matrix: nalgebra::Similarity3::identity(),
-            })
-            .build();
-
-        world
-            .create_entity()
-            .with(graphics::Renderable::new_from_glb(
-                &render_state.device,
-                &render_state.swap_chain_desc,
-                &render_state.queue,
-                include_bytes!("../res/BoxTextured.glb"),
-                &skybox,
-            ))
-            .with(graphics::Pose {
-                model_matrix: nalgebra::Similarity3::from_parts(
+            let box_path =
+                std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res/BoxTextured.glb"));
+            let box_entity = graphics::load_model_async(
+                &mut world,
+                box_path,
+                graphics::Pose::new(nalgebra::Similarity3::from_parts(
                     nalgebra::Translation3::new(3.0, 0.0, 0.0),
                     nalgebra::UnitQuaternion::identity(),
                     1.0,
-                ),
-            })
-            .with(graphics::Light {})
-            .with(RotatingModel)
-            .build();
+                )),
+            );
+            hot_reloader.watch_model(box_path, box_entity);
+
+            world
+                .write_storage::<graphics::Light>()
+                .insert(box_entity, graphics::Light::default())
+                .unwrap();
+            world
+                .write_storage::<RotatingModel>()
+                .insert(box_entity, RotatingModel)
+                .unwrap();
+
+            // Add a model passed on the command line.
+            if let Some(path) = std::env::args().nth(1) {
+                let path = std::path::Path::new(&path);
+
+                let entity = graphics::load_model_async(
+                    &mut world,
+                    path,
+                    graphics::Pose::new(nalgebra::Similarity3::from_parts(
+                        nalgebra::Translation3::new(-3.0, 0.0, 0.0),
+                        nalgebra::UnitQuaternion::identity(),
+                        1.0,
+                    )),
+                );
+                hot_reloader.watch_model(path, entity);
+            }
+        }
+
+        hot_reloader.watch_shaders(std::path::Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/graphics/shaders"
+        )));
 
         // Pass render state into ECS as last step.
         world.insert(render_state);
@@ -137,7 +173,21 @@ impl graphics::RenderLoopEvent for ExampleRenderLoop {
 
         dispatcher.setup(&mut world);
 
-        Self { world, dispatcher }
+        Self {
+            world,
+            dispatcher,
+            scene_camera_index: 0,
+            // Start from a camera in the model passed on the command line, if one is named.
+            startup_camera_name: std::env::args().nth(2),
+            // Then play the animation with the name after it.
+            startup_animation_name: std::env::args().nth(3),
+            hot_reloader,
+            benchmark: if is_benchmark {
+                Some(graphics::Benchmark::new())
+            } else {
+                None
+            },
+        }
     }
 
     fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -155,12 +205,211 @@ impl graphics::RenderLoopEvent for ExampleRenderLoop {
 
                 camera.handle_event(window, event);
             }
+            // Export the scene when E is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::E),
+                        ..
+                    },
+                ..
+            } => {
+                let path = std::path::Path::new("scene.glb");
+
+                match graphics::export_glb_to_path(&self.world, path) {
+                    Ok(()) => println!("Exported scene to {:?}", path),
+                    Err(err) => println!("Failed to export scene: {}", err),
+                }
+            }
+            // Print the last frame's render statistics when S is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::S),
+                        ..
+                    },
+                ..
+            } => {
+                let render_stats = self.world.read_resource::<graphics::RenderStats>();
+
+                println!(
+                    "Drew {} of {} objects ({} culled) in {} draw calls of {} instances",
+                    render_stats.objects - render_stats.culled_objects,
+                    render_stats.objects,
+                    render_stats.culled_objects,
+                    render_stats.draw_calls,
+                    render_stats.instances
+                );
+                println!(
+                    "Objects per level of detail: {:?}",
+                    render_stats.lod_objects
+                );
+                println!(
+                    "{} draw calls in the depth pre-pass",
+                    render_stats.depth_prepass_draw_calls
+                );
+            }
+            // Toggle the depth pre-pass when P is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::P),
+                        ..
+                    },
+                ..
+            } => {
+                let mut render_settings = self.world.write_resource::<graphics::RenderSettings>();
+
+                render_settings.depth_prepass = !render_settings.depth_prepass;
+
+                println!("Depth pre-pass: {}", render_settings.depth_prepass);
+            }
+            // Play the first animation, or pause and resume it, when space is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::Space),
+                        ..
+                    },
+                ..
+            } => {
+                for animation_player in
+                    (&mut self.world.write_storage::<graphics::AnimationPlayer>()).join()
+                {
+                    if animation_player.current_clip().is_none() {
+                        animation_player.play(0);
+                    } else if animation_player.is_playing {
+                        animation_player.pause();
+                    } else {
+                        animation_player.resume();
+                    }
+                }
+            }
+            // Cross-fade to the next animation when N is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::N),
+                        ..
+                    },
+                ..
+            } => {
+                for animation_player in
+                    (&mut self.world.write_storage::<graphics::AnimationPlayer>()).join()
+                {
+                    let clip = match animation_player.current_clip() {
+                        Some(clip) => (clip + 1) % animation_player.clips.len(),
+                        None => 0,
+                    };
+
+                    animation_player.cross_fade(clip, 0.5);
+                }
+            }
+            // Toggle looping when L is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::L),
+                        ..
+                    },
+                ..
+            } => {
+                for animation_player in
+                    (&mut self.world.write_storage::<graphics::AnimationPlayer>()).join()
+                {
+                    animation_player.set_looping(!animation_player.is_looping);
+                }
+            }
+            // Double or halve the animation speed when up or down is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode:
+                            Some(
+                                key @ winit::event::VirtualKeyCode::Up
+                                | key @ winit::event::VirtualKeyCode::Down,
+                            ),
+                        ..
+                    },
+                ..
+            } => {
+                let factor = if *key == winit::event::VirtualKeyCode::Up {
+                    2.0
+                } else {
+                    0.5
+                };
+
+                for animation_player in
+                    (&mut self.world.write_storage::<graphics::AnimationPlayer>()).join()
+                {
+                    animation_player.set_speed(animation_player.speed * factor);
+                }
+            }
+            // Cycle through the scene cameras when C is pressed.
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(winit::event::VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                let (mut camera, scene_cameras, poses): (
+                    WriteExpect<graphics::Camera>,
+                    ReadStorage<graphics::SceneCamera>,
+                    ReadStorage<graphics::Pose>,
+                ) = self.world.system_data();
+
+                let num_scene_cameras = (&scene_cameras, &poses).join().count();
+
+                if num_scene_cameras > 0 {
+                    let index = self.scene_camera_index % num_scene_cameras;
+                    self.scene_camera_index = index + 1;
+
+                    if let Some((scene_camera, pose)) = (&scene_cameras, &poses).join().nth(index) {
+                        camera.set_active(scene_camera, pose);
+                    }
+                }
+            }
             _ => {}
         };
     }
 
     fn render(&mut self) {
         self.dispatcher.dispatch(&mut self.world);
+        self.world.maintain();
+
+        self.hot_reloader.update(&mut self.world);
+
+        if !self
+            .world
+            .read_resource::<graphics::AssetLoader>()
+            .is_loading()
+        {
+            if let Some(camera_name) = self.startup_camera_name.take() {
+                if !graphics::set_active_camera(&mut self.world, &camera_name) {
+                    println!("No camera named {:?}", camera_name);
+                }
+            }
+
+            if let Some(animation_name) = self.startup_animation_name.take() {
+                if !graphics::play_animation(&mut self.world, &animation_name) {
+                    println!("No animation named {:?}", animation_name);
+                }
+            }
+
+            if let Some(benchmark) = &mut self.benchmark {
+                benchmark.update(&mut self.world);
+            }
+        }
     }
 }
 